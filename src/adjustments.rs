@@ -0,0 +1,147 @@
+use std::fmt;
+
+const BRIGHTNESS_STEP: f32 = 0.05;
+const CONTRAST_STEP: f32 = 0.1;
+const GAMMA_STEP: f32 = 0.1;
+const SATURATION_STEP: f32 = 0.1;
+
+/// Color adjustments applied when drawing. The image data is never changed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adjustments {
+    pub brightness: f32, // Added to every channel, -1.0 to 1.0
+    pub contrast: f32,   // Spread around mid-grey, 1.0 is unchanged
+    pub gamma: f32,      // 1.0 is unchanged, higher is lighter
+    pub saturation: f32, // 0.0 is grey, 1.0 is unchanged
+    pub invert: bool,
+    pub grayscale: bool,
+}
+
+impl Default for Adjustments {
+    fn default() -> Self {
+        Self {
+            brightness: 0.0,
+            contrast: 1.0,
+            gamma: 1.0,
+            saturation: 1.0,
+            invert: false,
+            grayscale: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Adjustment {
+    Brightness,
+    Contrast,
+    Gamma,
+    Saturation,
+}
+
+impl Adjustments {
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Moves one adjustment by `steps` (negative to decrease), within its range.
+    pub fn step(&mut self, adjustment: Adjustment, steps: i32) {
+        let n = steps as f32;
+        // Rounded to the step, so going up and down lands on the default again
+        let snap = |v: f32, step: f32| (v / step).round() * step;
+        match adjustment {
+            Adjustment::Brightness => {
+                self.brightness =
+                    snap(self.brightness + n * BRIGHTNESS_STEP, BRIGHTNESS_STEP).clamp(-1.0, 1.0);
+            }
+            Adjustment::Contrast => {
+                self.contrast =
+                    snap(self.contrast + n * CONTRAST_STEP, CONTRAST_STEP).clamp(0.0, 4.0);
+            }
+            Adjustment::Gamma => {
+                self.gamma = snap(self.gamma + n * GAMMA_STEP, GAMMA_STEP).clamp(0.1, 5.0);
+            }
+            Adjustment::Saturation => {
+                self.saturation =
+                    snap(self.saturation + n * SATURATION_STEP, SATURATION_STEP).clamp(0.0, 4.0);
+            }
+        }
+    }
+}
+
+/// Short summary for the status bar, e.g. "B+10% G1.20 inv". Empty when unchanged.
+impl fmt::Display for Adjustments {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        let percent = |v: f32| (v * 100.0).round() as i32;
+
+        if percent(self.brightness) != 0 {
+            parts.push(format!("B{:+}%", percent(self.brightness)));
+        }
+        if percent(self.contrast - 1.0) != 0 {
+            parts.push(format!("C{:+}%", percent(self.contrast - 1.0)));
+        }
+        if percent(self.gamma - 1.0) != 0 {
+            parts.push(format!("G{:.2}", self.gamma));
+        }
+        if percent(self.saturation - 1.0) != 0 && !self.grayscale {
+            parts.push(format!("S{:+}%", percent(self.saturation - 1.0)));
+        }
+        if self.grayscale {
+            parts.push("gray".to_string());
+        }
+        if self.invert {
+            parts.push("inv".to_string());
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+/// Adjustments baked into lookup tables, built once per frame.
+pub struct ColorLut {
+    table: [u8; 256],
+    saturation: Option<f32>,
+}
+
+impl ColorLut {
+    pub fn new(adj: &Adjustments) -> Self {
+        let mut table = [0u8; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let mut v = i as f32 / 255.0;
+            v = (v - 0.5) * adj.contrast + 0.5 + adj.brightness;
+            v = v.clamp(0.0, 1.0).powf(1.0 / adj.gamma);
+            if adj.invert {
+                v = 1.0 - v;
+            }
+            *entry = (v * 255.0).round() as u8;
+        }
+
+        let saturation = if adj.grayscale {
+            Some(0.0)
+        } else {
+            (adj.saturation != 1.0).then_some(adj.saturation)
+        };
+
+        Self { table, saturation }
+    }
+
+    /// Adjusts one straight-alpha RGBA pixel.
+    #[inline]
+    pub fn apply(&self, p: &[u8]) -> [u8; 4] {
+        let (mut r, mut g, mut b) = (p[0], p[1], p[2]);
+
+        if let Some(s) = self.saturation {
+            let (rf, gf, bf) = (r as f32, g as f32, b as f32);
+            let luma = 0.2126 * rf + 0.7152 * gf + 0.0722 * bf;
+            let mix = |c: f32| (luma + (c - luma) * s).round().clamp(0.0, 255.0) as u8;
+            r = mix(rf);
+            g = mix(gf);
+            b = mix(bf);
+        }
+
+        [
+            self.table[r as usize],
+            self.table[g as usize],
+            self.table[b as usize],
+            p[3],
+        ]
+    }
+}
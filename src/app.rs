@@ -1,16 +1,21 @@
+use crate::adjustments::{Adjustment, Adjustments};
 use crate::cache::CacheManager;
 use crate::image_item::{ImageItem, ImageSlot};
-use crate::keybinds::Action;
+use crate::keybinds::{Action, Trigger, WheelDirection};
 use crate::loader::Loader;
+use crate::resampling::Resampling;
+use crate::sorting::SortMode;
 use crate::status_bar::{StatusBar, StatusContext};
+use crate::tiles::{Tile, TileKey};
 use crate::view_mode::ViewMode;
+use indexmap::IndexSet;
 use pixels::{Pixels, SurfaceTexture};
-use std::collections::HashSet;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use winit::application::ApplicationHandler;
-use winit::event::WindowEvent;
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
 use winit::event_loop::{ActiveEventLoop, EventLoopProxy};
 use winit::keyboard::ModifiersState;
 use winit::window::{Window, WindowId};
@@ -34,19 +39,39 @@ use winit::platform::x11::WindowAttributesExtX11;
 
 use std::sync::atomic::AtomicBool;
 
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+const WHEEL_PIXELS_PER_NOTCH: f64 = 50.0;
+// How long after the last zoom/pan/resize frames are drawn with the fast filter
+const PREVIEW_DURATION: Duration = Duration::from_millis(150);
+
 #[derive(Debug)]
 pub enum AppEvent {
-    InitialCount(usize),
-    MetadataLoaded(usize, ImageItem),
-    MetadataError(usize, PathBuf, String),
-    DiscoveryComplete,
+    // Discovery events start with the generation of the discovery that sent them
+    InitialCount(usize, usize),
+    MetadataLoaded(usize, usize, ImageItem),
+    MetadataError(usize, usize, PathBuf, String),
+    DiscoveryComplete(usize),
     ImagePixelsLoaded(PathBuf, Arc<crate::image_item::LoadedImage>),
     ThumbnailLoaded(PathBuf, Arc<(u32, u32, Vec<u8>)>),
+    DetailsLoaded(PathBuf, crate::image_item::Details),
     LoadError(PathBuf, String),
     LoadCancelled(PathBuf),
+    TilesLoaded(PathBuf, TileKey, Vec<(TileKey, Arc<Tile>)>),
+    TilesError(PathBuf, TileKey, String),
+    TilesCancelled(PathBuf, TileKey),
     FileChanged(ImageItem),
     FileDeleted(PathBuf),
+    ArchiveChanged(PathBuf, Vec<ImageItem>), // With the entries it has now
     HandlerFinished,
+    ConfigChanged,
+    SaveFinished(PathBuf, Result<PathBuf, String>),
+    CropExported(PathBuf, Result<PathBuf, String>),
+    FileOpFinished(crate::file_ops::FileOpResult, Vec<String>),
+    #[cfg(unix)]
+    Remote(
+        crate::ipc::Command,
+        crossbeam_channel::Sender<serde_json::Value>,
+    ),
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -55,6 +80,14 @@ pub enum InputMode {
     Filtering,
     WaitingForHandler,
     AwaitingTarget(String),
+    ConfirmSave {
+        path: PathBuf,
+        jpeg: bool,
+    },
+    EnteringPath {
+        prompt: crate::file_ops::PathPrompt,
+        text: String,
+    },
 }
 
 pub struct App {
@@ -67,12 +100,23 @@ pub struct App {
     pub window: Option<Arc<Window>>,
     pub pixels: Option<Pixels<'static>>,
     pub filter_text: String,
+    pub sort_mode: SortMode,
+    pub sort_reverse: bool,
+    pub start_path: Option<PathBuf>, // Selected once its metadata arrives
+    pub start_index: Option<usize>,  // Fallback when start_path is not found
     pub proxy: EventLoopProxy<AppEvent>,
 
     // Resources
     pub loader: Loader,
     pub cache: CacheManager,
     pub pending: HashSet<PathBuf>, // Track what we've already sent to the loader
+    pub pending_tiles: HashSet<(PathBuf, TileKey)>, // Batches in flight
+    pub failed_tiles: HashSet<(PathBuf, TileKey)>, // Tried again once the image is reopened
+    pub pending_details: HashSet<PathBuf>,
+    pub watcher: Option<crate::watcher::Watcher>,
+    pub discovery: usize, // Generation of the running discovery, older events are dropped
+    pub include_hidden: bool,
+    pub socket: Option<PathBuf>, // Listened on with --ipc, handed to handlers
 
     // Animation state
     pub current_frame_index: usize,
@@ -86,15 +130,31 @@ pub struct App {
     pub handler_cancel_flag: Arc<AtomicBool>,
     pub is_handler_running: bool,
 
+    // Mouse state
+    pub cursor_pos: Option<(f64, f64)>,
+    pub drag_origin: Option<(f64, f64, i32, i32)>, // cursor x/y and offsets at press
+    pub last_click: Option<(Instant, usize)>,
+    pub wheel_accum: (f64, f64),
+    pub last_interaction: Instant,
+
     // UI
     pub status_bar: StatusBar,
     pub show_status_bar: bool,
     pub discovery_complete: bool,
     pub grid_mode: bool,
     pub show_alpha: bool,
-    pub marked_files: HashSet<String>,
+    pub adjustments: Adjustments, // Used by all images, unless adjust_per_image
+    pub image_adjustments: HashMap<PathBuf, Adjustments>,
+    pub compare: Option<crate::compare::Compare>,
+    pub crop: Option<crate::crop::Crop>,
+    pub marked_files: IndexSet<String>, // In the order they were marked
     pub bindings: Vec<crate::keybinds::Binding>,
     pub prefix_count: Option<usize>,
+    pub status_message: Option<String>, // Shown instead of the left status, until Escape
+    pub last_trashed: Vec<PathBuf>,     // Restored by undo_trash
+    pub last_target_dir: Option<PathBuf>, // Offered again by the move and copy prompts
+    pub picker: bool,                   // Enter ends the session with the selection, see --pick
+    pub picked: bool,
 
     pub slideshow_on: bool,
     pub slideshow_delay: Duration,
@@ -119,10 +179,21 @@ impl App {
             window: None,
             pixels: None,
             filter_text: String::new(),
+            sort_mode: config.options.sort,
+            sort_reverse: config.options.sort_reverse,
+            start_path: None,
+            start_index: None,
             loader: Loader::new(proxy.clone()),
             proxy,
             cache: CacheManager::new(config.options.max_memory_percent),
             pending: HashSet::new(),
+            pending_tiles: HashSet::new(),
+            failed_tiles: HashSet::new(),
+            pending_details: HashSet::new(),
+            watcher: None,
+            discovery: 0,
+            include_hidden: false,
+            socket: None,
             current_frame_index: 0,
             is_playing: config.options.autoplay_animations,
             last_update: Instant::now(),
@@ -131,14 +202,28 @@ impl App {
             handler_cancel_flag: Arc::new(AtomicBool::new(false)),
             is_handler_running: false,
             modifiers: ModifiersState::default(),
+            cursor_pos: None,
+            drag_origin: None,
+            last_click: None,
+            wheel_accum: (0.0, 0.0),
+            last_interaction: Instant::now(),
             status_bar: StatusBar::new(),
             show_status_bar: true,
             discovery_complete: false,
             grid_mode: start_in_grid_mode,
             show_alpha: false,
-            marked_files: HashSet::new(),
+            adjustments: Adjustments::default(),
+            image_adjustments: HashMap::new(),
+            compare: None,
+            crop: None,
+            marked_files: IndexSet::new(),
             bindings: crate::keybinds::Binding::get_all_bindings(),
             prefix_count: None,
+            status_message: crate::config::AppConfig::take_load_error(),
+            last_trashed: Vec::new(),
+            last_target_dir: None,
+            picker: false,
+            picked: false,
             slideshow_on: false,
             slideshow_delay: Duration::from_secs(config.options.slideshow_default_delay),
             last_slide_time: Instant::now(),
@@ -150,20 +235,26 @@ impl App {
         val.unwrap_or(1).max(1)
     }
 
-    fn get_available_window_size(&self) -> Option<(f64, f64)> {
+    /// Size of the image area. Side by side, the area of one pane.
+    pub fn get_available_window_size(&self) -> Option<(f64, f64)> {
         if let Some(w) = &self.window {
             let s = w.inner_size();
             let mut h = s.height as f64;
             if self.show_status_bar {
                 h -= self.status_bar.height as f64;
             }
-            Some((s.width as f64, h))
+            let width = if self.is_side_by_side() {
+                s.width / 2
+            } else {
+                s.width
+            };
+            Some((width as f64, h))
         } else {
             None
         }
     }
 
-    fn get_current_scale(&self) -> f64 {
+    pub fn get_current_scale(&self) -> f64 {
         if self.images.is_empty() {
             return 1.0;
         }
@@ -251,13 +342,14 @@ impl App {
         }
     }
 
-    fn reset_view_for_new_image(&mut self) {
+    pub fn reset_view_for_new_image(&mut self) {
         let config = crate::config::AppConfig::get();
         self.off_x = 0;
         self.off_y = 0;
         self.current_frame_index = 0;
         self.frame_timer = Duration::ZERO;
         self.is_playing = config.options.autoplay_animations;
+        self.failed_tiles.clear();
     }
 
     fn mutate_current_image<F>(&mut self, f: F) -> bool
@@ -287,6 +379,162 @@ impl App {
         false
     }
 
+    /// Tiles are read from the file as they are stored, so these images can't
+    /// be rotated or flipped in memory.
+    fn current_is_tiled(&self) -> bool {
+        let Some(ImageSlot::MetadataLoaded(item)) = self.images.get(self.current_index) else {
+            return false;
+        };
+        self.cache
+            .get_image(&item.path)
+            .is_some_and(|image| image.tiled.is_some())
+    }
+
+    /// Rebuilds what was derived from the config after it was reloaded.
+    fn apply_config(&mut self) {
+        self.status_message = None;
+        self.bindings = crate::keybinds::Binding::get_all_bindings();
+        self.status_bar = StatusBar::new();
+        if let Some(w) = &self.window {
+            self.status_bar.set_scale(w.scale_factor() as f32);
+        }
+    }
+
+    pub fn current_path(&self) -> Option<PathBuf> {
+        match self.images.get(self.current_index) {
+            Some(ImageSlot::MetadataLoaded(item)) => Some(item.path.clone()),
+            _ => None,
+        }
+    }
+
+    /// Moves the selection to `path` if it is in the (filtered) list.
+    pub fn select_path(&mut self, path: &Path) -> bool {
+        let found = self
+            .images
+            .iter()
+            .position(|slot| matches!(slot, ImageSlot::MetadataLoaded(item) if item.path == path));
+        if let Some(idx) = found {
+            self.current_index = idx;
+        }
+        found.is_some()
+    }
+
+    /// Drops a file that is gone from the lists.
+    pub fn file_deleted(&mut self, path: PathBuf) {
+        // Already dropped when rsiv moved or trashed it itself
+        let listed = self
+            .all_images
+            .iter()
+            .any(|slot| matches!(slot, ImageSlot::MetadataLoaded(item) if item.path == path));
+        if !listed {
+            return;
+        }
+        self.cache.remove(&path);
+        if matches!(&self.compare, Some(c) if c.other.path == path) {
+            self.compare = None;
+        }
+
+        // Remove from all_images
+        self.all_images.retain(|slot| {
+            if let ImageSlot::MetadataLoaded(item) = slot {
+                item.path != path
+            } else {
+                true
+            }
+        });
+
+        // If the deleted image was the current one, standard logic applies
+        let was_current = if !self.images.is_empty() && self.current_index < self.images.len() {
+            if let ImageSlot::MetadataLoaded(item) = &self.images[self.current_index] {
+                item.path == path
+            } else {
+                false
+            }
+        } else {
+            false
+        };
+
+        self.apply_filter();
+
+        if self.current_index >= self.images.len() {
+            self.current_index = self.images.len().saturating_sub(1);
+        }
+
+        if was_current || self.grid_mode {
+            self.reset_view_for_new_image();
+            if let Some(w) = &self.window {
+                w.request_redraw();
+            }
+        }
+    }
+
+    /// Adds a new file to the lists, or refreshes one that changed.
+    pub fn file_changed(&mut self, new_item: ImageItem) {
+        let path = new_item.path.clone();
+
+        // Check if this file already exists in our list
+        let existing_idx = self.all_images.iter().position(|slot| {
+            if let ImageSlot::MetadataLoaded(item) = slot {
+                item.path == path
+            } else {
+                false
+            }
+        });
+
+        let current = self.current_path();
+        self.failed_tiles.retain(|(p, _)| p != &path);
+
+        if let Some(idx) = existing_idx {
+            // MODIFICATION: Update existing slot and clear cache
+            self.cache.remove(&path);
+            if self.discovery_complete && self.sort_mode.needs_metadata() {
+                // The changed metadata may move it
+                self.all_images.remove(idx);
+                let pos = crate::sorting::insert_position(
+                    &self.all_images,
+                    &new_item,
+                    self.sort_mode,
+                    self.sort_reverse,
+                );
+                self.all_images
+                    .insert(pos, ImageSlot::MetadataLoaded(new_item.clone()));
+            } else {
+                self.all_images[idx] = ImageSlot::MetadataLoaded(new_item.clone());
+            }
+
+            // If currently visible, trigger redraw
+            if !self.images.is_empty() && self.current_index < self.images.len() {
+                if let ImageSlot::MetadataLoaded(current_item) = &self.images[self.current_index] {
+                    if current_item.path == path {
+                        if let Some(w) = &self.window {
+                            w.request_redraw();
+                        }
+                    }
+                }
+            }
+        } else {
+            // Creation: Insert new item
+            // Find correct position to keep list sorted
+            let insert_pos = crate::sorting::insert_position(
+                &self.all_images,
+                &new_item,
+                self.sort_mode,
+                self.sort_reverse,
+            );
+            self.all_images
+                .insert(insert_pos, ImageSlot::MetadataLoaded(new_item));
+        }
+
+        // Re-apply filter to ensure self.images reflects self.all_images
+        self.apply_filter();
+        if let Some(p) = current {
+            self.select_path(&p);
+        }
+        if let Some(w) = &self.window {
+            w.request_redraw();
+        }
+    }
+
     fn is_path_visible(&self, path: &PathBuf) -> bool {
         if !self.grid_mode {
             if let ImageSlot::MetadataLoaded(item) = &self.images[self.current_index] {
@@ -328,6 +576,7 @@ impl App {
         false
     }
 
+    #[allow(clippy::collapsible_match)]
     fn handle_navigation_action(&mut self, action: Action, count: usize) -> bool {
         let mut needs_redraw = false;
         match action {
@@ -396,6 +645,28 @@ impl App {
                     }
                 }
             }
+            Action::NextPage | Action::PrevPage => {
+                let forward = action == Action::NextPage;
+                let mut error = None;
+                needs_redraw = self.mutate_current_image(|img| {
+                    let pages = img.page_count();
+                    let target = if forward {
+                        (img.page + count) % pages
+                    } else {
+                        (img.page + pages - count % pages) % pages
+                    };
+                    img.show_page(target).unwrap_or_else(|e| {
+                        error = Some(format!("Failed to decode page {}: {}", target + 1, e));
+                        false
+                    })
+                });
+                if error.is_some() {
+                    self.status_message = error;
+                } else if needs_redraw {
+                    self.off_x = 0;
+                    self.off_y = 0;
+                }
+            }
             Action::NextMark => {
                 if !self.images.is_empty() && !self.marked_files.is_empty() {
                     for _ in 0..count {
@@ -689,11 +960,13 @@ impl App {
 
         if needs_redraw {
             self.clamp_offsets();
+            self.last_interaction = Instant::now();
         }
 
         needs_redraw
     }
 
+    #[allow(clippy::collapsible_match)]
     fn handle_image_ops_action(&mut self, action: Action, count: usize) -> bool {
         let mut needs_redraw = false;
         match action {
@@ -704,7 +977,7 @@ impl App {
                             let idx = (self.current_index + i) % self.images.len();
                             if let ImageSlot::MetadataLoaded(item) = &self.images[idx] {
                                 let path = item.path.to_string_lossy().to_string();
-                                if !self.marked_files.remove(&path) {
+                                if !self.marked_files.shift_remove(&path) {
                                     self.marked_files.insert(path);
                                 }
                             }
@@ -713,7 +986,7 @@ impl App {
                     } else if let ImageSlot::MetadataLoaded(item) = &self.images[self.current_index]
                     {
                         let path = item.path.to_string_lossy().to_string();
-                        if !self.marked_files.remove(&path) {
+                        if !self.marked_files.shift_remove(&path) {
                             self.marked_files.insert(path);
                         }
                     }
@@ -734,7 +1007,8 @@ impl App {
                             None
                         };
                         if let Some(p) = &path_to_remove {
-                            self.marked_files.remove(&p.to_string_lossy().to_string());
+                            self.marked_files
+                                .shift_remove(&p.to_string_lossy().to_string());
                         }
                         self.images.remove(self.current_index);
                         if let Some(p) = path_to_remove {
@@ -760,7 +1034,7 @@ impl App {
                 for item_slot in &self.images {
                     if let ImageSlot::MetadataLoaded(item) = item_slot {
                         let path = item.path.to_string_lossy().to_string();
-                        if !self.marked_files.remove(&path) {
+                        if !self.marked_files.shift_remove(&path) {
                             self.marked_files.insert(path);
                         }
                     }
@@ -771,6 +1045,15 @@ impl App {
                 self.marked_files.clear();
                 needs_redraw = true;
             }
+            Action::RotateCW
+            | Action::RotateCCW
+            | Action::FlipHorizontal
+            | Action::FlipVertical
+                if self.current_is_tiled() =>
+            {
+                self.status_message = Some("Tiled images can't be rotated or flipped".into());
+                needs_redraw = true;
+            }
             Action::RotateCW => {
                 needs_redraw = self.mutate_current_image(|img| {
                     img.rotate(true);
@@ -803,6 +1086,9 @@ impl App {
                     false // dimensions didn't change
                 });
             }
+            Action::SaveImage => {
+                needs_redraw = self.request_save();
+            }
             _ => {}
         }
         needs_redraw
@@ -846,12 +1132,273 @@ impl App {
                 self.show_alpha = !self.show_alpha;
                 needs_redraw = true;
             }
+            Action::SortNext | Action::SortPrev | Action::SortReverse => {
+                match action {
+                    Action::SortNext => self.sort_mode = self.sort_mode.next(),
+                    Action::SortPrev => self.sort_mode = self.sort_mode.prev(),
+                    _ => self.sort_reverse = !self.sort_reverse,
+                }
+                self.apply_sort();
+                needs_redraw = true;
+            }
             _ => {}
         }
         needs_redraw
     }
 
+    pub fn current_adjustments(&self) -> Adjustments {
+        match self.current_path() {
+            Some(path) => self.adjustments_for(&path),
+            None => self.adjustments,
+        }
+    }
+
+    pub fn adjustments_for(&self, path: &Path) -> Adjustments {
+        if !crate::config::AppConfig::get().options.adjust_per_image {
+            return self.adjustments;
+        }
+        self.image_adjustments
+            .get(path)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn handle_color_action(&mut self, action: Action, count: usize) -> bool {
+        let is_color_action = matches!(
+            action,
+            Action::BrightnessUp
+                | Action::BrightnessDown
+                | Action::ContrastUp
+                | Action::ContrastDown
+                | Action::GammaUp
+                | Action::GammaDown
+                | Action::SaturationUp
+                | Action::SaturationDown
+                | Action::ToggleInvert
+                | Action::ToggleGrayscale
+                | Action::ResetAdjustments
+        );
+        if !is_color_action {
+            return false;
+        }
+
+        let per_image = crate::config::AppConfig::get().options.adjust_per_image;
+        let path = self.current_path();
+        let adjustments = match (&path, per_image) {
+            (_, false) => &mut self.adjustments,
+            (Some(p), true) => self.image_adjustments.entry(p.clone()).or_default(),
+            (None, true) => return false,
+        };
+
+        let steps = count as i32;
+        match action {
+            Action::BrightnessUp => adjustments.step(Adjustment::Brightness, steps),
+            Action::BrightnessDown => adjustments.step(Adjustment::Brightness, -steps),
+            Action::ContrastUp => adjustments.step(Adjustment::Contrast, steps),
+            Action::ContrastDown => adjustments.step(Adjustment::Contrast, -steps),
+            Action::GammaUp => adjustments.step(Adjustment::Gamma, steps),
+            Action::GammaDown => adjustments.step(Adjustment::Gamma, -steps),
+            Action::SaturationUp => adjustments.step(Adjustment::Saturation, steps),
+            Action::SaturationDown => adjustments.step(Adjustment::Saturation, -steps),
+            Action::ToggleInvert => adjustments.invert = !adjustments.invert,
+            Action::ToggleGrayscale => adjustments.grayscale = !adjustments.grayscale,
+            _ => *adjustments = Adjustments::default(),
+        }
+
+        // Untouched images don't need an entry
+        if per_image && adjustments.is_identity() {
+            if let Some(p) = path {
+                self.image_adjustments.remove(&p);
+            }
+        }
+        true
+    }
+
+    pub fn run_action(&mut self, el: &ActiveEventLoop, action: Action) -> bool {
+        let old_scale = self.get_current_scale();
+        let mut needs_redraw = false;
+        match action {
+            Action::Quit => el.exit(),
+            Action::FilterMode => {
+                self.input_mode = InputMode::Filtering;
+                needs_redraw = true;
+            }
+            Action::ScriptHandlerPrefix => {
+                self.input_mode = InputMode::WaitingForHandler;
+                needs_redraw = true;
+            }
+            Action::Digit(d) => {
+                if d == 0 && self.prefix_count.is_none() {
+                    // Treat '0' as FirstImage (g) if no prefix count
+                    self.handle_navigation_action(Action::FirstImage, 1);
+                    needs_redraw = true;
+                } else {
+                    let current = self.prefix_count.unwrap_or(0);
+                    let new_count = current.saturating_mul(10).saturating_add(d);
+                    self.prefix_count = Some(new_count);
+                    needs_redraw = true;
+                }
+            }
+            other_action => {
+                // Capture raw prefix for toggles
+                let raw_prefix = self.prefix_count;
+                let count = self.pop_count();
+
+                if self.handle_navigation_action(other_action, count)
+                    || self.handle_grid_movement_action(other_action, count)
+                    || self.handle_image_ops_action(other_action, count)
+                    || self.handle_crop_action(other_action, count)
+                    || self.handle_view_action(other_action, old_scale)
+                    || self.handle_toggle_action(other_action, raw_prefix)
+                    || self.handle_color_action(other_action, count)
+                    || self.handle_compare_action(other_action, count)
+                    || self.handle_file_action(other_action)
+                {
+                    needs_redraw = true;
+                }
+                if matches!(other_action, Action::RemoveImage) && self.all_images.is_empty() {
+                    el.exit();
+                }
+            }
+        }
+        needs_redraw
+    }
+
+    fn grid_layout(&self) -> Option<crate::renderer::GridLayout> {
+        let (buf_w, buf_h) = self.get_available_window_size()?;
+        Some(crate::renderer::GridLayout::new(
+            buf_w as i32,
+            buf_h as i32,
+            self.current_index,
+        ))
+    }
+
+    /// Runs a mouse-triggered action. Zooming keeps the image point under the cursor fixed.
+    fn run_mouse_action(&mut self, el: &ActiveEventLoop, action: Action) -> bool {
+        let old_scale = self.get_current_scale();
+        let (old_off_x, old_off_y) = (self.off_x, self.off_y);
+
+        let needs_redraw = self.run_action(el, action);
+
+        if matches!(action, Action::ZoomIn | Action::ZoomOut) && !self.grid_mode {
+            if let (Some((cx, cy)), Some((buf_w, buf_h))) =
+                (self.cursor_pos, self.get_available_window_size())
+            {
+                let ratio = self.get_current_scale() / old_scale;
+                // Side by side panes share the view, so the cursor counts within its pane
+                let dx = cx % buf_w - buf_w / 2.0;
+                let dy = cy - buf_h / 2.0;
+                self.off_x = (dx - (dx - old_off_x as f64) * ratio) as i32;
+                self.off_y = (dy - (dy - old_off_y as f64) * ratio) as i32;
+                self.clamp_offsets();
+            }
+        }
+        needs_redraw
+    }
+
+    fn handle_mouse_button(&mut self, el: &ActiveEventLoop, button: MouseButton) -> bool {
+        let trigger = Trigger::Mouse(button);
+        if let Some(action) = crate::keybinds::Binding::resolve_mouse(
+            &trigger,
+            &self.bindings,
+            self.modifiers,
+            self.grid_mode,
+        ) {
+            return self.run_mouse_action(el, action);
+        }
+
+        if button != MouseButton::Left {
+            return false;
+        }
+        let Some((cx, cy)) = self.cursor_pos else {
+            return false;
+        };
+
+        if self.start_divider_drag(cx) {
+            return true;
+        }
+
+        if !self.grid_mode {
+            // Unbound left button: drag to pan
+            self.drag_origin = Some((cx, cy, self.off_x, self.off_y));
+            return false;
+        }
+
+        let Some(idx) = self
+            .grid_layout()
+            .and_then(|layout| layout.hit_test(cx, cy, self.images.len()))
+        else {
+            return false;
+        };
+
+        let now = Instant::now();
+        let is_double_click = matches!(
+            self.last_click,
+            Some((time, last_idx)) if last_idx == idx && now.duration_since(time) <= DOUBLE_CLICK_TIME
+        );
+
+        self.current_index = idx;
+        if is_double_click {
+            self.last_click = None;
+            self.handle_toggle_action(Action::ToggleGrid, None);
+        } else {
+            self.last_click = Some((now, idx));
+        }
+        true
+    }
+
+    fn handle_mouse_wheel(&mut self, el: &ActiveEventLoop, delta: MouseScrollDelta) -> bool {
+        let (dx, dy) = match delta {
+            MouseScrollDelta::LineDelta(x, y) => (x as f64, y as f64),
+            MouseScrollDelta::PixelDelta(pos) => {
+                // Touchpads report small pixel steps, accumulate them into whole notches
+                self.wheel_accum.0 += pos.x;
+                self.wheel_accum.1 += pos.y;
+                let notch_x = (self.wheel_accum.0 / WHEEL_PIXELS_PER_NOTCH).trunc();
+                let notch_y = (self.wheel_accum.1 / WHEEL_PIXELS_PER_NOTCH).trunc();
+                self.wheel_accum.0 -= notch_x * WHEEL_PIXELS_PER_NOTCH;
+                self.wheel_accum.1 -= notch_y * WHEEL_PIXELS_PER_NOTCH;
+                (notch_x, notch_y)
+            }
+        };
+
+        let mut steps = Vec::new();
+        if dy != 0.0 {
+            let dir = if dy > 0.0 {
+                WheelDirection::Up
+            } else {
+                WheelDirection::Down
+            };
+            steps.push((dir, dy.abs().round().max(1.0) as usize));
+        }
+        if dx != 0.0 {
+            let dir = if dx > 0.0 {
+                WheelDirection::Right
+            } else {
+                WheelDirection::Left
+            };
+            steps.push((dir, dx.abs().round().max(1.0) as usize));
+        }
+
+        let mut needs_redraw = false;
+        for (dir, count) in steps {
+            let Some(action) = crate::keybinds::Binding::resolve_mouse(
+                &Trigger::Wheel(dir),
+                &self.bindings,
+                self.modifiers,
+                self.grid_mode,
+            ) else {
+                continue;
+            };
+            for _ in 0..count {
+                needs_redraw |= self.run_mouse_action(el, action);
+            }
+        }
+        needs_redraw
+    }
+
     fn render(&mut self) {
+        self.sync_crop();
         let scale = self.get_current_scale();
 
         if !self.images.is_empty() {
@@ -920,6 +1467,16 @@ impl App {
                         self.loader.request_image(item.path.clone(), item.format);
                     }
 
+                    if let Some(compare) = &self.compare {
+                        let other = &compare.other;
+                        if self.cache.get_image(&other.path).is_none()
+                            && !self.pending.contains(&other.path)
+                        {
+                            self.pending.insert(other.path.clone());
+                            self.loader.request_image(other.path.clone(), other.format);
+                        }
+                    }
+
                     // Pre-fetch ahead
                     for offset in 1..=config.options.preload_ahead {
                         let idx = self.current_index + offset;
@@ -985,6 +1542,19 @@ impl App {
             }
         }
 
+        let adjustments = if self.grid_mode {
+            Adjustments::default()
+        } else {
+            self.current_adjustments()
+        };
+        let other_adjustments = self
+            .compare
+            .as_ref()
+            .map(|c| self.adjustments_for(&c.other.path))
+            .unwrap_or_default();
+        // Images drawn this frame, with their size and the tiles they were missing
+        let mut drawn = Vec::new();
+
         // Clear background and get pixels
         let Some(pixels) = &mut self.pixels else {
             return;
@@ -1019,31 +1589,132 @@ impl App {
                     error: crate::utils::parse_color(&config.ui.error_color),
                 };
 
-                crate::renderer::draw_grid(
-                    frame_slice,
-                    buf_w,
-                    available_h,
-                    &self.images,
-                    &self.cache,
-                    self.current_index,
-                    &colors,
-                    &self.marked_files,
-                );
+                let params = crate::renderer::DrawGridParams {
+                    images: &self.images,
+                    cache: &self.cache,
+                    selected_idx: self.current_index,
+                    colors: &colors,
+                    marked_paths: &self.marked_files,
+                };
+                crate::renderer::draw_grid(frame_slice, buf_w, available_h, &params);
             } else if let ImageSlot::MetadataLoaded(item) = &self.images[self.current_index] {
                 if let Some(loaded_image) = self.cache.get_image(&item.path) {
+                    // Show a fast preview while the view is moving, refine once it settles
+                    let resampling = if config.options.resampling != Resampling::Nearest
+                        && self.last_interaction.elapsed() < PREVIEW_DURATION
+                    {
+                        if let Some(w) = &self.window {
+                            w.request_redraw();
+                        }
+                        Resampling::Nearest
+                    } else {
+                        config.options.resampling
+                    };
+
                     let params = crate::renderer::DrawImageParams {
                         image: &loaded_image,
+                        cache: &self.cache,
                         frame_idx: self.current_frame_index,
                         scale,
                         off_x: self.off_x,
                         off_y: self.off_y,
                         show_alpha: self.show_alpha,
+                        resampling,
+                        adjustments,
+                        viewport: crate::renderer::Viewport::new(0, 0, buf_w, available_h),
+                        clip: None,
                     };
-                    crate::renderer::draw_image(frame_slice, buf_w, available_h, &params);
+                    let size_before = loaded_image.size_in_kb();
+
+                    if let Some(compare) = &mut self.compare {
+                        let other_path = compare.other.path.clone();
+                        let other_image = self.cache.get_image(&other_path);
+                        let other_size = other_image.as_ref().map(|o| o.size_in_kb());
+                        // Shown at the same size as the current image, so both line up
+                        let other_params =
+                            other_image
+                                .as_ref()
+                                .map(|other| crate::renderer::DrawImageParams {
+                                    image: other,
+                                    scale: scale * loaded_image.width as f64
+                                        / other.width.max(1) as f64,
+                                    adjustments: other_adjustments,
+                                    ..params
+                                });
+
+                        let (missing, other_missing) = compare.draw(
+                            frame_slice,
+                            buf_w,
+                            params,
+                            other_params,
+                            bg_color,
+                            crate::utils::parse_color(&config.ui.divider_color),
+                        );
+                        drawn.push((
+                            item.path.clone(),
+                            loaded_image.clone(),
+                            size_before,
+                            missing,
+                        ));
+                        if let (Some(image), Some(size)) = (other_image, other_size) {
+                            drawn.push((other_path, image, size, other_missing));
+                        }
+                    } else {
+                        let missing = crate::renderer::draw_image(frame_slice, buf_w, &params);
+                        if let Some(crop) = &self.crop {
+                            let top_left = crate::renderer::image_top_left(
+                                loaded_image.width,
+                                loaded_image.height,
+                                scale,
+                                (self.off_x, self.off_y),
+                                params.viewport,
+                            );
+                            let rect = crop.draw(
+                                frame_slice,
+                                buf_w,
+                                params.viewport,
+                                top_left,
+                                scale,
+                                crate::utils::parse_color(&config.ui.crop_color),
+                            );
+
+                            // Below the selection, or inside it at the bottom of the window
+                            let label_h = self.status_bar.height as i32;
+                            let label_y = (rect.y + rect.h + 4).min(available_h - label_h - 4);
+                            let mut fb = crate::frame_buffer::FrameBuffer::new(
+                                frame_slice,
+                                buf_w as u32,
+                                buf_h as u32,
+                            );
+                            self.status_bar.draw_label(
+                                &mut fb,
+                                &crop.to_string(),
+                                rect.x.max(0),
+                                label_y.max(0),
+                            );
+                        }
+                        drawn.push((
+                            item.path.clone(),
+                            loaded_image.clone(),
+                            size_before,
+                            missing,
+                        ));
+                    }
                 }
             }
         }
 
+        // Details are read by a worker, their tokens stay empty until then
+        if let Some(ImageSlot::MetadataLoaded(item)) = self.images.get(self.current_index) {
+            if self.show_status_bar
+                && self.status_bar.needs_details()
+                && item.details().is_none()
+                && self.pending_details.insert(item.path.clone())
+            {
+                self.loader.request_details(item.path.clone(), item.format);
+            }
+        }
+
         // Draw Status Bar
         if self.show_status_bar && buf_h > 0 {
             let mut fb =
@@ -1092,19 +1763,25 @@ impl App {
                 }
             };
 
-            let (current_frame, total_frames) = if !self.images.is_empty() {
-                if let ImageSlot::MetadataLoaded(item) = &self.images[self.current_index] {
-                    if let Some(img) = self.cache.get_image(&item.path) {
-                        (self.current_frame_index + 1, img.frames.len())
+            let (current_frame, total_frames, current_page, total_pages) =
+                if !self.images.is_empty() {
+                    if let ImageSlot::MetadataLoaded(item) = &self.images[self.current_index] {
+                        if let Some(img) = self.cache.get_image(&item.path) {
+                            (
+                                self.current_frame_index + 1,
+                                img.frames.len(),
+                                img.page + 1,
+                                img.page_count(),
+                            )
+                        } else {
+                            (0, 0, 0, 0)
+                        }
                     } else {
-                        (0, 0)
+                        (0, 0, 0, 0)
                     }
                 } else {
-                    (0, 0)
-                }
-            } else {
-                (0, 0)
-            };
+                    (0, 0, 0, 0)
+                };
 
             let spinner_frame = if self.is_handler_running {
                 if let Some(w) = &self.window {
@@ -1132,8 +1809,20 @@ impl App {
                 filter_text: &self.filter_text,
                 current_frame,
                 total_frames,
+                current_page,
+                total_pages,
                 spinner_frame,
                 is_handler_running: self.is_handler_running,
+                sort_mode: self.sort_mode,
+                sort_reverse: self.sort_reverse,
+                message: self.status_message.as_deref(),
+                adjustments,
+                compare: self.compare.as_ref().filter(|_| !self.grid_mode),
+                item: match self.images.get(self.current_index) {
+                    Some(ImageSlot::MetadataLoaded(item)) => Some(item),
+                    _ => None,
+                },
+                marked_count: self.marked_files.len(),
             };
 
             self.status_bar.draw(&mut fb, ctx);
@@ -1142,6 +1831,38 @@ impl App {
         if let Err(err) = pixels.render() {
             crate::rsiv_err!("Pixels render error: {}", err);
         }
+
+        for (path, image, size_before, missing_tiles) in drawn {
+            self.finish_drawn_image(path, image, size_before, missing_tiles);
+        }
+    }
+
+    /// Requests the tiles an image was missing, and re-weighs it in the cache
+    /// when drawing built new mip levels.
+    fn finish_drawn_image(
+        &mut self,
+        path: PathBuf,
+        image: Arc<crate::image_item::LoadedImage>,
+        size_before: u32,
+        missing_tiles: Vec<TileKey>,
+    ) {
+        if let Some(source) = &image.tiled {
+            let mut batches: HashMap<TileKey, Vec<TileKey>> = HashMap::new();
+            for key in missing_tiles {
+                batches.entry(source.batch_key(key)).or_default().push(key);
+            }
+            for (batch, keys) in batches {
+                let key = (source.path.clone(), batch);
+                if !self.failed_tiles.contains(&key) && self.pending_tiles.insert(key) {
+                    self.loader.request_tiles(source.clone(), batch, keys);
+                }
+            }
+        }
+
+        // New mip levels were built, re-insert so the cache weighs them
+        if image.size_in_kb() != size_before {
+            self.cache.insert_image(path, image);
+        }
     }
 }
 
@@ -1176,11 +1897,18 @@ impl ApplicationHandler<AppEvent> for App {
 
     fn user_event(&mut self, _el: &ActiveEventLoop, event: AppEvent) {
         match event {
-            AppEvent::InitialCount(count) => {
+            AppEvent::InitialCount(generation, _)
+            | AppEvent::MetadataLoaded(generation, ..)
+            | AppEvent::MetadataError(generation, ..)
+            | AppEvent::DiscoveryComplete(generation)
+                if generation != self.discovery => {}
+            AppEvent::InitialCount(_, count) => {
                 self.all_images = vec![ImageSlot::PendingMetadata; count];
                 self.images = vec![ImageSlot::PendingMetadata; count];
             }
-            AppEvent::MetadataLoaded(idx, item) => {
+            AppEvent::MetadataLoaded(_, idx, item) => {
+                let start_here = self.start_path.as_ref() == Some(&item.path);
+
                 if let Some(slot) = self.all_images.get_mut(idx) {
                     *slot = ImageSlot::MetadataLoaded(item.clone());
                 }
@@ -1190,16 +1918,30 @@ impl ApplicationHandler<AppEvent> for App {
                         *slot = ImageSlot::MetadataLoaded(item);
                     }
                 } else {
+                    let current = self.current_path();
                     self.apply_filter();
+                    if let Some(p) = current {
+                        self.select_path(&p);
+                    }
                 }
 
-                if self.current_index == idx {
+                if start_here {
+                    self.start_index = None;
+                    if let Some(path) = self.start_path.take() {
+                        if self.select_path(&path) {
+                            self.reset_view_for_new_image();
+                            if let Some(w) = self.window.as_ref() {
+                                w.request_redraw();
+                            }
+                        }
+                    }
+                } else if self.current_index == idx {
                     if let Some(w) = self.window.as_ref() {
                         w.request_redraw();
                     }
                 }
             }
-            AppEvent::MetadataError(idx, path, err) => {
+            AppEvent::MetadataError(_, idx, path, err) => {
                 crate::rsiv_err!("Metadata error for {:?}: {}", path, err);
                 if let Some(slot) = self.all_images.get_mut(idx) {
                     *slot = ImageSlot::Error(err.clone());
@@ -1208,24 +1950,56 @@ impl ApplicationHandler<AppEvent> for App {
                     *slot = ImageSlot::Error(err);
                 }
             }
-            AppEvent::DiscoveryComplete => {
+            AppEvent::DiscoveryComplete(_) => {
                 self.discovery_complete = true;
+                let missed_start = self.start_path.take().is_some();
+                let start_index = self.start_index.take().filter(|_| missed_start);
 
-                let has_valid_images = self
+                let listed: HashSet<String> = self
                     .all_images
                     .iter()
-                    .any(|slot| matches!(slot, ImageSlot::MetadataLoaded(_)));
+                    .filter_map(|slot| match slot {
+                        ImageSlot::MetadataLoaded(item) => {
+                            Some(item.path.to_string_lossy().into_owned())
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                // Marks only apply to listed images, e.g. after a remote open
+                self.marked_files.retain(|path| listed.contains(path));
 
-                if !has_valid_images {
+                if listed.is_empty() && self.discovery == 0 {
                     crate::rsiv_err!("No images found. Exiting...");
                     _el.exit();
+                } else {
+                    if self.sort_mode.needs_metadata() {
+                        self.apply_sort();
+                    }
+                    // The start image is gone, stay near where it was
+                    if let Some(idx) = start_index {
+                        self.current_index = idx.min(self.images.len().saturating_sub(1));
+                        self.reset_view_for_new_image();
+                    }
+                    if let Some(w) = &self.window {
+                        w.request_redraw();
+                    }
                 }
             }
             AppEvent::ImagePixelsLoaded(path, image) => {
                 self.pending.remove(&path);
+                // A reload shows the first page again, without the rotations made before
+                for slot in self.all_images.iter_mut().chain(self.images.iter_mut()) {
+                    if let ImageSlot::MetadataLoaded(item) = slot {
+                        if item.path == path {
+                            item.width = image.width;
+                            item.height = image.height;
+                        }
+                    }
+                }
                 self.cache.insert_image(path.clone(), image);
+                let is_compared = matches!(&self.compare, Some(c) if c.other.path == path);
                 if let ImageSlot::MetadataLoaded(item) = &self.images[self.current_index] {
-                    if item.path == path {
+                    if item.path == path || is_compared {
                         self.window.as_ref().unwrap().request_redraw();
                     }
                 }
@@ -1261,90 +2035,78 @@ impl ApplicationHandler<AppEvent> for App {
             AppEvent::LoadCancelled(path) => {
                 self.pending.remove(&path);
             }
-            AppEvent::FileChanged(new_item) => {
-                let path = new_item.path.clone();
-
-                // Check if this file already exists in our list
-                let existing_idx = self.all_images.iter().position(|slot| {
+            AppEvent::DetailsLoaded(path, details) => {
+                self.pending_details.remove(&path);
+                for slot in self.all_images.iter().chain(&self.images) {
                     if let ImageSlot::MetadataLoaded(item) = slot {
-                        item.path == path
-                    } else {
-                        false
-                    }
-                });
-
-                if let Some(idx) = existing_idx {
-                    // MODIFICATION: Update existing slot and clear cache
-                    self.cache.remove(&path);
-                    self.all_images[idx] = ImageSlot::MetadataLoaded(new_item.clone());
-
-                    // If currently visible, trigger redraw
-                    if !self.images.is_empty() && self.current_index < self.images.len() {
-                        if let ImageSlot::MetadataLoaded(current_item) =
-                            &self.images[self.current_index]
-                        {
-                            if current_item.path == path {
-                                if let Some(w) = &self.window {
-                                    w.request_redraw();
-                                }
-                            }
+                        if item.path == path {
+                            let _ = item.details.set(details.clone());
                         }
                     }
-                } else {
-                    // Creation: Insert new item
-                    // Find correct position to keep list sorted
-                    let insert_pos = self.all_images.partition_point(|slot| {
-                        if let ImageSlot::MetadataLoaded(item) = slot {
-                            item.path < path
-                        } else {
-                            true
+                }
+                if self.current_path().as_deref() == Some(path.as_path()) {
+                    if let Some(w) = &self.window {
+                        w.request_redraw();
+                    }
+                }
+            }
+            AppEvent::TilesLoaded(path, batch, tiles) => {
+                self.pending_tiles.remove(&(path.clone(), batch));
+                for (key, tile) in tiles {
+                    self.cache.insert_tile(path.clone(), key, tile);
+                }
+                // A JPEG decoded whole keeps the level, so the image weighs more now
+                if let Some(image) = self.cache.get_image(&path).filter(|image| {
+                    image
+                        .tiled
+                        .as_ref()
+                        .is_some_and(|source| source.cached_bytes() > 0)
+                }) {
+                    self.cache.insert_image(path.clone(), image);
+                }
+                if let Some(ImageSlot::MetadataLoaded(item)) = self.images.get(self.current_index) {
+                    if item.path == path && !self.grid_mode {
+                        if let Some(w) = &self.window {
+                            w.request_redraw();
                         }
-                    });
-                    self.all_images
-                        .insert(insert_pos, ImageSlot::MetadataLoaded(new_item));
+                    }
                 }
-
-                // Re-apply filter to ensure self.images reflects self.all_images
-                self.apply_filter();
+            }
+            AppEvent::TilesError(path, batch, err) => {
+                crate::rsiv_err!("Failed to decode tiles of {:?}: {}", path, err);
+                self.pending_tiles.remove(&(path.clone(), batch));
+                self.failed_tiles.insert((path, batch));
+                self.status_message = Some(format!("Failed to decode tiles: {}", err));
                 if let Some(w) = &self.window {
                     w.request_redraw();
                 }
             }
+            AppEvent::TilesCancelled(path, batch) => {
+                self.pending_tiles.remove(&(path, batch));
+            }
+            AppEvent::FileChanged(new_item) => self.file_changed(new_item),
 
-            AppEvent::FileDeleted(path) => {
-                self.cache.remove(&path);
-
-                // Remove from all_images
-                self.all_images.retain(|slot| {
-                    if let ImageSlot::MetadataLoaded(item) = slot {
-                        item.path != path
-                    } else {
-                        true
-                    }
-                });
-
-                // If the deleted image was the current one, standard logic applies
-                let was_current = if !self.images.is_empty() && self.current_index < self.images.len() {
-                    if let ImageSlot::MetadataLoaded(item) = &self.images[self.current_index] {
-                        item.path == path
-                    } else {
-                        false
-                    }
-                } else {
-                    false
-                };
-
-                self.apply_filter();
-
-                if self.current_index >= self.images.len() {
-                    self.current_index = self.images.len().saturating_sub(1);
+            AppEvent::FileDeleted(path) => self.file_deleted(path),
+            AppEvent::ArchiveChanged(archive, items) => {
+                // Entries that were renamed or removed from it are dropped
+                let stale: Vec<PathBuf> = self
+                    .all_images
+                    .iter()
+                    .filter_map(|slot| match slot {
+                        ImageSlot::MetadataLoaded(item)
+                            if crate::archive::is_entry_of(&item.path, &archive)
+                                && !items.iter().any(|i| i.path == item.path) =>
+                        {
+                            Some(item.path.clone())
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                for path in stale {
+                    self.file_deleted(path);
                 }
-
-                if was_current || self.grid_mode {
-                    self.reset_view_for_new_image();
-                    if let Some(w) = &self.window {
-                        w.request_redraw();
-                    }
+                for item in items {
+                    self.file_changed(item);
                 }
             }
             AppEvent::HandlerFinished => {
@@ -1353,9 +2115,45 @@ impl ApplicationHandler<AppEvent> for App {
                     w.request_redraw();
                 }
             }
+            AppEvent::ConfigChanged => {
+                match crate::config::AppConfig::reload() {
+                    Ok(()) => self.apply_config(),
+                    Err(e) => {
+                        crate::rsiv_err!("{}", e);
+                        self.status_message = Some(e);
+                    }
+                }
+                if let Some(w) = &self.window {
+                    w.request_redraw();
+                }
+            }
+            AppEvent::SaveFinished(path, result) => {
+                self.finish_save(path, result);
+                if let Some(w) = &self.window {
+                    w.request_redraw();
+                }
+            }
+            AppEvent::CropExported(path, result) => {
+                self.finish_crop_export(path, result);
+                if let Some(w) = &self.window {
+                    w.request_redraw();
+                }
+            }
+            AppEvent::FileOpFinished(result, errors) => {
+                self.finish_file_op(result, errors);
+                if let Some(w) = &self.window {
+                    w.request_redraw();
+                }
+            }
+            #[cfg(unix)]
+            AppEvent::Remote(command, reply) => {
+                let response = self.handle_remote(_el, command);
+                let _ = reply.send(response);
+            }
         }
     }
 
+    #[allow(clippy::collapsible_match)]
     fn window_event(&mut self, _el: &ActiveEventLoop, _id: WindowId, event: WindowEvent) {
         match event {
             WindowEvent::CloseRequested => _el.exit(),
@@ -1371,10 +2169,64 @@ impl ApplicationHandler<AppEvent> for App {
                     }
                 }
                 self.clamp_offsets();
+                self.last_interaction = Instant::now();
                 if let Some(w) = &self.window {
                     w.request_redraw();
                 }
             }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_pos = Some((position.x, position.y));
+                if self.drag_divider(position.x) {
+                    if let Some(w) = &self.window {
+                        w.request_redraw();
+                    }
+                } else if let Some((start_x, start_y, start_off_x, start_off_y)) = self.drag_origin
+                {
+                    self.off_x = start_off_x + (position.x - start_x) as i32;
+                    self.off_y = start_off_y + (position.y - start_y) as i32;
+                    self.clamp_offsets();
+                    self.last_interaction = Instant::now();
+                    if let Some(w) = &self.window {
+                        w.request_redraw();
+                    }
+                }
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor_pos = None;
+                self.drag_origin = None;
+                if let Some(compare) = &mut self.compare {
+                    compare.dragging = false;
+                }
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                if state == ElementState::Released {
+                    if button == MouseButton::Left {
+                        self.drag_origin = None;
+                        if let Some(compare) = &mut self.compare {
+                            compare.dragging = false;
+                        }
+                    }
+                    return;
+                }
+                if self.input_mode != InputMode::Normal || self.images.is_empty() {
+                    return;
+                }
+                if self.handle_mouse_button(_el, button) {
+                    if let Some(w) = &self.window {
+                        w.request_redraw();
+                    }
+                }
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                if self.input_mode != InputMode::Normal || self.images.is_empty() {
+                    return;
+                }
+                if self.handle_mouse_wheel(_el, delta) {
+                    if let Some(w) = &self.window {
+                        w.request_redraw();
+                    }
+                }
+            }
             WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
                 self.status_bar.set_scale(scale_factor as f32);
                 if let Some(w) = &self.window {
@@ -1418,11 +2270,23 @@ impl ApplicationHandler<AppEvent> for App {
                                 self.input_mode = InputMode::Normal;
                                 needs_redraw = true;
                             }
-                            InputMode::WaitingForHandler | InputMode::AwaitingTarget(_) => {
+                            InputMode::WaitingForHandler
+                            | InputMode::AwaitingTarget(_)
+                            | InputMode::ConfirmSave { .. }
+                            | InputMode::EnteringPath { .. } => {
                                 self.input_mode = InputMode::Normal;
                                 needs_redraw = true;
                             }
-                            InputMode::Normal => {}
+                            InputMode::Normal => {
+                                if self.crop.take().is_some()
+                                    || self.status_message.take().is_some()
+                                {
+                                    needs_redraw = true;
+                                } else if self.picker && !needs_redraw {
+                                    // Nothing else to cancel, so cancel the pick
+                                    _el.exit();
+                                }
+                            }
                         }
 
                         if needs_redraw {
@@ -1435,7 +2299,9 @@ impl ApplicationHandler<AppEvent> for App {
 
                     // Modal inputs (Handler, Target, Filtering)
                     match self.input_mode {
-                        InputMode::WaitingForHandler | InputMode::AwaitingTarget(_) => {
+                        InputMode::WaitingForHandler
+                        | InputMode::AwaitingTarget(_)
+                        | InputMode::ConfirmSave { .. } => {
                             if let Key::Character(c) = &event.logical_key {
                                 self.handle_modal_input(c.as_str());
                                 if let Some(w) = &self.window {
@@ -1475,59 +2341,34 @@ impl ApplicationHandler<AppEvent> for App {
                             }
                             return;
                         }
+                        InputMode::EnteringPath { .. } => {
+                            if self.handle_path_input(&event.logical_key) {
+                                if let Some(w) = &self.window {
+                                    w.request_redraw();
+                                }
+                            }
+                            return;
+                        }
                         InputMode::Normal => {}
                     }
 
+                    // Picking takes Enter over from its binding
+                    if self.picker && event.logical_key == Key::Named(NamedKey::Enter) {
+                        if !self.images.is_empty() {
+                            self.picked = true;
+                            _el.exit();
+                        }
+                        return;
+                    }
+
                     // Standard keybindings
-                    let old_scale = self.get_current_scale();
                     if let Some(action) = crate::keybinds::Binding::resolve(
                         &event,
                         &self.bindings,
                         self.modifiers,
                         self.grid_mode,
                     ) {
-                        match action {
-                            Action::Quit => _el.exit(),
-                            Action::FilterMode => {
-                                self.input_mode = InputMode::Filtering;
-                                needs_redraw = true;
-                            }
-                            Action::ScriptHandlerPrefix => {
-                                self.input_mode = InputMode::WaitingForHandler;
-                                needs_redraw = true;
-                            }
-                            Action::Digit(d) => {
-                                if d == 0 && self.prefix_count.is_none() {
-                                    // Treat '0' as FirstImage (g) if no prefix count
-                                    self.handle_navigation_action(Action::FirstImage, 1);
-                                    needs_redraw = true;
-                                } else {
-                                    let current = self.prefix_count.unwrap_or(0);
-                                    let new_count = current.saturating_mul(10).saturating_add(d);
-                                    self.prefix_count = Some(new_count);
-                                    needs_redraw = true;
-                                }
-                            }
-                            other_action => {
-                                // Capture raw prefix for toggles
-                                let raw_prefix = self.prefix_count;
-                                let count = self.pop_count();
-
-                                if self.handle_navigation_action(other_action, count)
-                                    || self.handle_grid_movement_action(other_action, count)
-                                    || self.handle_image_ops_action(other_action, count)
-                                    || self.handle_view_action(other_action, old_scale)
-                                    || self.handle_toggle_action(other_action, raw_prefix)
-                                {
-                                    needs_redraw = true;
-                                }
-                                if matches!(other_action, Action::RemoveImage)
-                                    && self.all_images.is_empty()
-                                {
-                                    _el.exit();
-                                }
-                            }
-                        }
+                        needs_redraw = self.run_action(_el, action);
                     }
 
                     if needs_redraw {
@@ -0,0 +1,138 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+/// Joins an archive and one of its entries into a virtual path, as in
+/// `comic.cbz#page01.jpg`.
+const SEPARATOR: char = '#';
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Zip,
+    Tar,
+}
+
+fn kind(path: &Path) -> Option<Kind> {
+    let ext = path.extension()?.to_str()?.to_lowercase();
+    match ext.as_str() {
+        "zip" | "cbz" => Some(Kind::Zip),
+        "tar" | "cbt" => Some(Kind::Tar),
+        _ => None,
+    }
+}
+
+pub fn is_archive(path: &Path) -> bool {
+    kind(path).is_some()
+}
+
+fn open_zip(archive: &Path) -> Result<ZipArchive<File>, String> {
+    let file = File::open(archive).map_err(|e| e.to_string())?;
+    ZipArchive::new(file).map_err(|e| e.to_string())
+}
+
+fn open_tar(archive: &Path) -> Result<tar::Archive<File>, String> {
+    let file = File::open(archive).map_err(|e| e.to_string())?;
+    Ok(tar::Archive::new(file))
+}
+
+/// Virtual paths of the files in an archive, in the order they are stored.
+pub fn entries(archive: &Path) -> Result<Vec<PathBuf>, String> {
+    let names: Vec<String> = match kind(archive).ok_or("Not an archive")? {
+        Kind::Zip => {
+            let mut zip = open_zip(archive)?;
+            (0..zip.len())
+                .filter_map(|i| {
+                    let file = zip.by_index_raw(i).ok()?;
+                    (!file.is_dir()).then(|| file.name().to_string())
+                })
+                .collect()
+        }
+        Kind::Tar => {
+            let mut tar = open_tar(archive)?;
+            tar.entries_with_seek()
+                .map_err(|e| e.to_string())?
+                .filter_map(|e| {
+                    let e = e.ok()?;
+                    if !e.header().entry_type().is_file() {
+                        return None;
+                    }
+                    Some(e.path().ok()?.to_str()?.to_string())
+                })
+                .collect()
+        }
+    };
+
+    Ok(names
+        .into_iter()
+        .map(|name| {
+            let mut path = OsString::from(archive);
+            path.push(SEPARATOR.to_string());
+            path.push(name);
+            PathBuf::from(path)
+        })
+        .collect())
+}
+
+/// The archive and entry name of a virtual path, `None` for ordinary files.
+pub fn split(path: &Path) -> Option<(PathBuf, String)> {
+    let s = path.to_str()?;
+    s.match_indices(SEPARATOR).find_map(|(i, _)| {
+        let archive = Path::new(&s[..i]);
+        (is_archive(archive) && archive.is_file())
+            .then(|| (archive.to_path_buf(), s[i + 1..].to_string()))
+    })
+}
+
+fn read_entry(reader: impl Read, limit: u64) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    reader
+        .take(limit)
+        .read_to_end(&mut data)
+        .map_err(|e| e.to_string())?;
+    Ok(data)
+}
+
+/// The first `limit` bytes of an entry, decompressed in memory.
+pub fn read_head(archive: &Path, entry: &str, limit: u64) -> Result<Vec<u8>, String> {
+    read_head_sized(archive, entry, limit).map(|(data, _)| data)
+}
+
+/// The first `limit` bytes of an entry and its whole decompressed size.
+pub fn read_head_sized(archive: &Path, entry: &str, limit: u64) -> Result<(Vec<u8>, u64), String> {
+    match kind(archive).ok_or("Not an archive")? {
+        Kind::Zip => {
+            let mut zip = open_zip(archive)?;
+            let file = zip.by_name(entry).map_err(|e| e.to_string())?;
+            let size = file.size();
+            Ok((read_entry(file, limit)?, size))
+        }
+        Kind::Tar => {
+            let mut tar = open_tar(archive)?;
+            for e in tar.entries_with_seek().map_err(|e| e.to_string())? {
+                let e = e.map_err(|e| e.to_string())?;
+                if e.path().is_ok_and(|p| p == Path::new(entry)) {
+                    let size = e.size();
+                    return Ok((read_entry(e, limit)?, size));
+                }
+            }
+            Err(format!("{} is not in {}", entry, archive.display()))
+        }
+    }
+}
+
+/// Whether `path` is an entry of `archive`. Unlike `split`, this still
+/// works once the archive is gone.
+pub fn is_entry_of(path: &Path, archive: &Path) -> bool {
+    let mut prefix = OsString::from(archive);
+    prefix.push(SEPARATOR.to_string());
+    path.as_os_str()
+        .as_encoded_bytes()
+        .starts_with(prefix.as_encoded_bytes())
+}
+
+/// A whole entry, decompressed in memory.
+pub fn read(archive: &Path, entry: &str) -> Result<Vec<u8>, String> {
+    read_head(archive, entry, u64::MAX)
+}
@@ -1,12 +1,14 @@
 use crate::image_item::LoadedImage;
+use crate::tiles::{Tile, TileKey};
 use moka::sync::Cache;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use sysinfo::System;
 
 pub struct CacheManager {
     pub image_cache: Cache<PathBuf, Arc<LoadedImage>>,
     pub thumb_cache: Cache<PathBuf, Arc<(u32, u32, Vec<u8>)>>,
+    pub tile_cache: Cache<(PathBuf, TileKey), Arc<Tile>>,
     image_limit_kb: u64,
     oversized_images: Mutex<Vec<(PathBuf, Arc<LoadedImage>)>>,
 }
@@ -18,7 +20,8 @@ impl CacheManager {
         let total_ram_kb = sys.total_memory() / 1024;
         let limit_kb = ((total_ram_kb as f64) * (max_memory_percent / 100.0)) as u64;
 
-        let image_limit_kb = (limit_kb as f64 * 0.8).max(1024.0) as u64;
+        let image_limit_kb = (limit_kb as f64 * 0.7).max(1024.0) as u64;
+        let tile_limit_kb = (limit_kb as f64 * 0.1).max(1024.0) as u64;
         let thumb_limit_kb = (limit_kb as f64 * 0.2).max(1024.0) as u64;
 
         Self {
@@ -34,6 +37,14 @@ impl CacheManager {
                     ((value.2.len() / 1024) as u32).max(1)
                 })
                 .build(),
+            tile_cache: Cache::builder()
+                .max_capacity(tile_limit_kb)
+                .time_to_idle(std::time::Duration::from_secs(5 * 60))
+                .weigher(|_key, value: &Arc<Tile>| -> u32 {
+                    ((value.pixels.len() / 1024) as u32).max(1)
+                })
+                .support_invalidation_closures()
+                .build(),
             image_limit_kb,
             oversized_images: Mutex::new(Vec::with_capacity(3)),
         }
@@ -76,11 +87,24 @@ impl CacheManager {
         self.thumb_cache.insert(path, thumb);
     }
 
+    pub fn get_tile(&self, path: &Path, key: TileKey) -> Option<Arc<Tile>> {
+        self.tile_cache.get(&(path.to_path_buf(), key))
+    }
+
+    pub fn insert_tile(&self, path: PathBuf, key: TileKey, tile: Arc<Tile>) {
+        self.tile_cache.insert((path, key), tile);
+    }
+
     pub fn remove(&self, path: &PathBuf) {
         if let Ok(mut oversized) = self.oversized_images.lock() {
             oversized.retain(|(p, _)| p != path);
         }
         self.image_cache.invalidate(path);
         self.thumb_cache.invalidate(path);
+
+        let path = path.clone();
+        let _ = self
+            .tile_cache
+            .invalidate_entries_if(move |(p, _), _| *p == path);
     }
 }
@@ -0,0 +1,264 @@
+use crate::app::App;
+use crate::image_item::{ImageItem, ImageSlot};
+use crate::keybinds::Action;
+use crate::renderer::{DrawImageParams, Viewport};
+use crate::tiles::TileKey;
+use serde::Deserialize;
+use std::fmt;
+
+const DIVIDER_STEP: f64 = 0.05;
+const DIVIDER_WIDTH: i32 = 2;
+// How close to the wipe divider a press has to be to grab it
+const DIVIDER_GRAB: f64 = 8.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum CompareLayout {
+    SideBySide,
+    Wipe,
+    Difference,
+}
+
+impl CompareLayout {
+    pub fn next(self) -> Self {
+        match self {
+            CompareLayout::SideBySide => CompareLayout::Wipe,
+            CompareLayout::Wipe => CompareLayout::Difference,
+            CompareLayout::Difference => CompareLayout::SideBySide,
+        }
+    }
+}
+
+impl fmt::Display for CompareLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CompareLayout::SideBySide => "split",
+            CompareLayout::Wipe => "wipe",
+            CompareLayout::Difference => "diff",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The current image shown together with `other`, picked when compare mode
+/// was turned on. Both share the zoom and pan of the current image.
+pub struct Compare {
+    pub other: ImageItem,
+    pub layout: CompareLayout,
+    pub divider: f64, // Wipe position, as a fraction of the width
+    pub dragging: bool,
+    scratch: Vec<u8>, // The other image, drawn apart for the difference blend
+}
+
+impl Compare {
+    pub fn new(other: ImageItem) -> Self {
+        Self {
+            other,
+            layout: crate::config::AppConfig::get().options.compare_layout,
+            divider: 0.5,
+            dragging: false,
+            scratch: Vec::new(),
+        }
+    }
+
+    /// Left and right halves of `area`, for the side by side layout.
+    pub fn panes(area: Viewport) -> (Viewport, Viewport) {
+        let left_w = area.w / 2;
+        (
+            Viewport::new(area.x, area.y, left_w, area.h),
+            Viewport::new(area.x + left_w, area.y, area.w - left_w, area.h),
+        )
+    }
+
+    fn divider_x(&self, area: Viewport) -> i32 {
+        area.x + (area.w as f64 * self.divider).round() as i32
+    }
+
+    /// Draws both images in the viewport of `current`. The other one is left out
+    /// until loaded. Returns the missing tiles of the current and the other image.
+    pub fn draw(
+        &mut self,
+        frame: &mut [u8],
+        buf_w: i32,
+        mut current: DrawImageParams,
+        other: Option<DrawImageParams>,
+        bg_color: (u8, u8, u8),
+        divider_color: (u8, u8, u8),
+    ) -> (Vec<TileKey>, Vec<TileKey>) {
+        use crate::renderer::{clear, difference, draw_image, fill_rect};
+
+        let area = current.viewport;
+        match self.layout {
+            CompareLayout::SideBySide => {
+                let (left, right) = Self::panes(area);
+                current.viewport = left;
+                let missing = draw_image(frame, buf_w, &current);
+                let other_missing = other
+                    .map(|mut other| {
+                        other.viewport = right;
+                        draw_image(frame, buf_w, &other)
+                    })
+                    .unwrap_or_default();
+
+                let line =
+                    Viewport::new(right.x - DIVIDER_WIDTH / 2, area.y, DIVIDER_WIDTH, area.h);
+                fill_rect(frame, buf_w, line, divider_color);
+                (missing, other_missing)
+            }
+            CompareLayout::Wipe => {
+                let x = self.divider_x(area);
+                current.clip = Some(Viewport::new(area.x, area.y, x - area.x, area.h));
+                let missing = draw_image(frame, buf_w, &current);
+                let other_missing = other
+                    .map(|mut other| {
+                        other.clip = Some(Viewport::new(x, area.y, area.x + area.w - x, area.h));
+                        draw_image(frame, buf_w, &other)
+                    })
+                    .unwrap_or_default();
+
+                let line = Viewport::new(x - DIVIDER_WIDTH / 2, area.y, DIVIDER_WIDTH, area.h);
+                fill_rect(frame, buf_w, line, divider_color);
+                (missing, other_missing)
+            }
+            CompareLayout::Difference => {
+                let missing = draw_image(frame, buf_w, &current);
+                let Some(other) = other else {
+                    return (missing, Vec::new());
+                };
+
+                self.scratch.resize(frame.len(), 0);
+                clear(&mut self.scratch, bg_color);
+                let other_missing = draw_image(&mut self.scratch, buf_w, &other);
+                difference(frame, &self.scratch, buf_w, area);
+                (missing, other_missing)
+            }
+        }
+    }
+}
+
+/// Status bar summary, e.g. "wipe IMG_0002.jpg".
+impl fmt::Display for Compare {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = self
+            .other
+            .path
+            .file_name()
+            .unwrap_or(self.other.path.as_os_str());
+        write!(f, "{} {}", self.layout, name.to_string_lossy())
+    }
+}
+
+impl App {
+    pub fn is_side_by_side(&self) -> bool {
+        !self.grid_mode && matches!(&self.compare, Some(c) if c.layout == CompareLayout::SideBySide)
+    }
+
+    /// The image to compare the current one with: the first marked one, or else the next.
+    fn compare_target(&self) -> Option<ImageItem> {
+        let current = self.current_path();
+        let other = |slot: &ImageSlot| match slot {
+            ImageSlot::MetadataLoaded(item) if Some(&item.path) != current.as_ref() => {
+                Some(item.clone())
+            }
+            _ => None,
+        };
+
+        self.all_images
+            .iter()
+            .filter(|slot| {
+                matches!(slot, ImageSlot::MetadataLoaded(item)
+                    if self.marked_files.contains(&item.path.to_string_lossy().to_string()))
+            })
+            .find_map(other)
+            .or_else(|| self.images.get(self.current_index + 1).and_then(other))
+            .or_else(|| {
+                let prev = self.current_index.checked_sub(1)?;
+                self.images.get(prev).and_then(other)
+            })
+    }
+
+    pub fn handle_compare_action(&mut self, action: Action, count: usize) -> bool {
+        match action {
+            Action::ToggleCompare => {
+                self.crop = None;
+                if self.compare.take().is_none() {
+                    match self.compare_target() {
+                        Some(other) => self.compare = Some(Compare::new(other)),
+                        None => self.status_message = Some("Nothing to compare with".into()),
+                    }
+                }
+            }
+            Action::CompareNextLayout => {
+                let Some(compare) = &mut self.compare else {
+                    return false;
+                };
+                compare.layout = compare.layout.next();
+            }
+            Action::CompareSwap => {
+                let Some(ImageSlot::MetadataLoaded(current)) =
+                    self.images.get(self.current_index).cloned()
+                else {
+                    return false;
+                };
+                let Some(other) = self.compare.as_ref().map(|c| c.other.path.clone()) else {
+                    return false;
+                };
+                if !self.select_path(&other) {
+                    self.status_message = Some(format!("{:?} is not in the image list", other));
+                    return true;
+                }
+                if let Some(compare) = &mut self.compare {
+                    compare.other = current;
+                }
+                self.current_frame_index = 0;
+            }
+            Action::CompareDividerLeft | Action::CompareDividerRight => {
+                let Some(compare) = &mut self.compare else {
+                    return false;
+                };
+                let step = DIVIDER_STEP * count as f64;
+                let delta = if action == Action::CompareDividerLeft {
+                    -step
+                } else {
+                    step
+                };
+                compare.divider = (compare.divider + delta).clamp(0.0, 1.0);
+            }
+            _ => return false,
+        }
+
+        self.clamp_offsets();
+        true
+    }
+
+    /// Grabs the wipe divider when pressed close to it.
+    pub fn start_divider_drag(&mut self, cx: f64) -> bool {
+        let Some((buf_w, _)) = self.get_available_window_size() else {
+            return false;
+        };
+        let Some(compare) = &mut self.compare else {
+            return false;
+        };
+        if self.grid_mode
+            || compare.layout != CompareLayout::Wipe
+            || (cx - buf_w * compare.divider).abs() > DIVIDER_GRAB
+        {
+            return false;
+        }
+        compare.dragging = true;
+        true
+    }
+
+    /// Moves a grabbed divider to the cursor.
+    pub fn drag_divider(&mut self, x: f64) -> bool {
+        let Some((buf_w, _)) = self.get_available_window_size() else {
+            return false;
+        };
+        let Some(compare) = self.compare.as_mut().filter(|c| c.dragging) else {
+            return false;
+        };
+        if buf_w > 0.0 {
+            compare.divider = (x / buf_w).clamp(0.0, 1.0);
+        }
+        true
+    }
+}
@@ -1,12 +1,18 @@
+use crate::compare::CompareLayout;
+use crate::output::MarkOrder;
+use crate::resampling::Resampling;
+use crate::sorting::SortMode;
 use crate::view_mode::ViewMode;
 use serde::de::Deserializer;
 use serde::Deserialize;
 use std::env;
 use std::fs;
 use std::path::PathBuf;
-use std::sync::OnceLock;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
 
-static CONFIG: OnceLock<AppConfig> = OnceLock::new();
+static CONFIG: OnceLock<RwLock<Arc<AppConfig>>> = OnceLock::new();
+// Why the config read at startup was rejected, until shown to the user
+static LOAD_ERROR: Mutex<Option<String>> = Mutex::new(None);
 
 #[derive(Deserialize, Debug, Clone, Default)]
 #[serde(default)]
@@ -18,29 +24,67 @@ pub struct AppConfig {
 }
 
 impl AppConfig {
-    pub fn get() -> &'static AppConfig {
-        CONFIG.get_or_init(Self::load)
+    pub fn get() -> Arc<AppConfig> {
+        CONFIG
+            .get_or_init(|| RwLock::new(Arc::new(Self::load())))
+            .read()
+            .unwrap()
+            .clone()
     }
 
     fn load() -> Self {
-        let config_path = Self::find_config_path();
-
-        if let Some(path) = config_path {
-            if path.exists() {
-                match fs::read_to_string(&path) {
-                    Ok(contents) => match toml::from_str(&contents) {
-                        Ok(config) => return config,
-                        Err(e) => crate::rsiv_warn!("Failed to parse config at {:?}: {}", path, e),
-                    },
-                    Err(e) => crate::rsiv_warn!("Failed to read config at {:?}: {}", path, e),
-                }
+        match Self::read() {
+            Ok(config) => config,
+            Err(e) => {
+                crate::rsiv_warn!("{}", e);
+                *LOAD_ERROR.lock().unwrap() = Some(e);
+                Self::default()
             }
         }
+    }
+
+    /// Parses the config file, or gives the defaults when there is none.
+    fn read() -> Result<Self, String> {
+        let Some(path) = Self::find_config_path().filter(|p| p.exists()) else {
+            return Ok(Self::default());
+        };
+
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read config at {:?}: {}", path, e))?;
+        toml::from_str(&contents).map_err(|e: toml::de::Error| {
+            // Kept on one line, so it fits in the status bar
+            match e.span() {
+                Some(span) => format!(
+                    "Failed to parse config at {:?}, line {}: {}",
+                    path,
+                    contents[..span.start].matches('\n').count() + 1,
+                    e.message().trim()
+                ),
+                None => format!(
+                    "Failed to parse config at {:?}: {}",
+                    path,
+                    e.message().trim()
+                ),
+            }
+        })
+    }
+
+    /// Reads the config file again. On error the current config is kept.
+    pub fn reload() -> Result<(), String> {
+        let config = Self::read()?;
+        *CONFIG
+            .get_or_init(|| RwLock::new(Arc::new(Self::default())))
+            .write()
+            .unwrap() = Arc::new(config);
+        Ok(())
+    }
 
-        Self::default()
+    /// The error that made the startup config fall back to the defaults, once.
+    pub fn take_load_error() -> Option<String> {
+        LOAD_ERROR.lock().unwrap().take()
     }
 
-    fn find_config_path() -> Option<PathBuf> {
+    pub fn find_config_path() -> Option<PathBuf> {
         // Check XDG_CONFIG_HOME first
         if let Ok(xdg_config) = env::var("XDG_CONFIG_HOME") {
             let path = PathBuf::from(xdg_config).join("rsiv/config.toml");
@@ -102,6 +146,7 @@ pub struct Keybindings {
     pub quit: BindingList,
     pub image_flip_horizontal: BindingList,
     pub image_flip_vertical: BindingList,
+    pub save_image: BindingList,
     pub image_next: BindingList,
     pub image_previous: BindingList,
     pub rotate_cw: BindingList,
@@ -125,6 +170,8 @@ pub struct Keybindings {
     pub view_pan_bottom_edge: BindingList,
     pub grid_page_up: BindingList,
     pub grid_page_down: BindingList,
+    pub grid_scroll_up: BindingList,
+    pub grid_scroll_down: BindingList,
     pub toggle_status_bar: BindingList,
     pub toggle_animation: BindingList,
     pub toggle_slideshow: BindingList,
@@ -142,6 +189,35 @@ pub struct Keybindings {
     pub toggle_alpha: BindingList,
     pub next_frame: BindingList,
     pub prev_frame: BindingList,
+    pub next_page: BindingList,
+    pub prev_page: BindingList,
+    pub sort_next: BindingList,
+    pub sort_prev: BindingList,
+    pub sort_reverse: BindingList,
+    pub brightness_up: BindingList,
+    pub brightness_down: BindingList,
+    pub contrast_up: BindingList,
+    pub contrast_down: BindingList,
+    pub gamma_up: BindingList,
+    pub gamma_down: BindingList,
+    pub saturation_up: BindingList,
+    pub saturation_down: BindingList,
+    pub toggle_invert: BindingList,
+    pub toggle_grayscale: BindingList,
+    pub reset_adjustments: BindingList,
+    pub toggle_compare: BindingList,
+    pub compare_layout: BindingList,
+    pub compare_swap: BindingList,
+    pub compare_divider_left: BindingList,
+    pub compare_divider_right: BindingList,
+    pub toggle_crop: BindingList,
+    pub crop_aspect: BindingList,
+    pub crop_export: BindingList,
+    pub trash_files: BindingList,
+    pub undo_trash: BindingList,
+    pub move_files: BindingList,
+    pub copy_files: BindingList,
+    pub rename_file: BindingList,
 }
 
 impl Default for Keybindings {
@@ -150,12 +226,13 @@ impl Default for Keybindings {
             quit: vec!["q"].into(),
             image_flip_horizontal: vec!["_"].into(),
             image_flip_vertical: vec!["?"].into(),
-            image_next: vec!["n"].into(),
-            image_previous: vec!["p"].into(),
+            save_image: vec!["Ctrl+s"].into(),
+            image_next: vec!["n", "MouseForward"].into(),
+            image_previous: vec!["p", "MouseBack"].into(),
             rotate_cw: vec![">"].into(),
             rotate_ccw: vec!["<"].into(),
-            zoom_in: vec!["+"].into(),
-            zoom_out: vec!["-"].into(),
+            zoom_in: vec!["+", "ScrollUp"].into(),
+            zoom_out: vec!["-", "ScrollDown"].into(),
             zoom_reset: vec!["="].into(),
             fit_width: vec!["W"].into(),
             fit_height: vec!["V"].into(),
@@ -173,6 +250,8 @@ impl Default for Keybindings {
             view_pan_right_edge: vec!["L", "Shift+Right"].into(),
             grid_page_up: vec!["Ctrl+u"].into(),
             grid_page_down: vec!["Ctrl+d"].into(),
+            grid_scroll_up: vec!["ScrollUp"].into(),
+            grid_scroll_down: vec!["ScrollDown"].into(),
             toggle_status_bar: vec!["b"].into(),
             toggle_animation: vec!["Ctrl+a"].into(),
             toggle_slideshow: vec!["s"].into(),
@@ -190,6 +269,35 @@ impl Default for Keybindings {
             toggle_alpha: vec!["A"].into(),
             next_frame: vec!["."].into(),
             prev_frame: vec![","].into(),
+            next_page: vec!["Ctrl+n"].into(),
+            prev_page: vec!["Ctrl+p"].into(),
+            sort_next: vec!["]"].into(),
+            sort_prev: vec!["["].into(),
+            sort_reverse: vec!["R"].into(),
+            brightness_up: vec![")"].into(),
+            brightness_down: vec!["("].into(),
+            contrast_up: vec!["Alt+)"].into(),
+            contrast_down: vec!["Alt+("].into(),
+            gamma_up: vec!["}"].into(),
+            gamma_down: vec!["{"].into(),
+            saturation_up: vec!["Alt+}"].into(),
+            saturation_down: vec!["Alt+{"].into(),
+            toggle_invert: vec!["I"].into(),
+            toggle_grayscale: vec!["Y"].into(),
+            reset_adjustments: vec!["Ctrl+g"].into(),
+            toggle_compare: vec!["c"].into(),
+            compare_layout: vec!["x"].into(),
+            compare_swap: vec!["X"].into(),
+            compare_divider_left: vec!["Ctrl+h", "Ctrl+Left"].into(),
+            compare_divider_right: vec!["Ctrl+l", "Ctrl+Right"].into(),
+            toggle_crop: vec!["e"].into(),
+            crop_aspect: vec!["a"].into(),
+            crop_export: vec!["E"].into(),
+            trash_files: vec!["Delete"].into(),
+            undo_trash: vec!["Ctrl+z"].into(),
+            move_files: vec!["Ctrl+m"].into(),
+            copy_files: vec!["Ctrl+y"].into(),
+            rename_file: vec!["r"].into(),
         }
     }
 }
@@ -212,6 +320,8 @@ pub struct Ui {
     pub mark_color: String,
     pub loading_color: String,
     pub error_color: String,
+    pub divider_color: String,
+    pub crop_color: String,
     pub status_format_left: String,
     pub status_format_right: String,
 }
@@ -234,8 +344,10 @@ impl Default for Ui {
             mark_color: "#FF0000".into(),
             loading_color: "#3c3c3c".into(),
             error_color: "#FF0000".into(),
+            divider_color: "#FFFFFF".into(),
+            crop_color: "#FFFFFF".into(),
             status_format_left: "%p".into(),
-            status_format_right: "%P %s %f %m %z %i".into(),
+            status_format_right: "%P %s %f %g %c %m %z %i".into(),
         }
     }
 }
@@ -258,6 +370,18 @@ pub struct Options {
     pub preload_ahead: usize,
     pub preload_behind: usize,
     pub slideshow_default_delay: u64,
+    pub resampling: Resampling,
+    pub tile_threshold: u64,
+    pub thumbnail_cache: bool,
+    pub sort: SortMode,
+    pub sort_reverse: bool,
+    pub expand_single_file: bool,
+    pub ipc: bool,
+    pub adjust_per_image: bool,
+    pub compare_layout: CompareLayout,
+    pub jpeg_quality: u8,
+    pub raw_demosaic: bool,
+    pub mark_order: MarkOrder,
 }
 
 impl Default for Options {
@@ -278,6 +402,18 @@ impl Default for Options {
             preload_ahead: 1,
             preload_behind: 1,
             slideshow_default_delay: 5,
+            resampling: Resampling::Bilinear,
+            tile_threshold: 256,
+            thumbnail_cache: true,
+            sort: SortMode::Name,
+            sort_reverse: false,
+            expand_single_file: false,
+            ipc: false,
+            adjust_per_image: false,
+            compare_layout: CompareLayout::SideBySide,
+            jpeg_quality: 90,
+            raw_demosaic: false,
+            mark_order: MarkOrder::List,
         }
     }
 }
@@ -0,0 +1,357 @@
+use crate::app::{App, AppEvent};
+use crate::image_item::{ImageFormat, ImageSlot, LoadedImage, Transform};
+use crate::keybinds::Action;
+use crate::renderer::Viewport;
+use image::{DynamicImage, ImageDecoder, ImageReader, RgbaImage};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+// Share of the image the selection starts with
+const INITIAL_SIZE: f64 = 0.8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CropAspect {
+    Free,
+    Square,
+    Wide,
+    Original,
+}
+
+impl CropAspect {
+    pub fn next(self) -> Self {
+        match self {
+            CropAspect::Free => CropAspect::Square,
+            CropAspect::Square => CropAspect::Wide,
+            CropAspect::Wide => CropAspect::Original,
+            CropAspect::Original => CropAspect::Free,
+        }
+    }
+
+    /// Width over height, `None` when the selection is free.
+    fn ratio(self, (img_w, img_h): (u32, u32)) -> Option<f64> {
+        match self {
+            CropAspect::Free => None,
+            CropAspect::Square => Some(1.0),
+            CropAspect::Wide => Some(16.0 / 9.0),
+            CropAspect::Original => Some(img_w as f64 / img_h.max(1) as f64),
+        }
+    }
+}
+
+/// "4:3", or "2.35:1" when the reduced terms get large.
+fn ratio_text(w: u32, h: u32) -> String {
+    fn gcd(a: u32, b: u32) -> u32 {
+        if b == 0 {
+            a
+        } else {
+            gcd(b, a % b)
+        }
+    }
+    let d = gcd(w, h).max(1);
+    if w / d <= 32 && h / d <= 32 {
+        format!("{}:{}", w / d, h / d)
+    } else {
+        format!("{:.2}:1", w as f64 / h.max(1) as f64)
+    }
+}
+
+/// A rectangle selected on the current image, in the pixels of the image as
+/// displayed (after the rotations and flips made in rsiv).
+pub struct Crop {
+    pub path: PathBuf,
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+    pub aspect: CropAspect,
+    bounds: (u32, u32), // Size of the image
+}
+
+impl Crop {
+    pub fn new(path: PathBuf, img_w: u32, img_h: u32) -> Self {
+        let w = ((img_w as f64 * INITIAL_SIZE).round() as u32).max(1);
+        let h = ((img_h as f64 * INITIAL_SIZE).round() as u32).max(1);
+        Self {
+            path,
+            x: (img_w - w.min(img_w)) / 2,
+            y: (img_h - h.min(img_h)) / 2,
+            w,
+            h,
+            aspect: CropAspect::Free,
+            bounds: (img_w, img_h),
+        }
+    }
+
+    /// Keeps the selection inside an image of the given size.
+    pub fn fit(&mut self, img_w: u32, img_h: u32) {
+        self.bounds = (img_w.max(1), img_h.max(1));
+        self.w = self.w.clamp(1, self.bounds.0);
+        self.h = self.h.clamp(1, self.bounds.1);
+        self.x = self.x.min(self.bounds.0 - self.w);
+        self.y = self.y.min(self.bounds.1 - self.h);
+    }
+
+    fn move_by(&mut self, dx: i64, dy: i64) {
+        self.x = (self.x as i64 + dx).clamp(0, (self.bounds.0 - self.w) as i64) as u32;
+        self.y = (self.y as i64 + dy).clamp(0, (self.bounds.1 - self.h) as i64) as u32;
+    }
+
+    /// Moves the right or bottom edge. A locked aspect ratio drags the other one along.
+    fn resize(&mut self, dw: i64, dh: i64) {
+        let mut w = (self.w as i64 + dw).max(1) as f64;
+        let mut h = (self.h as i64 + dh).max(1) as f64;
+        if let Some(ratio) = self.aspect.ratio(self.bounds) {
+            if dw != 0 {
+                h = w / ratio;
+            } else {
+                w = h * ratio;
+            }
+        }
+        self.set_size(w, h);
+    }
+
+    /// Applies the aspect ratio around the center of the selection.
+    fn apply_aspect(&mut self) {
+        let Some(ratio) = self.aspect.ratio(self.bounds) else {
+            return;
+        };
+        let (cx, cy) = (
+            self.x as f64 + self.w as f64 / 2.0,
+            self.y as f64 + self.h as f64 / 2.0,
+        );
+        // Same area, new shape
+        let area = self.w as f64 * self.h as f64;
+        self.set_size((area * ratio).sqrt(), (area / ratio).sqrt());
+        self.x = (cx - self.w as f64 / 2.0).max(0.0).round() as u32;
+        self.y = (cy - self.h as f64 / 2.0).max(0.0).round() as u32;
+        self.fit(self.bounds.0, self.bounds.1);
+    }
+
+    /// Shrinks (w, h) to fit the image, keeping its shape.
+    fn set_size(&mut self, w: f64, h: f64) {
+        let (img_w, img_h) = (self.bounds.0 as f64, self.bounds.1 as f64);
+        let shrink = (img_w / w).min(img_h / h).min(1.0);
+        self.w = ((w * shrink).round() as u32).max(1);
+        self.h = ((h * shrink).round() as u32).max(1);
+        self.fit(self.bounds.0, self.bounds.1);
+    }
+
+    /// The selection on screen, for an image drawn at `scale` from `top_left`.
+    pub fn on_screen(&self, (tl_x, tl_y): (f64, f64), scale: f64) -> Viewport {
+        let left = (tl_x + self.x as f64 * scale).round() as i32;
+        let top = (tl_y + self.y as f64 * scale).round() as i32;
+        let right = (tl_x + (self.x + self.w) as f64 * scale).round() as i32;
+        let bottom = (tl_y + (self.y + self.h) as f64 * scale).round() as i32;
+        Viewport::new(left, top, (right - left).max(1), (bottom - top).max(1))
+    }
+
+    /// Dims the image area around the selection and outlines it.
+    /// Returns the selection on screen.
+    pub fn draw(
+        &self,
+        frame: &mut [u8],
+        buf_w: i32,
+        area: Viewport,
+        top_left: (f64, f64),
+        scale: f64,
+        color: (u8, u8, u8),
+    ) -> Viewport {
+        let rect = self.on_screen(top_left, scale);
+        crate::renderer::dim_outside(frame, buf_w, area, rect);
+        crate::renderer::outline_rect(frame, buf_w, rect, color);
+        rect
+    }
+}
+
+/// Overlay label, e.g. "640x480 4:3", or "640x360 16:9 lock".
+impl fmt::Display for Crop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{} ", self.w, self.h)?;
+        match self.aspect {
+            CropAspect::Free => f.write_str(&ratio_text(self.w, self.h)),
+            CropAspect::Square => f.write_str("1:1 lock"),
+            CropAspect::Wide => f.write_str("16:9 lock"),
+            CropAspect::Original => {
+                write!(f, "{} lock", ratio_text(self.bounds.0, self.bounds.1))
+            }
+        }
+    }
+}
+
+/// Writes the selected region of a frame to a new file, in the format of the
+/// original when it can be written, or else as PNG. Runs off the UI thread.
+fn export(
+    path: &Path,
+    image: &LoadedImage,
+    frame_idx: usize,
+    (x, y, w, h): (u32, u32, u32, u32),
+) -> Result<PathBuf, String> {
+    let frame = image
+        .frames
+        .get(frame_idx)
+        .ok_or("The image has no pixels")?;
+    let pixels = RgbaImage::from_raw(image.width, image.height, frame.pixels.clone())
+        .ok_or("Unexpected frame size")?;
+    let cropped =
+        DynamicImage::ImageRgba8(image::imageops::crop_imm(&pixels, x, y, w, h).to_image());
+
+    let (format, extension) = match image::ImageFormat::from_path(path) {
+        Ok(format) if format.writing_enabled() => (
+            format,
+            path.extension()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+        ),
+        _ => (image::ImageFormat::Png, "png".to_string()),
+    };
+    // The colors stay as they were, the orientation does not carry over
+    let icc = ImageReader::open(path)
+        .ok()
+        .and_then(|r| r.with_guessed_format().ok())
+        .and_then(|r| r.into_decoder().ok())
+        .and_then(|mut d| d.icc_profile().ok().flatten());
+    let data = crate::save::encode(&cropped, format, None, icc)?;
+
+    // Crops of virtual images go where they would be as files
+    let path = crate::virtual_path::beside(path);
+    crate::save::write_unique(
+        path.parent().unwrap_or(Path::new("")),
+        &path.file_stem().unwrap_or_default().to_string_lossy(),
+        "-crop",
+        &extension,
+        &data,
+    )
+}
+
+impl App {
+    /// Ends the selection once another image is shown, and keeps it inside
+    /// the current one after a rotation.
+    pub fn sync_crop(&mut self) {
+        let Some(crop) = &mut self.crop else {
+            return;
+        };
+        match self.images.get(self.current_index) {
+            Some(ImageSlot::MetadataLoaded(item)) if item.path == crop.path && !self.grid_mode => {
+                crop.fit(item.width, item.height);
+            }
+            _ => self.crop = None,
+        }
+    }
+
+    /// Crop mode actions. While selecting, the pan keys move the selection and
+    /// the pan-to-edge keys move its right and bottom edges.
+    pub fn handle_crop_action(&mut self, action: Action, count: usize) -> bool {
+        self.sync_crop();
+        if action == Action::ToggleCrop {
+            if self.crop.take().is_none() {
+                let Some(ImageSlot::MetadataLoaded(item)) = self.images.get(self.current_index)
+                else {
+                    return false;
+                };
+                self.crop = Some(Crop::new(item.path.clone(), item.width, item.height));
+                self.compare = None;
+            }
+            return true;
+        }
+        if action == Action::CropExport {
+            return self.export_crop();
+        }
+
+        let scale = self.get_current_scale();
+        let Some(crop) = &mut self.crop else {
+            return false;
+        };
+        // The pan step is in screen pixels
+        let pan_step = crate::config::AppConfig::get().options.pan_step as f64;
+        let step = ((pan_step / scale).round().max(1.0) as i64) * count as i64;
+
+        match action {
+            Action::CropAspect => {
+                crop.aspect = crop.aspect.next();
+                crop.apply_aspect();
+            }
+            Action::PanLeft => crop.move_by(-step, 0),
+            Action::PanRight => crop.move_by(step, 0),
+            Action::PanUp => crop.move_by(0, -step),
+            Action::PanDown => crop.move_by(0, step),
+            Action::PanToLeftEdge => crop.resize(-step, 0),
+            Action::PanToRightEdge => crop.resize(step, 0),
+            Action::PanToTopEdge => crop.resize(0, -step),
+            Action::PanToBottomEdge => crop.resize(0, step),
+            _ => return false,
+        }
+        true
+    }
+
+    /// The geometry handlers get as `%c` for `path`, e.g. "640x480+10+20": the
+    /// selection when it was made on that image, or else the whole image. It is
+    /// in the pixels stored in the file, the rotations and flips made in rsiv
+    /// and the EXIF orientation are undone.
+    pub fn crop_geometry(&self, path: &Path) -> String {
+        let Some(item) = self.all_images.iter().find_map(|slot| match slot {
+            ImageSlot::MetadataLoaded(item) if item.path == path => Some(item),
+            _ => None,
+        }) else {
+            return String::new();
+        };
+        let size = (item.width, item.height);
+        let rect = match self.crop.as_ref().filter(|crop| crop.path == path) {
+            Some(crop) => (crop.x, crop.y, crop.w, crop.h),
+            None => (0, 0, item.width, item.height),
+        };
+
+        // Only the decoders of common formats turn images by their EXIF orientation
+        let file_orientation = match item.format {
+            ImageFormat::Raster => crate::loader::file_orientation(&item.path),
+            _ => Transform::default(),
+        };
+        let transform = self
+            .cache
+            .get_image(&item.path)
+            .map(|image| image.transform)
+            .unwrap_or_default();
+        let (x, y, w, h) = file_orientation
+            .then(transform)
+            .inverse()
+            .map_rect(rect, size);
+        format!("{}x{}+{}+{}", w, h, x, y)
+    }
+
+    fn export_crop(&mut self) -> bool {
+        let Some(crop) = &self.crop else {
+            return false;
+        };
+        let Some(image) = self.cache.get_image(&crop.path) else {
+            return false;
+        };
+        if image.tiled.is_some() {
+            self.status_message =
+                Some("Tiled images can't be exported, use a handler with %c instead".into());
+            return true;
+        }
+
+        let path = crop.path.clone();
+        let rect = (crop.x, crop.y, crop.w, crop.h);
+        let frame_idx = self.current_frame_index % image.frames.len().max(1);
+        let proxy = self.proxy.clone();
+        std::thread::spawn(move || {
+            let result = export(&path, &image, frame_idx, rect);
+            let _ = proxy.send_event(AppEvent::CropExported(path, result));
+        });
+        true
+    }
+
+    pub fn finish_crop_export(&mut self, path: PathBuf, result: Result<PathBuf, String>) {
+        match result {
+            Ok(dest) => {
+                let name = dest.file_name().unwrap_or_default().to_string_lossy();
+                self.status_message = Some(format!("Exported {}", name));
+            }
+            Err(e) => {
+                crate::rsiv_err!("Failed to export the crop of {:?}: {}", path, e);
+                self.status_message = Some(format!("Failed to export: {}", e));
+            }
+        }
+    }
+}
@@ -0,0 +1,500 @@
+use crate::app::{App, AppEvent, InputMode};
+use crate::image_item::{ImageItem, ImageSlot};
+use crate::keybinds::Action;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use winit::keyboard::{Key, NamedKey};
+
+/// What a path typed in the status bar is for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathPrompt {
+    Move,
+    Copy,
+    Rename,
+}
+
+impl fmt::Display for PathPrompt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            PathPrompt::Move => "Move to",
+            PathPrompt::Copy => "Copy to",
+            PathPrompt::Rename => "Rename to",
+        };
+        f.write_str(s)
+    }
+}
+
+/// The files a finished operation handled. Failures are reported separately.
+#[derive(Debug)]
+pub enum FileOpResult {
+    Trashed(Vec<PathBuf>),
+    Restored(Vec<ImageItem>),
+    Moved(Vec<(PathBuf, PathBuf)>),
+    Copied(Vec<(PathBuf, PathBuf)>),
+    Renamed(PathBuf, PathBuf),
+}
+
+/// `~/` is the home directory, relative paths start from `base`.
+fn resolve(text: &str, base: &Path) -> PathBuf {
+    let path = match text.strip_prefix("~/").zip(std::env::var_os("HOME")) {
+        Some((rest, home)) => Path::new(&home).join(rest),
+        None if text == "~" => std::env::var_os("HOME")
+            .map(PathBuf::from)
+            .unwrap_or_default(),
+        None => PathBuf::from(text),
+    };
+    base.join(path)
+}
+
+/// Completes the last component of `text` to the longest name shared by the
+/// directories it can start. A single match gets its trailing slash.
+fn complete_dir(text: &str, base: &Path) -> Option<String> {
+    let (dir_part, prefix) = match text.rfind('/') {
+        Some(i) => text.split_at(i + 1),
+        None => ("", text),
+    };
+    let mut names: Vec<String> = fs::read_dir(resolve(dir_part, base))
+        .ok()?
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        // Hidden directories only once a dot is typed
+        .filter(|name| {
+            name.starts_with(prefix) && (prefix.starts_with('.') || !name.starts_with('.'))
+        })
+        .collect();
+    names.sort();
+
+    let first = names.first()?;
+    let shared = names.iter().fold(first.len(), |len, name| {
+        first[..len]
+            .char_indices()
+            .zip(name.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(len.min(name.len()), |((i, _), _)| i)
+    });
+    let mut completed = format!("{}{}", dir_part, &first[..shared]);
+    if names.len() == 1 {
+        completed.push('/');
+    }
+    Some(completed)
+}
+
+/// Renames, or copies then deletes when `to` is on another filesystem.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            fs::copy(from, to)?;
+            fs::remove_file(from)
+        }
+        result => result,
+    }
+}
+
+/// Moves or copies `paths` into `dir`, never over an existing file.
+fn transfer(paths: Vec<PathBuf>, dir: &Path, copy: bool) -> (FileOpResult, Vec<String>) {
+    let mut done = Vec::new();
+    let mut errors = Vec::new();
+    for from in paths {
+        let Some(name) = from.file_name() else {
+            continue;
+        };
+        let to = dir.join(name);
+        let result = if to.exists() {
+            Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "already exists",
+            ))
+        } else if copy {
+            fs::copy(&from, &to).map(|_| ())
+        } else {
+            move_file(&from, &to)
+        };
+        match result {
+            Ok(()) => done.push((from, to)),
+            Err(e) => errors.push(format!("{}: {}", to.display(), e)),
+        }
+    }
+    let result = if copy {
+        FileOpResult::Copied(done)
+    } else {
+        FileOpResult::Moved(done)
+    };
+    (result, errors)
+}
+
+fn trash(paths: Vec<PathBuf>) -> (FileOpResult, Vec<String>) {
+    let mut done = Vec::new();
+    let mut errors = Vec::new();
+    for path in paths {
+        match trash::delete(&path) {
+            Ok(()) => done.push(path),
+            Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+    (FileOpResult::Trashed(done), errors)
+}
+
+/// Puts the latest trashed copy of each of `paths` back where it was.
+#[cfg(any(
+    target_os = "windows",
+    all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )
+))]
+fn restore(paths: Vec<PathBuf>) -> (FileOpResult, Vec<String>) {
+    let mut items = match trash::os_limited::list() {
+        Ok(items) => items,
+        Err(e) => return (FileOpResult::Restored(vec![]), vec![e.to_string()]),
+    };
+    items.sort_by_key(|item| std::cmp::Reverse(item.time_deleted));
+
+    let mut errors = Vec::new();
+    let mut found = Vec::new();
+    for path in &paths {
+        match items.iter().position(|item| &item.original_path() == path) {
+            Some(i) => found.push(items.swap_remove(i)),
+            None => errors.push(format!("{}: no longer in the trash", path.display())),
+        }
+    }
+    let restored: Vec<PathBuf> = found.iter().map(|item| item.original_path()).collect();
+    if let Err(e) = trash::os_limited::restore_all(found) {
+        errors.push(e.to_string());
+        return (FileOpResult::Restored(vec![]), errors);
+    }
+
+    let items = restored
+        .iter()
+        .filter_map(|path| {
+            let format = crate::loader::identify_format(path).ok()?;
+            crate::loader::probe_item(path, format).ok()
+        })
+        .collect();
+    (FileOpResult::Restored(items), errors)
+}
+
+#[cfg(not(any(
+    target_os = "windows",
+    all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )
+)))]
+fn restore(_paths: Vec<PathBuf>) -> (FileOpResult, Vec<String>) {
+    (
+        FileOpResult::Restored(vec![]),
+        vec!["Restoring from the trash isn't supported on this platform".into()],
+    )
+}
+
+fn count(n: usize) -> String {
+    if n == 1 {
+        "1 file".into()
+    } else {
+        format!("{} files", n)
+    }
+}
+
+impl App {
+    /// The marked files, or else the current one.
+    fn file_op_targets(&self) -> Vec<PathBuf> {
+        if self.marked_files.is_empty() {
+            return self.current_path().into_iter().collect();
+        }
+        let mut paths: Vec<PathBuf> = self.marked_files.iter().map(PathBuf::from).collect();
+        paths.sort();
+        paths
+    }
+
+    /// Directory relative paths typed in the prompt start from.
+    fn prompt_base(&self) -> PathBuf {
+        self.current_path()
+            .and_then(|p| p.parent().map(Path::to_path_buf))
+            .unwrap_or_default()
+    }
+
+    pub fn handle_file_action(&mut self, action: Action) -> bool {
+        let targets = match action {
+            Action::RenameFile => self.current_path().into_iter().collect(),
+            Action::TrashFiles | Action::MoveFiles | Action::CopyFiles => self.file_op_targets(),
+            _ => Vec::new(),
+        };
+        if targets.iter().any(|p| crate::virtual_path::is_virtual(p)) {
+            self.status_message = Some("Piped images and archive entries aren't files".into());
+            return true;
+        }
+
+        let (prompt, text) = match action {
+            Action::TrashFiles => {
+                let paths = self.file_op_targets();
+                if paths.is_empty() {
+                    return false;
+                }
+                self.run_file_op(move || trash(paths));
+                return true;
+            }
+            Action::UndoTrash => {
+                if self.last_trashed.is_empty() {
+                    self.status_message = Some("Nothing to restore".into());
+                    return true;
+                }
+                let paths = self.last_trashed.clone();
+                self.run_file_op(move || restore(paths));
+                return true;
+            }
+            Action::MoveFiles | Action::CopyFiles => {
+                let prompt = if action == Action::MoveFiles {
+                    PathPrompt::Move
+                } else {
+                    PathPrompt::Copy
+                };
+                let dir = self
+                    .last_target_dir
+                    .clone()
+                    .unwrap_or_else(|| self.prompt_base());
+                let mut text = dir.to_string_lossy().to_string();
+                if !text.ends_with('/') {
+                    text.push('/');
+                }
+                (prompt, text)
+            }
+            Action::RenameFile => {
+                let Some(path) = self.current_path() else {
+                    return false;
+                };
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                (PathPrompt::Rename, name.to_string())
+            }
+            _ => return false,
+        };
+        if self.images.is_empty() {
+            return false;
+        }
+        self.input_mode = InputMode::EnteringPath { prompt, text };
+        true
+    }
+
+    /// Keys typed in the path prompt. Tab completes directory names.
+    pub fn handle_path_input(&mut self, key: &Key) -> bool {
+        let base = self.prompt_base();
+        let InputMode::EnteringPath { prompt, text } = &mut self.input_mode else {
+            return false;
+        };
+        match key {
+            Key::Named(NamedKey::Enter) => {
+                let (prompt, text) = (*prompt, std::mem::take(text));
+                self.input_mode = InputMode::Normal;
+                if !text.is_empty() {
+                    self.confirm_path(prompt, resolve(&text, &base));
+                }
+            }
+            Key::Named(NamedKey::Backspace) => {
+                text.pop();
+            }
+            Key::Named(NamedKey::Space) => text.push(' '),
+            Key::Named(NamedKey::Tab) if *prompt != PathPrompt::Rename => {
+                if let Some(completed) = complete_dir(text, &base) {
+                    *text = completed;
+                }
+            }
+            Key::Character(c) => text.push_str(c),
+            _ => return false,
+        }
+        true
+    }
+
+    fn confirm_path(&mut self, prompt: PathPrompt, target: PathBuf) {
+        match prompt {
+            PathPrompt::Move | PathPrompt::Copy => {
+                if !target.is_dir() {
+                    self.status_message = Some(format!("Not a directory: {}", target.display()));
+                    return;
+                }
+                self.last_target_dir = Some(target.clone());
+                let paths = self.file_op_targets();
+                let copy = prompt == PathPrompt::Copy;
+                self.run_file_op(move || transfer(paths, &target, copy));
+            }
+            PathPrompt::Rename => {
+                let Some(from) = self.current_path() else {
+                    return;
+                };
+                if target == from {
+                    return;
+                }
+                self.run_file_op(move || {
+                    let result = if target.exists() {
+                        Err(io::Error::new(
+                            io::ErrorKind::AlreadyExists,
+                            "already exists",
+                        ))
+                    } else {
+                        move_file(&from, &target)
+                    };
+                    match result {
+                        Ok(()) => (FileOpResult::Renamed(from, target), vec![]),
+                        Err(e) => (
+                            FileOpResult::Renamed(from.clone(), from),
+                            vec![format!("{}: {}", target.display(), e)],
+                        ),
+                    }
+                });
+            }
+        }
+    }
+
+    /// Runs a file operation off the UI thread.
+    fn run_file_op<F>(&self, op: F)
+    where
+        F: FnOnce() -> (FileOpResult, Vec<String>) + Send + 'static,
+    {
+        let proxy = self.proxy.clone();
+        std::thread::spawn(move || {
+            let (result, errors) = op();
+            let _ = proxy.send_event(AppEvent::FileOpFinished(result, errors));
+        });
+    }
+
+    /// Updates the lists, marks and caches right away, the watcher
+    /// events that follow find them up to date.
+    pub fn finish_file_op(&mut self, result: FileOpResult, errors: Vec<String>) {
+        for e in &errors {
+            crate::rsiv_err!("{}", e);
+        }
+
+        let message = match result {
+            FileOpResult::Trashed(paths) => {
+                self.forget_paths(&paths);
+                let message = format!("Trashed {}", count(paths.len()));
+                if !paths.is_empty() {
+                    self.last_trashed = paths;
+                }
+                message
+            }
+            FileOpResult::Restored(items) => {
+                let first = items.first().map(|item| item.path.clone());
+                for item in &items {
+                    self.last_trashed.retain(|p| *p != item.path);
+                }
+                let message = format!("Restored {}", count(items.len()));
+                for item in items {
+                    self.file_changed(item);
+                }
+                if let Some(path) = first {
+                    if self.select_path(&path) {
+                        self.reset_view_for_new_image();
+                    }
+                }
+                message
+            }
+            FileOpResult::Moved(moved) => {
+                let sources: Vec<PathBuf> = moved.iter().map(|(from, _)| from.clone()).collect();
+                self.forget_paths(&sources);
+                format!("Moved {}", count(moved.len()))
+            }
+            FileOpResult::Copied(copied) => format!("Copied {}", count(copied.len())),
+            FileOpResult::Renamed(from, to) => {
+                if from != to {
+                    self.rename_path(&from, &to);
+                }
+                let name = to.file_name().unwrap_or_default().to_string_lossy();
+                format!("Renamed to {}", name)
+            }
+        };
+
+        self.status_message = Some(match errors.as_slice() {
+            [] => message,
+            [e] => format!("Failed: {}", e),
+            [e, rest @ ..] => format!("Failed: {} (and {} more)", e, rest.len()),
+        });
+    }
+
+    /// Drops files that are gone from the lists, marks and caches. The
+    /// selection stays on the current image, or else at its position.
+    fn forget_paths(&mut self, paths: &[PathBuf]) {
+        if paths.is_empty() {
+            return;
+        }
+        let gone: HashSet<&PathBuf> = paths.iter().collect();
+        for path in paths {
+            self.cache.remove(path);
+            self.pending.remove(path);
+            self.image_adjustments.remove(path);
+            self.marked_files
+                .shift_remove(&path.to_string_lossy().to_string());
+        }
+        if matches!(&self.compare, Some(c) if gone.contains(&c.other.path)) {
+            self.compare = None;
+        }
+
+        self.all_images.retain(
+            |slot| !matches!(slot, ImageSlot::MetadataLoaded(item) if gone.contains(&item.path)),
+        );
+
+        let current = self.current_path().filter(|p| !gone.contains(p));
+        let index = self.current_index;
+        self.apply_filter();
+        match current {
+            Some(path) if self.select_path(&path) => {}
+            _ => {
+                self.current_index = index.min(self.images.len().saturating_sub(1));
+                self.reset_view_for_new_image();
+            }
+        }
+    }
+
+    /// Follows a renamed file, keeping its place, mark and loaded pixels.
+    fn rename_path(&mut self, from: &PathBuf, to: &Path) {
+        for slot in self.all_images.iter_mut().chain(self.images.iter_mut()) {
+            if let ImageSlot::MetadataLoaded(item) = slot {
+                if item.path == *from {
+                    item.path = to.to_path_buf();
+                }
+            }
+        }
+
+        // The mark keeps its place in the mark order
+        let to_mark = to.to_string_lossy().to_string();
+        if let Some(i) = self
+            .marked_files
+            .get_index_of(&from.to_string_lossy().to_string())
+        {
+            if self.marked_files.replace_index(i, to_mark).is_err() {
+                self.marked_files.shift_remove_index(i);
+            }
+        }
+        if let Some(adjustments) = self.image_adjustments.remove(from) {
+            self.image_adjustments.insert(to.to_path_buf(), adjustments);
+        }
+        // Tiles are read from the file, so tiled images load again
+        if let Some(image) = self.cache.get_image(from).filter(|img| img.tiled.is_none()) {
+            self.cache.insert_image(to.to_path_buf(), image);
+        }
+        if let Some(thumb) = self.cache.get_thumbnail(from) {
+            self.cache.insert_thumbnail(to.to_path_buf(), thumb);
+        }
+        self.cache.remove(from);
+        self.pending.remove(from);
+        if let Some(compare) = &mut self.compare {
+            if compare.other.path == *from {
+                compare.other.path = to.to_path_buf();
+            }
+        }
+        if let Some(crop) = &mut self.crop {
+            if crop.path == *from {
+                crop.path = to.to_path_buf();
+            }
+        }
+
+        // The new name may sort elsewhere
+        self.apply_sort();
+    }
+}
@@ -36,7 +36,7 @@ impl App {
             })
             .collect();
 
-        scored_matches.sort_by(|a, b| b.0.cmp(&a.0));
+        scored_matches.sort_by_key(|m| std::cmp::Reverse(m.0));
 
         self.images = scored_matches.into_iter().map(|(_, slot)| slot).collect();
 
@@ -0,0 +1,289 @@
+pub use imp::{decode, probe, thumbnail};
+
+/// HEIF, HEIC and AVIF are decoded by libheif, with the `heif` feature.
+/// Without it these files are not listed at all.
+pub fn is_supported() -> bool {
+    cfg!(feature = "heif")
+}
+
+/// Whether the file lists the `avis` brand of image sequences (animated AVIF).
+#[cfg(feature = "heif")]
+fn is_sequence(data: &[u8]) -> bool {
+    let Some(size) = data
+        .get(..4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    else {
+        return false;
+    };
+    let Some(ftyp) = data.get(..(size as usize).min(data.len())) else {
+        return false;
+    };
+    ftyp.get(4..8) == Some(b"ftyp")
+        && ftyp
+            .get(8..)
+            .is_some_and(|brands| brands.chunks_exact(4).any(|b| b == b"avis"))
+}
+
+#[cfg(feature = "heif")]
+mod imp {
+    use crate::image_item::{FrameData, LoadedImage};
+    use crate::loader::map_file;
+    use image::{DynamicImage, RgbaImage};
+    use libheif_rs::{ColorSpace, DecodingOptions, HeifContext, ImageHandle, LibHeif, RgbChroma};
+    use std::path::Path;
+    use std::time::Duration;
+
+    /// Size of the primary image as libheif reports it, after its `irot` and
+    /// `imir` properties.
+    pub fn probe(path: &Path) -> Result<(u32, u32), String> {
+        let data = map_file(path)?;
+        let ctx = HeifContext::read_from_bytes(&data).map_err(|e| e.to_string())?;
+        let handle = ctx.primary_image_handle().map_err(|e| e.to_string())?;
+        Ok((handle.width(), handle.height()))
+    }
+
+    /// Decodes to 8 bit RGBA. libheif applies the rotation and mirroring
+    /// stored in the file, the EXIF orientation must not be applied again.
+    fn decode_handle(handle: &ImageHandle) -> Result<RgbaImage, String> {
+        let mut options = DecodingOptions::new();
+        if let Some(options) = &mut options {
+            options.set_convert_hdr_to_8bit(true);
+        }
+        let image = LibHeif::new()
+            .decode(handle, ColorSpace::Rgb(RgbChroma::Rgba), options)
+            .map_err(|e| e.to_string())?;
+
+        let planes = image.planes();
+        let plane = planes.interleaved.ok_or("No RGBA plane")?;
+        let row = plane.width as usize * 4;
+        let mut pixels = Vec::with_capacity(row * plane.height as usize);
+        for y in 0..plane.height as usize {
+            let start = y * plane.stride;
+            pixels.extend_from_slice(&plane.data[start..start + row]);
+        }
+        RgbaImage::from_raw(plane.width, plane.height, pixels).ok_or("Unexpected plane size".into())
+    }
+
+    /// The frames of an image sequence, or else the primary image. A sequence
+    /// that can't be decoded falls back to the still image stored next to it.
+    pub fn decode(data: &[u8]) -> Result<LoadedImage, String> {
+        if super::is_sequence(data) {
+            match super::sequence::decode(data) {
+                Ok(Some((width, height, frames))) => {
+                    return Ok(LoadedImage::new(width, height, frames))
+                }
+                Ok(None) => {}
+                Err(e) => crate::rsiv_warn!("{}, showing the still image of the AVIF", e),
+            }
+        }
+        let ctx = HeifContext::read_from_bytes(data).map_err(|e| e.to_string())?;
+        let handle = ctx.primary_image_handle().map_err(|e| e.to_string())?;
+        crate::loader::check_memory_before_decode(handle.width(), handle.height(), 1)?;
+
+        let img = decode_handle(&handle)?;
+        Ok(LoadedImage::new(
+            img.width(),
+            img.height(),
+            vec![FrameData {
+                pixels: img.into_raw(),
+                delay: Duration::MAX,
+            }],
+        ))
+    }
+
+    /// Thumbnail items are linked to the primary image, and phone photos
+    /// usually carry one. The full image is decoded when none covers `size`.
+    pub fn thumbnail(path: &Path, size: u32) -> Result<(u32, u32, Vec<u8>), String> {
+        let data = map_file(path)?;
+        let ctx = HeifContext::read_from_bytes(&data).map_err(|e| e.to_string())?;
+        let handle = ctx.primary_image_handle().map_err(|e| e.to_string())?;
+
+        let mut ids = vec![0; handle.number_of_thumbnails()];
+        let count = handle.thumbnail_ids(&mut ids);
+        let embedded = ids[..count]
+            .iter()
+            .filter_map(|&id| handle.thumbnail(id).ok())
+            .filter(|t| t.width().max(t.height()) >= size)
+            .min_by_key(|t| t.width() * t.height());
+
+        let img = match embedded.map(|t| decode_handle(&t)) {
+            Some(Ok(img)) => img,
+            _ => decode_handle(&handle)?,
+        };
+        let thumb = DynamicImage::ImageRgba8(img).thumbnail(size, size);
+        Ok((thumb.width(), thumb.height(), thumb.to_rgba8().into_raw()))
+    }
+}
+
+/// Image sequences are read through the track API of libheif 1.20. The
+/// bindings predate it, so its functions are declared here.
+#[cfg(feature = "avif-sequences")]
+mod sequence {
+    use crate::image_item::FrameData;
+    use libheif_sys as lh;
+    use std::ffi::{c_int, CStr};
+    use std::ptr;
+    use std::time::Duration;
+
+    #[repr(C)]
+    struct HeifTrack {
+        _private: [u8; 0],
+    }
+
+    extern "C" {
+        fn heif_context_has_sequence(ctx: *const lh::heif_context) -> c_int;
+        // Track id 0 is the first visual track
+        fn heif_context_get_track(ctx: *const lh::heif_context, track_id: u32) -> *mut HeifTrack;
+        fn heif_track_release(track: *mut HeifTrack);
+        fn heif_track_get_timescale(track: *const HeifTrack) -> u32;
+        fn heif_track_decode_next_image(
+            track: *mut HeifTrack,
+            out_img: *mut *mut lh::heif_image,
+            colorspace: lh::heif_colorspace,
+            chroma: lh::heif_chroma,
+            options: *const lh::heif_decoding_options,
+        ) -> lh::heif_error;
+        fn heif_image_get_duration(img: *const lh::heif_image) -> u32;
+    }
+
+    /// Releases what libheif allocated, however decoding ends.
+    struct Handles {
+        ctx: *mut lh::heif_context,
+        track: *mut HeifTrack,
+        options: *mut lh::heif_decoding_options,
+    }
+
+    impl Drop for Handles {
+        fn drop(&mut self) {
+            unsafe {
+                if !self.track.is_null() {
+                    heif_track_release(self.track);
+                }
+                lh::heif_decoding_options_free(self.options);
+                lh::heif_context_free(self.ctx);
+            }
+        }
+    }
+
+    fn check(err: lh::heif_error) -> Result<(), String> {
+        if err.code == lh::heif_error_code_heif_error_Ok {
+            return Ok(());
+        }
+        Err(unsafe { CStr::from_ptr(err.message) }
+            .to_string_lossy()
+            .into_owned())
+    }
+
+    /// Copies an interleaved RGBA image out of libheif, dropping the row padding.
+    unsafe fn rgba_pixels(img: *const lh::heif_image) -> Result<(u32, u32, Vec<u8>), String> {
+        let channel = lh::heif_channel_heif_channel_interleaved;
+        let width = lh::heif_image_get_width(img, channel).max(0) as usize;
+        let height = lh::heif_image_get_height(img, channel).max(0) as usize;
+        let mut stride = 0;
+        let plane = lh::heif_image_get_plane_readonly(img, channel, &mut stride);
+        if plane.is_null() {
+            return Err("No RGBA plane".to_string());
+        }
+        let data = std::slice::from_raw_parts(plane, stride as usize * height);
+        let mut pixels = Vec::with_capacity(width * 4 * height);
+        for row in data.chunks_exact(stride as usize) {
+            pixels.extend_from_slice(&row[..width * 4]);
+        }
+        Ok((width as u32, height as u32, pixels))
+    }
+
+    /// Decodes every frame of the first visual track, `None` when there is none.
+    /// Frames without a duration get 100ms.
+    pub fn decode(data: &[u8]) -> Result<Option<(u32, u32, Vec<FrameData>)>, String> {
+        unsafe {
+            let mut handles = Handles {
+                ctx: lh::heif_context_alloc(),
+                track: ptr::null_mut(),
+                options: lh::heif_decoding_options_alloc(),
+            };
+            check(lh::heif_context_read_from_memory_without_copy(
+                handles.ctx,
+                data.as_ptr().cast(),
+                data.len(),
+                ptr::null(),
+            ))?;
+            if heif_context_has_sequence(handles.ctx) == 0 {
+                return Ok(None);
+            }
+            handles.track = heif_context_get_track(handles.ctx, 0);
+            if handles.track.is_null() {
+                return Ok(None);
+            }
+            (*handles.options).convert_hdr_to_8bit = 1;
+            let timescale = heif_track_get_timescale(handles.track).max(1) as u64;
+
+            let mut size = None;
+            let mut frames = Vec::new();
+            loop {
+                let mut img = ptr::null_mut();
+                let result = check(heif_track_decode_next_image(
+                    handles.track,
+                    &mut img,
+                    lh::heif_colorspace_heif_colorspace_RGB,
+                    lh::heif_chroma_heif_chroma_interleaved_RGBA,
+                    handles.options,
+                ));
+                // The end of the track is reported as an error too
+                if let Err(e) = result {
+                    if frames.is_empty() {
+                        return Err(e);
+                    }
+                    break;
+                }
+
+                let duration = heif_image_get_duration(img) as u64;
+                let pixels = rgba_pixels(img);
+                lh::heif_image_release(img);
+                let (width, height, pixels) = pixels?;
+                if *size.get_or_insert((width, height)) != (width, height) {
+                    return Err("Frames of the AVIF sequence differ in size".to_string());
+                }
+                crate::loader::check_memory_before_decode(width, height, 1)?;
+
+                frames.push(FrameData {
+                    pixels,
+                    delay: match duration {
+                        0 => Duration::from_millis(100),
+                        d => Duration::from_millis(d * 1000 / timescale),
+                    },
+                });
+            }
+
+            Ok(size.map(|(width, height)| (width, height, frames)))
+        }
+    }
+}
+
+#[cfg(all(feature = "heif", not(feature = "avif-sequences")))]
+mod sequence {
+    use crate::image_item::FrameData;
+
+    pub fn decode(_data: &[u8]) -> Result<Option<(u32, u32, Vec<FrameData>)>, String> {
+        Err("Animated AVIF needs libheif 1.20 and the avif-sequences feature".to_string())
+    }
+}
+
+#[cfg(not(feature = "heif"))]
+mod imp {
+    use crate::image_item::LoadedImage;
+    use std::path::Path;
+
+    const UNSUPPORTED: &str = "rsiv was built without HEIF/AVIF support (the heif feature)";
+
+    pub fn probe(_path: &Path) -> Result<(u32, u32), String> {
+        Err(UNSUPPORTED.into())
+    }
+
+    pub fn decode(_data: &[u8]) -> Result<LoadedImage, String> {
+        Err(UNSUPPORTED.into())
+    }
+
+    pub fn thumbnail(_path: &Path, _size: u32) -> Result<(u32, u32, Vec<u8>), String> {
+        Err(UNSUPPORTED.into())
+    }
+}
@@ -1,6 +1,11 @@
+use crate::pages::Document;
+use crate::tiles::TiledSource;
+use image::metadata::Orientation;
 use image::{ImageBuffer, Rgba};
+use rayon::prelude::*;
 use std::path::PathBuf;
-use std::time::Duration;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
 
 #[derive(Clone, Debug)]
 pub struct FrameData {
@@ -8,10 +13,24 @@ pub struct FrameData {
     pub delay: Duration,
 }
 
+/// A page of a document kept aside while another one is shown. Its pixels
+/// are empty when it is not kept decoded.
+#[derive(Clone, Debug, Default)]
+pub struct Page {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+    pub transform: Transform, // Already applied to the pixels
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ImageFormat {
     Raster,
     Svg,
+    Heif, // HEIF, HEIC and AVIF
+    Jxl,
+    Raw, // Camera RAW, shown through its embedded preview
+    Psd, // The flattened composite
 }
 
 #[derive(Clone)]
@@ -27,6 +46,204 @@ pub struct ImageItem {
     pub width: u32,
     pub height: u32,
     pub format: ImageFormat,
+    pub file_size: u64,
+    pub mtime: Option<SystemTime>,
+    pub ctime: Option<SystemTime>,
+    pub date_taken: Option<String>, // EXIF DateTimeOriginal, "YYYY:MM:DD HH:MM:SS"
+    pub details: Arc<OnceLock<Details>>, // Read once shown, see `details()`
+}
+
+impl ImageItem {
+    /// Only the status bar needs these, so a loader worker reads them once
+    /// they are shown. `None` until it is done.
+    pub fn details(&self) -> Option<&Details> {
+        self.details.get()
+    }
+}
+
+/// Extra information shown by the status bar, formatted for display.
+#[derive(Clone, Debug, Default)]
+pub struct Details {
+    pub mime: Option<String>,
+    pub camera: Option<String>,
+    pub lens: Option<String>,
+    pub exposure: Option<String>,
+    pub aperture: Option<String>,
+    pub iso: Option<String>,
+    pub focal_length: Option<String>,
+}
+
+/// A frame downscaled by `2^shift`, sizes rounded up.
+#[derive(Debug)]
+pub struct MipLevel {
+    pub width: u32,
+    pub height: u32,
+    pub shift: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl MipLevel {
+    fn halve(src: &[u8], src_w: u32, src_h: u32, shift: u32) -> Self {
+        let width = src_w.div_ceil(2);
+        let height = src_h.div_ceil(2);
+        let (sw, sh) = (src_w as usize, src_h as usize);
+        let mut pixels = vec![0u8; width as usize * height as usize * 4];
+
+        pixels
+            .par_chunks_exact_mut(width as usize * 4)
+            .enumerate()
+            .for_each(|(y, row)| {
+                let y0 = (y * 2).min(sh - 1);
+                let y1 = (y * 2 + 1).min(sh - 1);
+                for (x, dest) in row.chunks_exact_mut(4).enumerate() {
+                    let x0 = (x * 2).min(sw - 1);
+                    let x1 = (x * 2 + 1).min(sw - 1);
+
+                    // Alpha-weighted 2x2 box filter
+                    let mut acc = [0u32; 4];
+                    for (sx, sy) in [(x0, y0), (x1, y0), (x0, y1), (x1, y1)] {
+                        let p = &src[(sy * sw + sx) * 4..(sy * sw + sx) * 4 + 4];
+                        let a = p[3] as u32;
+                        acc[0] += p[0] as u32 * a;
+                        acc[1] += p[1] as u32 * a;
+                        acc[2] += p[2] as u32 * a;
+                        acc[3] += a;
+                    }
+                    // Fully transparent blocks have zero color sums and stay black
+                    let total = acc[3].max(1);
+                    dest[0] = ((acc[0] + total / 2) / total) as u8;
+                    dest[1] = ((acc[1] + total / 2) / total) as u8;
+                    dest[2] = ((acc[2] + total / 2) / total) as u8;
+                    dest[3] = ((acc[3] + 2) / 4) as u8;
+                }
+            });
+
+        Self {
+            width,
+            height,
+            shift,
+            pixels,
+        }
+    }
+}
+
+/// Rotations and flips applied since decoding: a horizontal flip if `flipped`,
+/// then `quarter_turns` clockwise quarter turns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Transform {
+    pub quarter_turns: u8,
+    pub flipped: bool,
+}
+
+impl Transform {
+    pub const ROTATE_CW: Self = Self {
+        quarter_turns: 1,
+        flipped: false,
+    };
+    pub const ROTATE_CCW: Self = Self {
+        quarter_turns: 3,
+        flipped: false,
+    };
+    pub const FLIP_HORIZONTAL: Self = Self {
+        quarter_turns: 0,
+        flipped: true,
+    };
+    // A horizontal flip turned upside down
+    pub const FLIP_VERTICAL: Self = Self {
+        quarter_turns: 2,
+        flipped: true,
+    };
+
+    pub fn is_identity(self) -> bool {
+        self == Self::default()
+    }
+
+    pub fn inverse(self) -> Self {
+        if self.flipped {
+            // A flip followed by turns undoes itself
+            self
+        } else {
+            Self {
+                quarter_turns: (4 - self.quarter_turns) % 4,
+                flipped: false,
+            }
+        }
+    }
+
+    /// This transform followed by `next`.
+    pub fn then(self, next: Self) -> Self {
+        if next.flipped {
+            // Flipping reverses the rotations made before it
+            Self {
+                quarter_turns: (next.quarter_turns + 4 - self.quarter_turns) % 4,
+                flipped: !self.flipped,
+            }
+        } else {
+            Self {
+                quarter_turns: (next.quarter_turns + self.quarter_turns) % 4,
+                flipped: self.flipped,
+            }
+        }
+    }
+
+    pub fn from_orientation(orientation: Orientation) -> Self {
+        let (quarter_turns, flipped) = match orientation {
+            Orientation::NoTransforms => (0, false),
+            Orientation::Rotate90 => (1, false),
+            Orientation::Rotate180 => (2, false),
+            Orientation::Rotate270 => (3, false),
+            Orientation::FlipHorizontal => (0, true),
+            Orientation::Rotate270FlipH => (1, true),
+            Orientation::FlipVertical => (2, true),
+            Orientation::Rotate90FlipH => (3, true),
+        };
+        Self {
+            quarter_turns,
+            flipped,
+        }
+    }
+
+    pub fn to_orientation(self) -> Orientation {
+        match (self.quarter_turns % 4, self.flipped) {
+            (0, false) => Orientation::NoTransforms,
+            (1, false) => Orientation::Rotate90,
+            (2, false) => Orientation::Rotate180,
+            (3, false) => Orientation::Rotate270,
+            (0, true) => Orientation::FlipHorizontal,
+            (1, true) => Orientation::Rotate270FlipH,
+            (2, true) => Orientation::FlipVertical,
+            _ => Orientation::Rotate90FlipH,
+        }
+    }
+
+    /// Where the rectangle `(x, y, w, h)` of an image of `size` ends up once
+    /// this transform is applied to the image.
+    pub fn map_rect(
+        self,
+        (mut x, mut y, mut w, mut h): (u32, u32, u32, u32),
+        (mut width, mut height): (u32, u32),
+    ) -> (u32, u32, u32, u32) {
+        if self.flipped {
+            x = width - x - w;
+        }
+        // Each clockwise quarter turn moves the left edge to the top
+        for _ in 0..self.quarter_turns % 4 {
+            (x, y, w, h) = (height - y - h, x, h, w);
+            (width, height) = (height, width);
+        }
+        (x, y, w, h)
+    }
+}
+
+/// Halved levels per frame, extended on demand as the view zooms further out.
+#[derive(Debug, Default)]
+pub struct MipLevels(Mutex<Vec<Vec<Arc<MipLevel>>>>);
+
+impl Clone for MipLevels {
+    fn clone(&self) -> Self {
+        let levels = self.0.lock().map(|l| l.clone()).unwrap_or_default();
+        Self(Mutex::new(levels))
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -34,15 +251,173 @@ pub struct LoadedImage {
     pub width: u32,
     pub height: u32,
     pub frames: Vec<FrameData>,
+    pub mip_levels: MipLevels,
+    pub tiled: Option<Arc<TiledSource>>,
+    pub transform: Transform, // Not written to the file yet
+    pub pages: Vec<Page>,     // Empty unless there are several, placeholders unless kept decoded
+    pub page: usize,
+    pub document: Option<Arc<Document>>,
+    pub preview: Option<Arc<MipLevel>>, // Drawn while the frames are still being decoded
 }
 
 impl LoadedImage {
+    pub fn new(width: u32, height: u32, frames: Vec<FrameData>) -> Self {
+        Self {
+            width,
+            height,
+            frames,
+            mip_levels: MipLevels::default(),
+            tiled: None,
+            transform: Transform::default(),
+            pages: Vec::new(),
+            page: 0,
+            document: None,
+            preview: None,
+        }
+    }
+
+    /// A document showing its first page. Pages are not animation frames:
+    /// each one has its own size and they are only switched by hand.
+    pub fn from_document(document: Document, first: Page) -> Self {
+        let mut image = Self::new(
+            first.width,
+            first.height,
+            vec![FrameData {
+                pixels: first.pixels,
+                delay: Duration::MAX,
+            }],
+        );
+        image.pages = vec![Page::default(); document.len()];
+        image.document = Some(Arc::new(document));
+        image
+    }
+
+    /// An image too large to decode at once, drawn from its overview and tiles.
+    pub fn from_tiles(source: TiledSource) -> Self {
+        Self {
+            width: source.width,
+            height: source.height,
+            frames: Vec::new(),
+            mip_levels: MipLevels::default(),
+            tiled: Some(Arc::new(source)),
+            transform: Transform::default(),
+            pages: Vec::new(),
+            page: 0,
+            document: None,
+            preview: None,
+        }
+    }
+
+    /// A reduced copy standing in for an image whose full decode is not done.
+    /// It has no frames, so it can't be edited or saved.
+    pub fn from_preview(width: u32, height: u32, preview: MipLevel) -> Self {
+        let mut image = Self::new(width, height, Vec::new());
+        image.preview = Some(Arc::new(preview));
+        image
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.len().max(1)
+    }
+
+    /// Swaps the shown page for another one, which gets the rotations and
+    /// flips made so far. Pages that weren't kept are decoded from the file.
+    /// Returns false if it is already shown.
+    pub fn show_page(&mut self, index: usize) -> Result<bool, String> {
+        if index == self.page || index >= self.pages.len() || self.frames.is_empty() {
+            return Ok(false);
+        }
+        let incoming = if self.pages[index].pixels.is_empty() {
+            let document = self
+                .document
+                .as_ref()
+                .ok_or("The pages are not in a file")?;
+            document.decode(index)?
+        } else {
+            std::mem::take(&mut self.pages[index])
+        };
+
+        let transform = self.transform;
+        self.pages[self.page] = Page {
+            width: self.width,
+            height: self.height,
+            pixels: std::mem::replace(&mut self.frames[0].pixels, incoming.pixels),
+            transform,
+        };
+        self.width = incoming.width;
+        self.height = incoming.height;
+        self.page = index;
+        self.mip_levels = MipLevels::default();
+
+        self.transform = incoming.transform;
+        let delta = incoming.transform.inverse().then(transform);
+        if delta.flipped {
+            self.flip_horizontal();
+        }
+        for _ in 0..delta.quarter_turns {
+            self.rotate(true);
+        }
+
+        // Only the pages next to the shown one stay decoded
+        let count = self.pages.len();
+        for (i, page) in self.pages.iter_mut().enumerate() {
+            let distance = i.abs_diff(index).min(count - i.abs_diff(index));
+            if distance > 1 {
+                *page = Page::default();
+            }
+        }
+        Ok(true)
+    }
+
     pub fn size_in_kb(&self) -> u32 {
-        let bytes: usize = self.frames.iter().map(|f| f.pixels.len()).sum();
+        let mut bytes: usize = self.frames.iter().map(|f| f.pixels.len()).sum();
+        bytes += self.pages.iter().map(|p| p.pixels.len()).sum::<usize>();
+        if let Some(tiled) = &self.tiled {
+            bytes += tiled.overview.pixels.len() + tiled.cached_bytes();
+        }
+        if let Some(preview) = &self.preview {
+            bytes += preview.pixels.len();
+        }
+        if let Ok(levels) = self.mip_levels.0.lock() {
+            bytes += levels
+                .iter()
+                .flatten()
+                .map(|l| l.pixels.len())
+                .sum::<usize>();
+        }
         ((bytes / 1024) as u32).max(1)
     }
 
+    /// Returns the smallest level still at least as large as the image drawn at `scale`,
+    /// building missing levels first. `None` means the full-resolution frame should be used.
+    pub fn mip_level(&self, frame_idx: usize, scale: f64) -> Option<Arc<MipLevel>> {
+        if scale > 0.5 || scale <= 0.0 {
+            return None;
+        }
+        let frame = self.frames.get(frame_idx)?;
+        let wanted = (1.0 / scale).log2().floor() as usize;
+
+        let mut levels = self.mip_levels.0.lock().ok()?;
+        if levels.len() < self.frames.len() {
+            levels.resize_with(self.frames.len(), Vec::new);
+        }
+        let chain = &mut levels[frame_idx];
+
+        while chain.len() < wanted {
+            let next = match chain.last() {
+                Some(prev) if prev.width == 1 && prev.height == 1 => break,
+                Some(prev) => {
+                    MipLevel::halve(&prev.pixels, prev.width, prev.height, prev.shift + 1)
+                }
+                None => MipLevel::halve(&frame.pixels, self.width, self.height, 1),
+            };
+            chain.push(Arc::new(next));
+        }
+        chain.last().cloned()
+    }
+
     pub fn rotate(&mut self, clockwise: bool) {
+        self.mip_levels = MipLevels::default();
         let mut new_size = None;
         for frame in &mut self.frames {
             if let Some(img_buf) = ImageBuffer::<Rgba<u8>, Vec<u8>>::from_raw(
@@ -62,10 +437,16 @@ impl LoadedImage {
         if let Some((w, h)) = new_size {
             self.width = w;
             self.height = h;
+            self.transform = self.transform.then(if clockwise {
+                Transform::ROTATE_CW
+            } else {
+                Transform::ROTATE_CCW
+            });
         }
     }
 
     pub fn flip_horizontal(&mut self) {
+        self.mip_levels = MipLevels::default();
         for frame in &mut self.frames {
             if let Some(img_buf) = ImageBuffer::<Rgba<u8>, Vec<u8>>::from_raw(
                 self.width,
@@ -75,9 +456,13 @@ impl LoadedImage {
                 frame.pixels = image::imageops::flip_horizontal(&img_buf).into_raw();
             }
         }
+        if !self.frames.is_empty() {
+            self.transform = self.transform.then(Transform::FLIP_HORIZONTAL);
+        }
     }
 
     pub fn flip_vertical(&mut self) {
+        self.mip_levels = MipLevels::default();
         for frame in &mut self.frames {
             if let Some(img_buf) = ImageBuffer::<Rgba<u8>, Vec<u8>>::from_raw(
                 self.width,
@@ -87,5 +472,114 @@ impl LoadedImage {
                 frame.pixels = image::imageops::flip_vertical(&img_buf).into_raw();
             }
         }
+        if !self.frames.is_empty() {
+            self.transform = self.transform.then(Transform::FLIP_VERTICAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::imageops::{flip_horizontal, rotate90};
+    use image::{DynamicImage, GrayImage};
+
+    fn all() -> impl Iterator<Item = Transform> {
+        (0..8).map(|i| Transform {
+            quarter_turns: i % 4,
+            flipped: i >= 4,
+        })
+    }
+
+    /// A 3x2 image with a different value in every pixel.
+    fn image() -> GrayImage {
+        GrayImage::from_fn(3, 2, |x, y| image::Luma([(y * 3 + x) as u8]))
+    }
+
+    fn apply(image: &GrayImage, transform: Transform) -> GrayImage {
+        let mut image = match transform.flipped {
+            true => flip_horizontal(image),
+            false => image.clone(),
+        };
+        for _ in 0..transform.quarter_turns {
+            image = rotate90(&image);
+        }
+        image
+    }
+
+    #[test]
+    fn then_matches_applying_both_transforms() {
+        for a in all() {
+            for b in all() {
+                assert_eq!(
+                    apply(&apply(&image(), a), b),
+                    apply(&image(), a.then(b)),
+                    "{a:?} then {b:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn inverse_undoes_the_transform() {
+        for t in all() {
+            assert!(t.then(t.inverse()).is_identity(), "{t:?}");
+            assert!(t.inverse().then(t).is_identity(), "{t:?}");
+        }
+        assert_eq!(Transform::ROTATE_CW.inverse(), Transform::ROTATE_CCW);
+    }
+
+    #[test]
+    fn named_transforms_match_the_image_ops() {
+        let image = image();
+        let flip_vertical = Transform::FLIP_VERTICAL;
+        assert_eq!(
+            apply(&image, flip_vertical),
+            image::imageops::flip_vertical(&image)
+        );
+        assert_eq!(
+            apply(&image, Transform::ROTATE_CCW),
+            image::imageops::rotate270(&image)
+        );
+    }
+
+    #[test]
+    fn orientations_round_trip_and_match_the_image_crate() {
+        for t in all() {
+            let orientation = t.to_orientation();
+            assert_eq!(Transform::from_orientation(orientation), t);
+
+            let mut oriented = DynamicImage::ImageLuma8(image());
+            oriented.apply_orientation(orientation);
+            assert_eq!(oriented.into_luma8(), apply(&image(), t), "{orientation:?}");
+        }
+    }
+
+    #[test]
+    fn map_rect_follows_the_pixels() {
+        let (width, height) = (5, 3);
+        let rect = (1, 0, 2, 1);
+        let marked = GrayImage::from_fn(width, height, |x, y| {
+            let inside = (1..3).contains(&x) && y == 0;
+            image::Luma([inside as u8])
+        });
+
+        for t in all() {
+            let moved = apply(&marked, t);
+            let inside: Vec<(u32, u32)> = moved
+                .enumerate_pixels()
+                .filter(|(_, _, p)| p.0[0] == 1)
+                .map(|(x, y, _)| (x, y))
+                .collect();
+            let x = inside.iter().map(|p| p.0).min().unwrap();
+            let y = inside.iter().map(|p| p.1).min().unwrap();
+            let right = inside.iter().map(|p| p.0).max().unwrap();
+            let bottom = inside.iter().map(|p| p.1).max().unwrap();
+            assert_eq!(
+                t.map_rect(rect, (width, height)),
+                (x, y, right - x + 1, bottom - y + 1),
+                "{t:?}"
+            );
+        }
     }
 }
@@ -0,0 +1,312 @@
+use crate::app::{App, AppEvent};
+use crate::image_item::ImageSlot;
+use crate::keybinds::Action;
+use crate::view_mode::ViewMode;
+use crossbeam_channel::bounded;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use winit::event_loop::{ActiveEventLoop, EventLoopProxy};
+
+/// Set for the children of rsiv (e.g. handlers), so they talk to their parent.
+pub const SOCKET_ENV: &str = "RSIV_SOCKET";
+
+/// One line of the remote control protocol, e.g. `{"cmd": "goto", "index": 3}`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Command {
+    Open {
+        paths: Vec<PathBuf>,
+        #[serde(default)]
+        recursive: bool,
+    },
+    Append {
+        paths: Vec<PathBuf>,
+        #[serde(default)]
+        recursive: bool,
+    },
+    Goto {
+        index: Option<usize>,
+        path: Option<PathBuf>,
+    },
+    Mark {
+        path: Option<PathBuf>,
+    },
+    Unmark {
+        path: Option<PathBuf>,
+    },
+    Filter {
+        text: String,
+    },
+    View {
+        mode: ViewMode,
+    },
+    Action {
+        name: String,
+    },
+    Query,
+}
+
+fn runtime_dir() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+pub fn default_socket_path() -> PathBuf {
+    runtime_dir().join(format!("rsiv-{}.sock", std::process::id()))
+}
+
+/// Socket used by `--remote` when none is given: the one of the parent rsiv,
+/// or else the most recently started instance.
+pub fn find_socket() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(SOCKET_ENV) {
+        return Some(PathBuf::from(path));
+    }
+
+    std::fs::read_dir(runtime_dir())
+        .ok()?
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            let name = e.file_name();
+            let name = name.to_string_lossy();
+            name.starts_with("rsiv-") && name.ends_with(".sock")
+        })
+        .filter_map(|e| Some((e.metadata().ok()?.modified().ok()?, e.path())))
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, path)| path)
+}
+
+pub fn spawn_listener(path: &Path, proxy: EventLoopProxy<AppEvent>) -> Result<(), String> {
+    if path.exists() {
+        // Only replace sockets left behind by a dead instance
+        if UnixStream::connect(path).is_ok() {
+            return Err(format!("{:?} is used by another instance", path));
+        }
+        std::fs::remove_file(path).map_err(|e| e.to_string())?;
+    }
+
+    let listener = UnixListener::bind(path).map_err(|e| e.to_string())?;
+    set_private(path);
+
+    thread::spawn(move || {
+        for stream in listener.incoming().filter_map(|s| s.ok()) {
+            let proxy = proxy.clone();
+            thread::spawn(move || serve(stream, proxy));
+        }
+    });
+    Ok(())
+}
+
+fn set_private(path: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+    let _ = std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600));
+}
+
+fn serve(stream: UnixStream, proxy: EventLoopProxy<AppEvent>) {
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+
+    for line in BufReader::new(stream).lines().map_while(Result::ok) {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<Command>(&line) {
+            Ok(command) => {
+                let (tx, rx) = bounded(1);
+                if proxy.send_event(AppEvent::Remote(command, tx)).is_err() {
+                    break;
+                }
+                match rx.recv() {
+                    Ok(response) => response,
+                    Err(_) => break,
+                }
+            }
+            Err(e) => json!({ "ok": false, "error": e.to_string() }),
+        };
+
+        if writeln!(writer, "{}", response).is_err() {
+            break;
+        }
+    }
+}
+
+/// Client side of `--remote`. Returns the response line.
+pub fn send(socket: &Path, command: &str) -> Result<Value, String> {
+    // Plain words are commands without arguments, like `query`
+    let mut request: Value = if command.trim_start().starts_with('{') {
+        serde_json::from_str(command).map_err(|e| e.to_string())?
+    } else {
+        json!({ "cmd": command.trim() })
+    };
+
+    // Paths are resolved here, as rsiv may run in another directory
+    if let Some(paths) = request.get_mut("paths").and_then(|p| p.as_array_mut()) {
+        for p in paths.iter_mut() {
+            absolutize(p);
+        }
+    }
+    if let Some(p) = request.get_mut("path") {
+        absolutize(p);
+    }
+
+    let mut stream = UnixStream::connect(socket).map_err(|e| format!("{:?}: {}", socket, e))?;
+    writeln!(stream, "{}", request).map_err(|e| e.to_string())?;
+
+    let mut line = String::new();
+    BufReader::new(stream)
+        .read_line(&mut line)
+        .map_err(|e| e.to_string())?;
+    serde_json::from_str(&line).map_err(|e| e.to_string())
+}
+
+fn absolutize(value: &mut Value) {
+    if let Some(p) = value.as_str() {
+        if let Ok(canonical) = std::fs::canonicalize(p) {
+            *value = Value::String(canonical.to_string_lossy().into_owned());
+        }
+    }
+}
+
+impl App {
+    /// Runs a remote command and builds its response.
+    pub fn handle_remote(&mut self, el: &ActiveEventLoop, command: Command) -> Value {
+        let result = self.run_remote(el, command);
+
+        if let Some(w) = &self.window {
+            w.request_redraw();
+        }
+
+        match result {
+            Ok(Value::Object(mut fields)) => {
+                fields.insert("ok".into(), Value::Bool(true));
+                Value::Object(fields)
+            }
+            Ok(_) => json!({ "ok": true }),
+            Err(e) => json!({ "ok": false, "error": e }),
+        }
+    }
+
+    fn run_remote(&mut self, el: &ActiveEventLoop, command: Command) -> Result<Value, String> {
+        match command {
+            Command::Open { paths, recursive } => {
+                if !paths.iter().any(|p| p.exists()) {
+                    return Err("None of the paths exist".into());
+                }
+                self.open_paths(paths, recursive);
+            }
+            Command::Append { paths, recursive } => {
+                crate::loader::spawn_append_worker(paths, recursive, self.proxy.clone());
+            }
+            Command::Goto { index, path } => {
+                if let Some(path) = path {
+                    if !self.select_path(&path) {
+                        return Err(format!("{:?} is not in the image list", path));
+                    }
+                } else if let Some(index) = index {
+                    if index == 0 || index > self.images.len() {
+                        return Err(format!("Index {} is out of range", index));
+                    }
+                    self.current_index = index - 1;
+                } else {
+                    return Err("Expected an index or a path".into());
+                }
+                self.reset_view_for_new_image();
+            }
+            Command::Mark { path } => {
+                let path = self.remote_target(path)?;
+                self.marked_files.insert(path);
+            }
+            Command::Unmark { path } => {
+                let path = self.remote_target(path)?;
+                self.marked_files.shift_remove(&path);
+            }
+            Command::Filter { text } => {
+                self.filter_text = text;
+                self.apply_filter();
+            }
+            Command::View { mode } => {
+                self.mode = mode;
+                if crate::config::AppConfig::get().options.auto_center {
+                    self.off_x = 0;
+                    self.off_y = 0;
+                }
+            }
+            Command::Action { name } => {
+                let action = Action::from_name(&name, self.grid_mode)
+                    .ok_or_else(|| format!("Unknown action for this view: {}", name))?;
+                self.run_action(el, action);
+            }
+            Command::Query => {
+                let marks = self.marks_in_order();
+                return Ok(json!({
+                    "path": self.current_path(),
+                    "index": if self.images.is_empty() { 0 } else { self.current_index + 1 },
+                    "total": self.images.len(),
+                    "marks": marks,
+                    "zoom": (self.get_current_scale() * 100.0).round() as u32,
+                }));
+            }
+        }
+        Ok(Value::Null)
+    }
+
+    /// Replaces the image list with the images under `paths`, starting over
+    /// like a new instance would.
+    fn open_paths(&mut self, paths: Vec<PathBuf>, recursive: bool) {
+        self.discovery += 1;
+        self.discovery_complete = false;
+        self.all_images.clear();
+        self.images.clear();
+        self.current_index = 0;
+        self.start_path = None;
+        self.start_index = None;
+        self.pending.clear();
+        self.pending_tiles.clear();
+        self.failed_tiles.clear();
+        self.pending_details.clear();
+        self.compare = None;
+        self.crop = None;
+        self.reset_view_for_new_image();
+
+        if let Some(watcher) = &mut self.watcher {
+            watcher.watch(&paths, recursive);
+        }
+        crate::loader::spawn_discovery_worker(
+            paths,
+            None,
+            recursive,
+            None,
+            self.include_hidden,
+            (self.sort_mode, self.sort_reverse),
+            self.discovery,
+            self.proxy.clone(),
+        );
+    }
+
+    /// The listed image at `path`, or the current one.
+    fn remote_target(&self, path: Option<PathBuf>) -> Result<String, String> {
+        let Some(path) = path else {
+            return self
+                .current_path()
+                .map(|p| p.to_string_lossy().to_string())
+                .ok_or_else(|| "No current image".to_string());
+        };
+
+        let listed = self
+            .all_images
+            .iter()
+            .any(|slot| matches!(slot, ImageSlot::MetadataLoaded(item) if item.path == path));
+        if listed {
+            Ok(path.to_string_lossy().to_string())
+        } else {
+            Err(format!("{:?} is not in the image list", path))
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use crate::image_item::{FrameData, LoadedImage, MipLevel};
+use crate::loader::map_file;
+use image::codecs::png::PngDecoder;
+use image::AnimationDecoder;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+const DJXL: &str = "djxl";
+const CONTAINER_SIGNATURE: [u8; 12] = [
+    0, 0, 0, 0x0C, b'J', b'X', b'L', b' ', 0x0D, 0x0A, 0x87, 0x0A,
+];
+const CODESTREAM_SIGNATURE: [u8; 2] = [0xFF, 0x0A];
+
+/// Images this large get a coarse progressive pass shown before the full decode.
+const PREVIEW_MIN_PIXELS: u64 = 8_000_000;
+
+/// JPEG XL is decoded by `djxl` from libjxl. Without it these files are still
+/// listed, with the size read from their header, but fail to open.
+fn is_supported() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+    *AVAILABLE.get_or_init(|| {
+        Command::new(DJXL)
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok()
+    })
+}
+
+/// ISOBMFF boxes of a container file, as (type, content).
+fn boxes(data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    let mut rest = data.get(CONTAINER_SIGNATURE.len()..).unwrap_or(&[]);
+    std::iter::from_fn(move || {
+        let size = u32::from_be_bytes(rest.get(0..4)?.try_into().ok()?) as u64;
+        let kind: [u8; 4] = rest.get(4..8)?.try_into().ok()?;
+        let (header, size) = match size {
+            0 => (8, rest.len() as u64),
+            1 => (16, u64::from_be_bytes(rest.get(8..16)?.try_into().ok()?)),
+            n => (8, n),
+        };
+        if size < header || size > rest.len() as u64 {
+            return None;
+        }
+        let content = &rest[header as usize..size as usize];
+        rest = &rest[size as usize..];
+        Some((kind, content))
+    })
+}
+
+/// Start of the codestream, either a bare file or the first codestream box.
+fn codestream(data: &[u8]) -> Option<&[u8]> {
+    if !data.starts_with(&CONTAINER_SIGNATURE) {
+        return Some(data);
+    }
+    boxes(data).find_map(|(kind, content)| match &kind {
+        b"jxlc" => Some(content),
+        // Partial codestream boxes start with their index
+        b"jxlp" => content.get(4..),
+        _ => None,
+    })
+}
+
+/// Least significant bit first, as the codestream header is written.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, n: u32) -> Option<u32> {
+        let mut value = 0;
+        for i in 0..n {
+            let byte = *self.data.get(self.pos / 8)?;
+            value |= (((byte >> (self.pos % 8)) & 1) as u32) << i;
+            self.pos += 1;
+        }
+        Some(value)
+    }
+
+    fn bool(&mut self) -> Option<bool> {
+        self.bits(1).map(|b| b == 1)
+    }
+
+    /// A dimension: a 2 bit selector then 9, 13, 18 or 30 bits, plus one.
+    fn size(&mut self) -> Option<u32> {
+        let bits = [9, 13, 18, 30][self.bits(2)? as usize];
+        Some(self.bits(bits)? + 1)
+    }
+}
+
+/// Size from the codestream header, swapped when the orientation turns the
+/// image on its side. Only the first bytes of the file are read.
+pub fn probe(path: &Path) -> Result<(u32, u32), String> {
+    let data = map_file(path)?;
+    let stream = codestream(&data)
+        .filter(|s| s.starts_with(&CODESTREAM_SIGNATURE))
+        .ok_or("No JPEG XL codestream")?;
+    let mut reader = BitReader {
+        data: &stream[CODESTREAM_SIGNATURE.len()..],
+        pos: 0,
+    };
+    read_header(&mut reader).ok_or_else(|| "Truncated JPEG XL header".to_string())
+}
+
+fn read_header(r: &mut BitReader) -> Option<(u32, u32)> {
+    let small = r.bool()?;
+    let height = if small {
+        (r.bits(5)? + 1) * 8
+    } else {
+        r.size()?
+    };
+    let width = match r.bits(3)? {
+        0 if small => (r.bits(5)? + 1) * 8,
+        0 => r.size()?,
+        ratio => {
+            let (num, den) =
+                [(1, 1), (12, 10), (4, 3), (3, 2), (16, 9), (5, 4), (2, 1)][ratio as usize - 1];
+            (height as u64 * num / den) as u32
+        }
+    };
+
+    // The orientation is the first field of the image metadata
+    let all_default = r.bool()?;
+    let orientation = if !all_default && r.bool()? {
+        r.bits(3)? + 1
+    } else {
+        1
+    };
+    if orientation > 4 {
+        Some((height, width))
+    } else {
+        Some((width, height))
+    }
+}
+
+/// EXIF from the `Exif` box. Brotli compressed metadata boxes are skipped.
+pub fn read_exif(path: &Path) -> Option<exif::Exif> {
+    let data = map_file(path).ok()?;
+    if !data.starts_with(&CONTAINER_SIGNATURE) {
+        return None;
+    }
+    let (_, content) = boxes(&data).find(|(kind, _)| kind == b"Exif")?;
+    let offset = u32::from_be_bytes(content.get(0..4)?.try_into().ok()?) as usize;
+    let tiff = content.get(4 + offset..)?;
+    exif::Reader::new().read_raw(tiff.to_vec()).ok()
+}
+
+/// Temporary PNG written by djxl, removed when dropped.
+struct Output(PathBuf);
+
+impl Output {
+    fn new() -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        Self(std::env::temp_dir().join(format!("rsiv-{}-{}.png", std::process::id(), n)))
+    }
+
+    /// Animations come out as APNG. djxl has applied the orientation already.
+    fn read(&self) -> Result<LoadedImage, String> {
+        let data = std::fs::read(&self.0).map_err(|e| e.to_string())?;
+        let decoder = PngDecoder::new(Cursor::new(&data)).map_err(|e| e.to_string())?;
+        if decoder.is_apng().unwrap_or(false) {
+            let frames = decoder
+                .apng()
+                .and_then(|a| a.into_frames().collect_frames())
+                .map_err(|e| e.to_string())?;
+            if frames.len() > 1 {
+                return Ok(crate::loader::collect_animation(frames));
+            }
+        }
+
+        let img = image::load_from_memory(&data)
+            .map_err(|e| e.to_string())?
+            .to_rgba8();
+        Ok(LoadedImage::new(
+            img.width(),
+            img.height(),
+            vec![FrameData {
+                pixels: img.into_raw(),
+                delay: Duration::MAX,
+            }],
+        ))
+    }
+
+    /// A progressive pass comes out at its reduced size, which is drawn
+    /// scaled up rather than resized here.
+    fn read_preview(&self, width: u32, height: u32) -> Result<LoadedImage, String> {
+        let img = image::open(&self.0).map_err(|e| e.to_string())?.to_rgba8();
+        let shift = (0..=3)
+            .find(|s| (width.div_ceil(1 << s), height.div_ceil(1 << s)) == img.dimensions())
+            .ok_or("Unexpected size of the progressive pass")?;
+        if shift == 0 {
+            return self.read();
+        }
+        Ok(LoadedImage::from_preview(
+            width,
+            height,
+            MipLevel {
+                width: img.width(),
+                height: img.height(),
+                shift,
+                pixels: img.into_raw(),
+            },
+        ))
+    }
+}
+
+impl Drop for Output {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn djxl(path: &Path, output: &Output, downsampling: Option<u32>) -> Command {
+    let mut cmd = Command::new(DJXL);
+    cmd.arg(path)
+        .arg(&output.0)
+        .arg("--bits_per_sample=8")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null());
+    if let Some(factor) = downsampling {
+        cmd.arg(format!("--downsampling={}", factor));
+    }
+    cmd
+}
+
+/// Decodes the whole image. For large files a coarse progressive pass is
+/// decoded alongside and handed to `show_preview` if it finishes first.
+///
+/// djxl can't report its passes while it decodes, so the preview is a second
+/// djxl run at 1/8 of the size. For files without progressive passes it takes
+/// as long as the full decode, and is killed once that one is done.
+pub fn decode<F>(path: &Path, show_preview: F) -> Result<LoadedImage, String>
+where
+    F: FnOnce(LoadedImage) + Send + 'static,
+{
+    if !is_supported() {
+        return Err(format!("JPEG XL needs {} from libjxl", DJXL));
+    }
+    let (width, height) = probe(path)?;
+    crate::loader::check_memory_before_decode(width, height, 1)?;
+
+    let done = Arc::new(Mutex::new(false));
+    if width as u64 * height as u64 >= PREVIEW_MIN_PIXELS {
+        spawn_preview(
+            path.to_path_buf(),
+            (width, height),
+            done.clone(),
+            show_preview,
+        );
+    }
+
+    let output = Output::new();
+    let result = djxl(path, &output, None)
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", DJXL, e))
+        .and_then(|out| {
+            if out.status.success() {
+                output.read()
+            } else {
+                Err(String::from_utf8_lossy(&out.stderr).trim().to_string())
+            }
+        });
+
+    // Holding the lock orders this after any preview that is being sent
+    *done.lock().unwrap() = true;
+    result
+}
+
+fn spawn_preview<F>(path: PathBuf, size: (u32, u32), done: Arc<Mutex<bool>>, show_preview: F)
+where
+    F: FnOnce(LoadedImage) + Send + 'static,
+{
+    thread::spawn(move || {
+        let output = Output::new();
+        let Ok(mut child) = djxl(&path, &output, Some(8)).stderr(Stdio::null()).spawn() else {
+            return;
+        };
+
+        // Files without progressive passes take as long as the full decode,
+        // so stop as soon as that one is done
+        let status = loop {
+            if *done.lock().unwrap() {
+                let _ = child.kill();
+                let _ = child.wait();
+                return;
+            }
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) => thread::sleep(Duration::from_millis(20)),
+                Err(_) => return,
+            }
+        };
+        if !status.success() {
+            return;
+        }
+
+        if let Ok(preview) = output.read_preview(size.0, size.1) {
+            let done = done.lock().unwrap();
+            if !*done {
+                show_preview(preview);
+            }
+        }
+    });
+}
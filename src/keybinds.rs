@@ -1,4 +1,5 @@
-use crate::config::AppConfig;
+use crate::config::{AppConfig, BindingList, Keybindings};
+use winit::event::MouseButton;
 use winit::keyboard::{Key, ModifiersState, NamedKey};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -21,6 +22,8 @@ pub enum Action {
     LastImage,
     NextFrame,
     PrevFrame,
+    NextPage,
+    PrevPage,
 
     // View Mode Specific
     PanLeft,
@@ -44,6 +47,7 @@ pub enum Action {
     RotateCCW,
     FlipHorizontal,
     FlipVertical,
+    SaveImage,
 
     // Grid Mode Specific
     GridMoveLeft,
@@ -65,11 +69,443 @@ pub enum Action {
     ScriptHandlerPrefix,
     FilterMode,
     ToggleAlpha,
+    SortNext,
+    SortPrev,
+    SortReverse,
+
+    // Color Adjustments
+    BrightnessUp,
+    BrightnessDown,
+    ContrastUp,
+    ContrastDown,
+    GammaUp,
+    GammaDown,
+    SaturationUp,
+    SaturationDown,
+    ToggleInvert,
+    ToggleGrayscale,
+    ResetAdjustments,
+
+    // Compare Mode
+    ToggleCompare,
+    CompareNextLayout,
+    CompareSwap,
+    CompareDividerLeft,
+    CompareDividerRight,
+
+    // Crop Mode
+    ToggleCrop,
+    CropAspect,
+    CropExport,
+
+    // File Operations
+    TrashFiles,
+    UndoTrash,
+    MoveFiles,
+    CopyFiles,
+    RenameFile,
     Digit(usize),
 }
 
+/// Reads the keys bound to an action from the config.
+type KeysOf = fn(&Keybindings) -> &BindingList;
+
+/// Every bindable action by its `[keybindings]` name, with the mode it runs in.
+/// Bindings are built from this table in order, so earlier entries win when a
+/// key is bound twice.
+const ACTIONS: &[(&str, BindingMode, Action, KeysOf)] = &[
+    ("quit", BindingMode::Global, Action::Quit, |k| &k.quit),
+    (
+        "handler_prefix",
+        BindingMode::Global,
+        Action::ScriptHandlerPrefix,
+        |k| &k.handler_prefix,
+    ),
+    (
+        "toggle_status_bar",
+        BindingMode::Global,
+        Action::ToggleStatusBar,
+        |k| &k.toggle_status_bar,
+    ),
+    (
+        "toggle_animation",
+        BindingMode::Global,
+        Action::ToggleAnimation,
+        |k| &k.toggle_animation,
+    ),
+    (
+        "toggle_slideshow",
+        BindingMode::Global,
+        Action::ToggleSlideshow,
+        |k| &k.toggle_slideshow,
+    ),
+    ("image_next", BindingMode::Global, Action::NextImage, |k| {
+        &k.image_next
+    }),
+    (
+        "image_previous",
+        BindingMode::Global,
+        Action::PrevImage,
+        |k| &k.image_previous,
+    ),
+    ("next_mark", BindingMode::Global, Action::NextMark, |k| {
+        &k.next_mark
+    }),
+    ("prev_mark", BindingMode::Global, Action::PrevMark, |k| {
+        &k.prev_mark
+    }),
+    (
+        "toggle_grid",
+        BindingMode::Global,
+        Action::ToggleGrid,
+        |k| &k.toggle_grid,
+    ),
+    (
+        "filter_mode",
+        BindingMode::Global,
+        Action::FilterMode,
+        |k| &k.filter_mode,
+    ),
+    ("mark_file", BindingMode::Global, Action::MarkFile, |k| {
+        &k.mark_file
+    }),
+    ("unmark_all", BindingMode::Global, Action::UnmarkAll, |k| {
+        &k.unmark_all
+    }),
+    (
+        "remove_image",
+        BindingMode::Global,
+        Action::RemoveImage,
+        |k| &k.remove_image,
+    ),
+    ("mark_all", BindingMode::Global, Action::ToggleMarks, |k| {
+        &k.mark_all
+    }),
+    (
+        "first_image",
+        BindingMode::Global,
+        Action::FirstImage,
+        |k| &k.first_image,
+    ),
+    ("last_image", BindingMode::Global, Action::LastImage, |k| {
+        &k.last_image
+    }),
+    ("zoom_in", BindingMode::View, Action::ZoomIn, |k| &k.zoom_in),
+    ("zoom_out", BindingMode::View, Action::ZoomOut, |k| {
+        &k.zoom_out
+    }),
+    ("zoom_reset", BindingMode::View, Action::ZoomReset, |k| {
+        &k.zoom_reset
+    }),
+    ("fit_best", BindingMode::View, Action::FitToWindow, |k| {
+        &k.fit_best
+    }),
+    (
+        "fit_best_no_upscale",
+        BindingMode::View,
+        Action::BestFit,
+        |k| &k.fit_best_no_upscale,
+    ),
+    ("fit_cover", BindingMode::View, Action::Cover, |k| {
+        &k.fit_cover
+    }),
+    ("fit_width", BindingMode::View, Action::FitWidth, |k| {
+        &k.fit_width
+    }),
+    ("fit_height", BindingMode::View, Action::FitHeight, |k| {
+        &k.fit_height
+    }),
+    (
+        "view_reset_pan",
+        BindingMode::View,
+        Action::ResetView,
+        |k| &k.view_reset_pan,
+    ),
+    (
+        "image_flip_horizontal",
+        BindingMode::View,
+        Action::FlipHorizontal,
+        |k| &k.image_flip_horizontal,
+    ),
+    (
+        "image_flip_vertical",
+        BindingMode::View,
+        Action::FlipVertical,
+        |k| &k.image_flip_vertical,
+    ),
+    ("rotate_cw", BindingMode::View, Action::RotateCW, |k| {
+        &k.rotate_cw
+    }),
+    ("rotate_ccw", BindingMode::View, Action::RotateCCW, |k| {
+        &k.rotate_ccw
+    }),
+    ("view_pan_left", BindingMode::View, Action::PanLeft, |k| {
+        &k.view_pan_left
+    }),
+    ("view_pan_right", BindingMode::View, Action::PanRight, |k| {
+        &k.view_pan_right
+    }),
+    ("view_pan_up", BindingMode::View, Action::PanUp, |k| {
+        &k.view_pan_up
+    }),
+    ("view_pan_down", BindingMode::View, Action::PanDown, |k| {
+        &k.view_pan_down
+    }),
+    (
+        "view_pan_left_edge",
+        BindingMode::View,
+        Action::PanToLeftEdge,
+        |k| &k.view_pan_left_edge,
+    ),
+    (
+        "view_pan_right_edge",
+        BindingMode::View,
+        Action::PanToRightEdge,
+        |k| &k.view_pan_right_edge,
+    ),
+    (
+        "view_pan_top_edge",
+        BindingMode::View,
+        Action::PanToTopEdge,
+        |k| &k.view_pan_top_edge,
+    ),
+    (
+        "view_pan_bottom_edge",
+        BindingMode::View,
+        Action::PanToBottomEdge,
+        |k| &k.view_pan_bottom_edge,
+    ),
+    (
+        "view_pan_left",
+        BindingMode::Grid,
+        Action::GridMoveLeft,
+        |k| &k.view_pan_left,
+    ),
+    (
+        "view_pan_right",
+        BindingMode::Grid,
+        Action::GridMoveRight,
+        |k| &k.view_pan_right,
+    ),
+    ("view_pan_up", BindingMode::Grid, Action::GridMoveUp, |k| {
+        &k.view_pan_up
+    }),
+    (
+        "view_pan_down",
+        BindingMode::Grid,
+        Action::GridMoveDown,
+        |k| &k.view_pan_down,
+    ),
+    (
+        "grid_page_up",
+        BindingMode::Grid,
+        Action::GridMovePageUp,
+        |k| &k.grid_page_up,
+    ),
+    (
+        "grid_page_down",
+        BindingMode::Grid,
+        Action::GridMovePageDown,
+        |k| &k.grid_page_down,
+    ),
+    (
+        "grid_scroll_up",
+        BindingMode::Grid,
+        Action::GridMoveUp,
+        |k| &k.grid_scroll_up,
+    ),
+    (
+        "grid_scroll_down",
+        BindingMode::Grid,
+        Action::GridMoveDown,
+        |k| &k.grid_scroll_down,
+    ),
+    (
+        "toggle_alpha",
+        BindingMode::Global,
+        Action::ToggleAlpha,
+        |k| &k.toggle_alpha,
+    ),
+    ("next_frame", BindingMode::View, Action::NextFrame, |k| {
+        &k.next_frame
+    }),
+    ("prev_frame", BindingMode::View, Action::PrevFrame, |k| {
+        &k.prev_frame
+    }),
+    ("next_page", BindingMode::View, Action::NextPage, |k| {
+        &k.next_page
+    }),
+    ("prev_page", BindingMode::View, Action::PrevPage, |k| {
+        &k.prev_page
+    }),
+    ("sort_next", BindingMode::Global, Action::SortNext, |k| {
+        &k.sort_next
+    }),
+    ("sort_prev", BindingMode::Global, Action::SortPrev, |k| {
+        &k.sort_prev
+    }),
+    (
+        "sort_reverse",
+        BindingMode::Global,
+        Action::SortReverse,
+        |k| &k.sort_reverse,
+    ),
+    (
+        "brightness_up",
+        BindingMode::View,
+        Action::BrightnessUp,
+        |k| &k.brightness_up,
+    ),
+    (
+        "brightness_down",
+        BindingMode::View,
+        Action::BrightnessDown,
+        |k| &k.brightness_down,
+    ),
+    ("contrast_up", BindingMode::View, Action::ContrastUp, |k| {
+        &k.contrast_up
+    }),
+    (
+        "contrast_down",
+        BindingMode::View,
+        Action::ContrastDown,
+        |k| &k.contrast_down,
+    ),
+    ("gamma_up", BindingMode::View, Action::GammaUp, |k| {
+        &k.gamma_up
+    }),
+    ("gamma_down", BindingMode::View, Action::GammaDown, |k| {
+        &k.gamma_down
+    }),
+    (
+        "saturation_up",
+        BindingMode::View,
+        Action::SaturationUp,
+        |k| &k.saturation_up,
+    ),
+    (
+        "saturation_down",
+        BindingMode::View,
+        Action::SaturationDown,
+        |k| &k.saturation_down,
+    ),
+    (
+        "toggle_invert",
+        BindingMode::View,
+        Action::ToggleInvert,
+        |k| &k.toggle_invert,
+    ),
+    (
+        "toggle_grayscale",
+        BindingMode::View,
+        Action::ToggleGrayscale,
+        |k| &k.toggle_grayscale,
+    ),
+    (
+        "reset_adjustments",
+        BindingMode::View,
+        Action::ResetAdjustments,
+        |k| &k.reset_adjustments,
+    ),
+    ("save_image", BindingMode::View, Action::SaveImage, |k| {
+        &k.save_image
+    }),
+    (
+        "toggle_compare",
+        BindingMode::View,
+        Action::ToggleCompare,
+        |k| &k.toggle_compare,
+    ),
+    (
+        "compare_layout",
+        BindingMode::View,
+        Action::CompareNextLayout,
+        |k| &k.compare_layout,
+    ),
+    (
+        "compare_swap",
+        BindingMode::View,
+        Action::CompareSwap,
+        |k| &k.compare_swap,
+    ),
+    (
+        "compare_divider_left",
+        BindingMode::View,
+        Action::CompareDividerLeft,
+        |k| &k.compare_divider_left,
+    ),
+    (
+        "compare_divider_right",
+        BindingMode::View,
+        Action::CompareDividerRight,
+        |k| &k.compare_divider_right,
+    ),
+    ("toggle_crop", BindingMode::View, Action::ToggleCrop, |k| {
+        &k.toggle_crop
+    }),
+    ("crop_aspect", BindingMode::View, Action::CropAspect, |k| {
+        &k.crop_aspect
+    }),
+    ("crop_export", BindingMode::View, Action::CropExport, |k| {
+        &k.crop_export
+    }),
+    (
+        "trash_files",
+        BindingMode::Global,
+        Action::TrashFiles,
+        |k| &k.trash_files,
+    ),
+    ("undo_trash", BindingMode::Global, Action::UndoTrash, |k| {
+        &k.undo_trash
+    }),
+    ("move_files", BindingMode::Global, Action::MoveFiles, |k| {
+        &k.move_files
+    }),
+    ("copy_files", BindingMode::Global, Action::CopyFiles, |k| {
+        &k.copy_files
+    }),
+    (
+        "rename_file",
+        BindingMode::Global,
+        Action::RenameFile,
+        |k| &k.rename_file,
+    ),
+];
+
+impl Action {
+    /// Looks up an action by its keybinding name, for the current view.
+    pub fn from_name(name: &str, is_grid: bool) -> Option<Action> {
+        let current_mode = if is_grid {
+            BindingMode::Grid
+        } else {
+            BindingMode::View
+        };
+        ACTIONS
+            .iter()
+            .find(|(n, mode, ..)| {
+                *n == name && (*mode == current_mode || *mode == BindingMode::Global)
+            })
+            .map(|(_, _, action, _)| *action)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WheelDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Trigger {
+    Key(Key),
+    Mouse(MouseButton),
+    Wheel(WheelDirection),
+}
+
 pub struct Binding {
-    pub key: Key,
+    pub trigger: Trigger,
     pub mods: ModifiersState,
     pub mode: BindingMode,
     pub action: Action,
@@ -82,22 +518,12 @@ impl Binding {
         current_mods: ModifiersState,
         is_grid: bool,
     ) -> Option<Action> {
-        let current_mode = if is_grid {
-            BindingMode::Grid
-        } else {
-            BindingMode::View
-        };
-
-        let result = bindings
-            .iter()
-            .find(|b| {
-                let key_matches = b.key == event.logical_key;
-                let mods_match = modifiers_match(current_mods, b.mods, &b.key);
-                key_matches
-                    && mods_match
-                    && (b.mode == current_mode || b.mode == BindingMode::Global)
-            })
-            .map(|b| b.action);
+        let result = Self::find(
+            bindings,
+            current_mods,
+            is_grid,
+            |trigger| matches!(trigger, Trigger::Key(key) if *key == event.logical_key),
+        );
 
         if result.is_some() {
             return result;
@@ -116,319 +542,62 @@ impl Binding {
         None
     }
 
-    pub fn get_all_bindings() -> Vec<Binding> {
-        let config = AppConfig::get();
-        let mut bindings = Vec::new();
-        let add =
-            |target: &mut Vec<Binding>, keys: &[String], mode: BindingMode, action: Action| {
-                for key_str in keys {
-                    if let Some((key, mods)) = parse_keybinding(key_str) {
-                        target.push(Binding {
-                            key,
-                            mods,
-                            mode,
-                            action,
-                        });
-                    }
-                }
-            };
-
-        let k = &config.keybindings;
-
-        add(&mut bindings, &k.quit.0, BindingMode::Global, Action::Quit);
-        add(
-            &mut bindings,
-            &k.handler_prefix.0,
-            BindingMode::Global,
-            Action::ScriptHandlerPrefix,
-        );
-        add(
-            &mut bindings,
-            &k.toggle_status_bar.0,
-            BindingMode::Global,
-            Action::ToggleStatusBar,
-        );
-        add(
-            &mut bindings,
-            &k.toggle_animation.0,
-            BindingMode::Global,
-            Action::ToggleAnimation,
-        );
-        add(
-            &mut bindings,
-            &k.toggle_slideshow.0,
-            BindingMode::Global,
-            Action::ToggleSlideshow,
-        );
-        add(
-            &mut bindings,
-            &k.image_next.0,
-            BindingMode::Global,
-            Action::NextImage,
-        );
-        add(
-            &mut bindings,
-            &k.image_previous.0,
-            BindingMode::Global,
-            Action::PrevImage,
-        );
-        add(
-            &mut bindings,
-            &k.next_mark.0,
-            BindingMode::Global,
-            Action::NextMark,
-        );
-        add(
-            &mut bindings,
-            &k.prev_mark.0,
-            BindingMode::Global,
-            Action::PrevMark,
-        );
-
-        add(
-            &mut bindings,
-            &k.toggle_grid.0,
-            BindingMode::Global,
-            Action::ToggleGrid,
-        );
-
-        add(
-            &mut bindings,
-            &k.filter_mode.0,
-            BindingMode::Global,
-            Action::FilterMode,
-        );
-
-        add(
-            &mut bindings,
-            &k.mark_file.0,
-            BindingMode::Global,
-            Action::MarkFile,
-        );
-        add(
-            &mut bindings,
-            &k.unmark_all.0,
-            BindingMode::Global,
-            Action::UnmarkAll,
-        );
-        add(
-            &mut bindings,
-            &k.remove_image.0,
-            BindingMode::Global,
-            Action::RemoveImage,
-        );
-        add(
-            &mut bindings,
-            &k.mark_all.0,
-            BindingMode::Global,
-            Action::ToggleMarks,
-        );
-        add(
-            &mut bindings,
-            &k.first_image.0,
-            BindingMode::Global,
-            Action::FirstImage,
-        );
-        add(
-            &mut bindings,
-            &k.last_image.0,
-            BindingMode::Global,
-            Action::LastImage,
-        );
-
-        // View Mode
-        add(
-            &mut bindings,
-            &k.zoom_in.0,
-            BindingMode::View,
-            Action::ZoomIn,
-        );
-        add(
-            &mut bindings,
-            &k.zoom_out.0,
-            BindingMode::View,
-            Action::ZoomOut,
-        );
-        add(
-            &mut bindings,
-            &k.zoom_reset.0,
-            BindingMode::View,
-            Action::ZoomReset,
-        );
-        add(
-            &mut bindings,
-            &k.fit_best.0,
-            BindingMode::View,
-            Action::FitToWindow,
-        ); // 'f'
-        add(
-            &mut bindings,
-            &k.fit_best_no_upscale.0,
-            BindingMode::View,
-            Action::BestFit,
-        ); // 'F'
-        add(
-            &mut bindings,
-            &k.fit_cover.0,
-            BindingMode::View,
-            Action::Cover,
-        ); //C
-        add(
-            &mut bindings,
-            &k.fit_width.0,
-            BindingMode::View,
-            Action::FitWidth,
-        );
-        add(
-            &mut bindings,
-            &k.fit_height.0,
-            BindingMode::View,
-            Action::FitHeight,
-        );
-        add(
-            &mut bindings,
-            &k.view_reset_pan.0,
-            BindingMode::View,
-            Action::ResetView,
-        );
-        add(
-            &mut bindings,
-            &k.image_flip_horizontal.0,
-            BindingMode::View,
-            Action::FlipHorizontal,
-        );
-        add(
-            &mut bindings,
-            &k.image_flip_vertical.0,
-            BindingMode::View,
-            Action::FlipVertical,
-        );
-        add(
-            &mut bindings,
-            &k.rotate_cw.0,
-            BindingMode::View,
-            Action::RotateCW,
-        );
-        add(
-            &mut bindings,
-            &k.rotate_ccw.0,
-            BindingMode::View,
-            Action::RotateCCW,
-        );
+    /// Resolves a mouse button press or a wheel step to an action.
+    pub fn resolve_mouse(
+        trigger: &Trigger,
+        bindings: &[Binding],
+        current_mods: ModifiersState,
+        is_grid: bool,
+    ) -> Option<Action> {
+        Self::find(bindings, current_mods, is_grid, |t| t == trigger)
+    }
 
-        // Pan Keys - Dual Mode
-        // View Mode: Pan
-        add(
-            &mut bindings,
-            &k.view_pan_left.0,
-            BindingMode::View,
-            Action::PanLeft,
-        );
-        add(
-            &mut bindings,
-            &k.view_pan_right.0,
-            BindingMode::View,
-            Action::PanRight,
-        );
-        add(
-            &mut bindings,
-            &k.view_pan_up.0,
-            BindingMode::View,
-            Action::PanUp,
-        );
-        add(
-            &mut bindings,
-            &k.view_pan_down.0,
-            BindingMode::View,
-            Action::PanDown,
-        );
-        add(
-            &mut bindings,
-            &k.view_pan_left_edge.0,
-            BindingMode::View,
-            Action::PanToLeftEdge,
-        );
-        add(
-            &mut bindings,
-            &k.view_pan_right_edge.0,
-            BindingMode::View,
-            Action::PanToRightEdge,
-        );
-        add(
-            &mut bindings,
-            &k.view_pan_top_edge.0,
-            BindingMode::View,
-            Action::PanToTopEdge,
-        );
-        add(
-            &mut bindings,
-            &k.view_pan_bottom_edge.0,
-            BindingMode::View,
-            Action::PanToBottomEdge,
-        );
+    fn find<F>(
+        bindings: &[Binding],
+        current_mods: ModifiersState,
+        is_grid: bool,
+        trigger_matches: F,
+    ) -> Option<Action>
+    where
+        F: Fn(&Trigger) -> bool,
+    {
+        let current_mode = if is_grid {
+            BindingMode::Grid
+        } else {
+            BindingMode::View
+        };
 
-        // Grid Mode: Move
-        add(
-            &mut bindings,
-            &k.view_pan_left.0,
-            BindingMode::Grid,
-            Action::GridMoveLeft,
-        );
-        add(
-            &mut bindings,
-            &k.view_pan_right.0,
-            BindingMode::Grid,
-            Action::GridMoveRight,
-        );
-        add(
-            &mut bindings,
-            &k.view_pan_up.0,
-            BindingMode::Grid,
-            Action::GridMoveUp,
-        );
-        add(
-            &mut bindings,
-            &k.view_pan_down.0,
-            BindingMode::Grid,
-            Action::GridMoveDown,
-        );
+        bindings
+            .iter()
+            .find(|b| {
+                trigger_matches(&b.trigger)
+                    && modifiers_match(current_mods, b.mods, &b.trigger)
+                    && (b.mode == current_mode || b.mode == BindingMode::Global)
+            })
+            .map(|b| b.action)
+    }
 
-        add(
-            &mut bindings,
-            &k.grid_page_up.0,
-            BindingMode::Grid,
-            Action::GridMovePageUp,
-        );
-        add(
-            &mut bindings,
-            &k.grid_page_down.0,
-            BindingMode::Grid,
-            Action::GridMovePageDown,
-        );
-        add(
-            &mut bindings,
-            &k.toggle_alpha.0,
-            BindingMode::Global,
-            Action::ToggleAlpha,
-        );
-        add(
-            &mut bindings,
-            &k.next_frame.0,
-            BindingMode::View,
-            Action::NextFrame,
-        );
-        add(
-            &mut bindings,
-            &k.prev_frame.0,
-            BindingMode::View,
-            Action::PrevFrame,
-        );
+    pub fn get_all_bindings() -> Vec<Binding> {
+        let config = AppConfig::get();
+        let mut bindings = Vec::new();
 
+        for (_, mode, action, keys_of) in ACTIONS {
+            for key_str in &keys_of(&config.keybindings).0 {
+                if let Some((trigger, mods)) = parse_keybinding(key_str) {
+                    bindings.push(Binding {
+                        trigger,
+                        mods,
+                        mode: *mode,
+                        action: *action,
+                    });
+                }
+            }
+        }
         bindings
     }
 }
 
-fn modifiers_match(current: ModifiersState, required: ModifiersState, key: &Key) -> bool {
+fn modifiers_match(current: ModifiersState, required: ModifiersState, trigger: &Trigger) -> bool {
     // We want to ensure that 'required' bits are set in 'current'.
     // And that no *other* primary modifiers (Ctrl, Alt, Shift, Super) are set if not required.
     // This prevents "Ctrl+a" from triggering "a".
@@ -437,7 +606,7 @@ fn modifiers_match(current: ModifiersState, required: ModifiersState, key: &Key)
     // E.g. Shift + 'g' -> "G".
     // If we enforce exact modifier match, Shift+"g" vs Binding("G", NoMods) will fail.
     // So for Character keys, we ignore the Shift modifier state in the comparison.
-    let ignore_shift = matches!(key, Key::Character(_));
+    let ignore_shift = matches!(trigger, Trigger::Key(Key::Character(_)));
 
     let shift = ignore_shift || (current.shift_key() == required.shift_key());
     let ctrl = current.control_key() == required.control_key();
@@ -447,7 +616,7 @@ fn modifiers_match(current: ModifiersState, required: ModifiersState, key: &Key)
     shift && ctrl && alt && super_key
 }
 
-fn parse_keybinding(s: &str) -> Option<(Key, ModifiersState)> {
+fn parse_keybinding(s: &str) -> Option<(Trigger, ModifiersState)> {
     let (mods_part, key_part) = if s == "+" {
         ("", "+")
     } else if s.ends_with("++") {
@@ -473,6 +642,23 @@ fn parse_keybinding(s: &str) -> Option<(Key, ModifiersState)> {
         }
     }
 
+    // Mouse buttons and wheel directions
+    let mouse = match key_part {
+        "MouseLeft" => Some(Trigger::Mouse(MouseButton::Left)),
+        "MouseRight" => Some(Trigger::Mouse(MouseButton::Right)),
+        "MouseMiddle" => Some(Trigger::Mouse(MouseButton::Middle)),
+        "MouseBack" => Some(Trigger::Mouse(MouseButton::Back)),
+        "MouseForward" => Some(Trigger::Mouse(MouseButton::Forward)),
+        "ScrollUp" => Some(Trigger::Wheel(WheelDirection::Up)),
+        "ScrollDown" => Some(Trigger::Wheel(WheelDirection::Down)),
+        "ScrollLeft" => Some(Trigger::Wheel(WheelDirection::Left)),
+        "ScrollRight" => Some(Trigger::Wheel(WheelDirection::Right)),
+        _ => None,
+    };
+    if let Some(trigger) = mouse {
+        return Some((trigger, mods));
+    }
+
     // Parse Key
     let key = match key_part {
         "Left" => Key::Named(NamedKey::ArrowLeft),
@@ -483,6 +669,7 @@ fn parse_keybinding(s: &str) -> Option<(Key, ModifiersState)> {
         "Space" => Key::Named(NamedKey::Space),
         "Backspace" => Key::Named(NamedKey::Backspace),
         "Tab" => Key::Named(NamedKey::Tab),
+        "Delete" | "Del" => Key::Named(NamedKey::Delete),
         "Escape" | "Esc" => Key::Named(NamedKey::Escape),
         "Home" => Key::Named(NamedKey::Home),
         "End" => Key::Named(NamedKey::End),
@@ -492,5 +679,5 @@ fn parse_keybinding(s: &str) -> Option<(Key, ModifiersState)> {
         _ => return None, // Unknown key
     };
 
-    Some((key, mods))
+    Some((Trigger::Key(key), mods))
 }
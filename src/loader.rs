@@ -1,5 +1,7 @@
 use crate::app::AppEvent;
-use crate::image_item::{FrameData, ImageFormat, ImageItem, LoadedImage};
+use crate::image_item::{Details, FrameData, ImageFormat, ImageItem, LoadedImage};
+use crate::sorting::SortMode;
+use crate::tiles::{TileKey, TiledSource};
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use exif::{In, Tag};
 use image::{AnimationDecoder, ImageBuffer, ImageReader, Rgba};
@@ -18,24 +20,55 @@ use tiny_skia::Pixmap;
 use walkdir::WalkDir;
 use winit::event_loop::EventLoopProxy;
 
+/// How much of an archive entry is read to list it.
+const ENTRY_PROBE_LIMIT: u64 = 1 << 20;
+
 // Discovery
 pub fn identify_format(path: &Path) -> Result<ImageFormat, String> {
+    if let Some(data) = crate::stdin::data(path) {
+        return in_memory_format(&data[..data.len().min(1024)]);
+    }
+    if let Some((archive, entry)) = crate::archive::split(path) {
+        return in_memory_format(&crate::archive::read_head(&archive, &entry, 1024)?);
+    }
+
     let mut file = File::open(path).map_err(|e| e.to_string())?;
     let mut buffer = [0; 1024];
     let n = file.read(&mut buffer).map_err(|e| e.to_string())?;
-    let data = &buffer[..n];
 
+    if crate::raw::is_raw(path) {
+        return Ok(ImageFormat::Raw);
+    }
+    sniff_format(&buffer[..n])
+}
+
+/// Only some formats are decoded from memory, for piped images and archive entries.
+fn in_memory_format(data: &[u8]) -> Result<ImageFormat, String> {
+    match sniff_format(data)? {
+        format @ (ImageFormat::Raster | ImageFormat::Svg | ImageFormat::Psd) => Ok(format),
+        format => Err(format!("{:?} images must be files of their own", format)),
+    }
+}
+
+/// Tells the format from the first bytes of a file.
+fn sniff_format(data: &[u8]) -> Result<ImageFormat, String> {
     let kind = infer::get(data);
     let mime = kind.map(|k| k.mime_type()).unwrap_or("unknown/raw");
 
     match mime {
         "image/svg+xml" => Ok(ImageFormat::Svg),
+        "image/heif" | "image/avif" if crate::heif::is_supported() => Ok(ImageFormat::Heif),
+        "image/heif" | "image/avif" => Err(format!("{} needs the heif feature", mime)),
+        "image/vnd.adobe.photoshop" => Ok(ImageFormat::Psd),
+        "image/jxl" => Ok(ImageFormat::Jxl),
         m if m.starts_with("image/") => Ok(ImageFormat::Raster),
         _ => {
             // Manual sniffing for SVG
             let content = String::from_utf8_lossy(data).to_lowercase();
             if content.contains("<svg") {
                 Ok(ImageFormat::Svg)
+            } else if crate::pages::is_cursor(data) {
+                Ok(ImageFormat::Raster)
             } else {
                 Err(format!("Not a recognized image format: {}", mime))
             }
@@ -43,18 +76,21 @@ pub fn identify_format(path: &Path) -> Result<ImageFormat, String> {
     }
 }
 
-pub fn probe_image(path: &Path, format: ImageFormat) -> Result<(u32, u32), String> {
-    match format {
+/// Reads the dimensions and the file metadata used for sorting.
+pub fn probe_item(path: &Path, format: ImageFormat) -> Result<ImageItem, String> {
+    if let Some(data) = crate::stdin::data(path) {
+        return probe_data(path, data, data.len() as u64, format);
+    }
+    if let Some((archive, entry)) = crate::archive::split(path) {
+        return probe_entry(path, &archive, &entry, format);
+    }
+
+    let mut date_taken = None;
+
+    let (width, height) = match format {
         ImageFormat::Svg => {
-            let opt = Options {
-                resources_dir: path.parent().map(|p| p.to_path_buf()),
-                fontdb: Arc::new(crate::utils::get_svg_font_db().clone()),
-                ..Default::default()
-            };
             let data = std::fs::read(path).map_err(|e| e.to_string())?;
-            let tree = Tree::from_data(&data, &opt).map_err(|e| e.to_string())?;
-            let size = tree.size().to_int_size();
-            Ok((size.width(), size.height()))
+            svg_size(&data, path)?
         }
         ImageFormat::Raster => {
             let reader = ImageReader::open(path)
@@ -62,69 +98,343 @@ pub fn probe_image(path: &Path, format: ImageFormat) -> Result<(u32, u32), Strin
                 .with_guessed_format()
                 .map_err(|e| e.to_string())?;
 
-            let (mut width, mut height) = reader.into_dimensions().map_err(|e| e.to_string())?;
-
-            if let Some(orientation) = get_exif_orientation_path(path) {
-                if [5, 6, 7, 8].contains(&orientation) {
-                    std::mem::swap(&mut width, &mut height);
+            let size = match reader.format() {
+                Some(_) => reader.into_dimensions().map_err(|e| e.to_string())?,
+                // Cursors are the only raster files the image crate can't tell apart
+                None => {
+                    let data = std::fs::read(path).map_err(|e| e.to_string())?;
+                    crate::pages::cursor_size(&data)?
                 }
-            }
-            Ok((width, height))
+            };
+
+            let exif = read_exif_path(path);
+            date_taken = exif.as_ref().and_then(exif_date_taken);
+            oriented_size(size, exif.as_ref())
+        }
+        ImageFormat::Heif => {
+            // The reported size already has the rotation applied
+            date_taken = read_exif_path(path).and_then(|exif| exif_date_taken(&exif));
+            crate::heif::probe(path)?
         }
+        ImageFormat::Jxl => {
+            date_taken = crate::jxl::read_exif(path).and_then(|exif| exif_date_taken(&exif));
+            crate::jxl::probe(path)?
+        }
+        ImageFormat::Psd => crate::psd::probe(path)?,
+        ImageFormat::Raw => {
+            let (width, height, exif) = crate::raw::probe(path)?;
+            date_taken = exif.and_then(|exif| exif_date_taken(&exif));
+            (width, height)
+        }
+    };
+
+    let meta = std::fs::metadata(path).map_err(|e| e.to_string())?;
+
+    Ok(ImageItem {
+        path: path.to_path_buf(),
+        width,
+        height,
+        format,
+        file_size: meta.len(),
+        mtime: meta.modified().ok(),
+        ctime: change_time(&meta),
+        date_taken,
+        details: Default::default(),
+    })
+}
+
+/// Probes an archive entry from its first bytes, which hold the header of
+/// every format but SVG. The whole entry is only read when they don't.
+fn probe_entry(
+    path: &Path,
+    archive: &Path,
+    entry: &str,
+    format: ImageFormat,
+) -> Result<ImageItem, String> {
+    let limit = if format == ImageFormat::Svg {
+        u64::MAX
+    } else {
+        ENTRY_PROBE_LIMIT
+    };
+    let (head, size) = crate::archive::read_head_sized(archive, entry, limit)?;
+    match probe_data(path, &head, size, format) {
+        // Like JPEGs with large metadata, or TIFFs with their directory last
+        Err(_) if (head.len() as u64) < size => {
+            probe_data(path, &crate::archive::read(archive, entry)?, size, format)
+        }
+        result => result,
+    }
+}
+
+/// Like `probe_item`, for a virtual image. Archive entries take the times of
+/// their archive, piped images have none.
+fn probe_data(
+    path: &Path,
+    data: &[u8],
+    file_size: u64,
+    format: ImageFormat,
+) -> Result<ImageItem, String> {
+    let exif = exif::Reader::new()
+        .read_from_container(&mut Cursor::new(data))
+        .ok();
+
+    let (width, height) = match format {
+        ImageFormat::Svg => svg_size(data, path)?,
+        ImageFormat::Psd => crate::psd::size(data)?,
+        _ if crate::pages::is_cursor(data) => crate::pages::cursor_size(data)?,
+        _ => {
+            let size = ImageReader::new(Cursor::new(data))
+                .with_guessed_format()
+                .map_err(|e| e.to_string())?
+                .into_dimensions()
+                .map_err(|e| e.to_string())?;
+            oriented_size(size, exif.as_ref())
+        }
+    };
+
+    let meta = crate::archive::split(path).and_then(|(archive, _)| std::fs::metadata(archive).ok());
+
+    Ok(ImageItem {
+        path: path.to_path_buf(),
+        width,
+        height,
+        format,
+        file_size,
+        mtime: meta.as_ref().and_then(|m| m.modified().ok()),
+        ctime: meta.as_ref().and_then(change_time),
+        date_taken: exif.as_ref().and_then(exif_date_taken),
+        details: Default::default(),
+    })
+}
+
+fn svg_size(data: &[u8], path: &Path) -> Result<(u32, u32), String> {
+    let opt = Options {
+        resources_dir: path.parent().map(|p| p.to_path_buf()),
+        fontdb: Arc::new(crate::utils::get_svg_font_db().clone()),
+        ..Default::default()
+    };
+    let tree = Tree::from_data(data, &opt).map_err(|e| e.to_string())?;
+    let size = tree.size().to_int_size();
+    Ok((size.width(), size.height()))
+}
+
+/// Swaps the stored size when the EXIF orientation turns the image sideways.
+fn oriented_size((width, height): (u32, u32), exif: Option<&exif::Exif>) -> (u32, u32) {
+    let orientation = exif
+        .and_then(|e| e.get_field(Tag::Orientation, In::PRIMARY))
+        .and_then(|f| f.value.get_uint(0));
+    if orientation.is_some_and(|o| [5, 6, 7, 8].contains(&o)) {
+        (height, width)
+    } else {
+        (width, height)
     }
 }
 
-fn get_exif_orientation_path(path: &Path) -> Option<u32> {
+/// The EXIF orientation of a file, as the transform that shows it upright.
+pub fn file_orientation(path: &Path) -> crate::image_item::Transform {
+    let orientation = if let Some(data) = crate::stdin::data(path) {
+        exif_orientation(data)
+    } else if let Some((archive, entry)) = crate::archive::split(path) {
+        // EXIF data sits at the start of the file
+        crate::archive::read_head(&archive, &entry, 64 * 1024)
+            .map(|head| exif_orientation(&head))
+            .unwrap_or(1)
+    } else {
+        read_exif_path(path)
+            .and_then(|exif| {
+                exif.get_field(Tag::Orientation, In::PRIMARY)?
+                    .value
+                    .get_uint(0)
+            })
+            .unwrap_or(1)
+    };
+    image::metadata::Orientation::from_exif(orientation as u8)
+        .map(crate::image_item::Transform::from_orientation)
+        .unwrap_or_default()
+}
+
+fn read_exif_path(path: &Path) -> Option<exif::Exif> {
     let file = File::open(path).ok()?;
     let mut reader = std::io::BufReader::new(&file);
-    let exif = exif::Reader::new().read_from_container(&mut reader).ok()?;
-    exif.get_field(Tag::Orientation, In::PRIMARY)
+    exif::Reader::new().read_from_container(&mut reader).ok()
+}
+
+fn exif_date_taken(exif: &exif::Exif) -> Option<String> {
+    exif_text(exif, Tag::DateTimeOriginal).or_else(|| exif_text(exif, Tag::DateTime))
+}
+
+fn exif_text(exif: &exif::Exif, tag: Tag) -> Option<String> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        exif::Value::Ascii(values) => values
+            .first()
+            .map(|v| String::from_utf8_lossy(v).trim().to_string())
+            .filter(|s| !s.is_empty()),
+        _ => None,
+    }
+}
+
+fn exif_rational(exif: &exif::Exif, tag: Tag) -> Option<f64> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        exif::Value::Rational(values) => values.first().map(|r| r.to_f64()),
+        _ => None,
+    }
+    .filter(|v| v.is_finite() && *v > 0.0)
+}
+
+/// The MIME type and the camera settings of an image, see `ImageItem::details`.
+fn read_details(path: &Path, format: ImageFormat) -> Details {
+    let (head, exif) = match crate::virtual_path::read(path).and_then(Result::ok) {
+        Some(data) => {
+            let exif = exif::Reader::new()
+                .read_from_container(&mut Cursor::new(&data))
+                .ok();
+            (data, exif)
+        }
+        None => {
+            let mut head = Vec::new();
+            let _ = File::open(path).and_then(|f| f.take(1024).read_to_end(&mut head));
+            let exif = match format {
+                ImageFormat::Svg | ImageFormat::Psd => None,
+                ImageFormat::Jxl => crate::jxl::read_exif(path),
+                ImageFormat::Raw => crate::raw::probe(path).ok().and_then(|(_, _, exif)| exif),
+                ImageFormat::Raster | ImageFormat::Heif => read_exif_path(path),
+            };
+            (head, exif)
+        }
+    };
+
+    let mime = match infer::get(&head) {
+        Some(kind) => Some(kind.mime_type().to_string()),
+        None if format == ImageFormat::Svg => Some("image/svg+xml".to_string()),
+        None => None,
+    };
+    let Some(exif) = exif else {
+        return Details {
+            mime,
+            ..Default::default()
+        };
+    };
+
+    // Models usually repeat the make, e.g. "Canon EOS R5"
+    let make = exif_text(&exif, Tag::Make);
+    let camera = match (make, exif_text(&exif, Tag::Model)) {
+        (Some(make), Some(model)) if !model.starts_with(&make) => {
+            Some(format!("{} {}", make, model))
+        }
+        (make, model) => model.or(make),
+    };
+    let exposure = exif_rational(&exif, Tag::ExposureTime).map(|t| {
+        if t < 1.0 {
+            format!("1/{}s", (1.0 / t).round())
+        } else {
+            format!("{}s", (t * 10.0).round() / 10.0)
+        }
+    });
+    let iso = exif
+        .get_field(Tag::PhotographicSensitivity, In::PRIMARY)
         .and_then(|f| f.value.get_uint(0))
+        .map(|iso| format!("ISO {}", iso));
+
+    Details {
+        mime,
+        camera,
+        lens: exif_text(&exif, Tag::LensModel),
+        exposure,
+        aperture: exif_rational(&exif, Tag::FNumber)
+            .map(|f| format!("f/{}", (f * 10.0).round() / 10.0)),
+        iso,
+        focal_length: exif_rational(&exif, Tag::FocalLength)
+            .map(|f| format!("{}mm", (f * 10.0).round() / 10.0)),
+    }
 }
 
-pub fn spawn_discovery_worker(
-    paths: Vec<String>,
+#[cfg(unix)]
+fn change_time(meta: &std::fs::Metadata) -> Option<std::time::SystemTime> {
+    use std::os::unix::fs::MetadataExt;
+    let secs = u64::try_from(meta.ctime()).ok()?;
+    Some(std::time::UNIX_EPOCH + Duration::new(secs, meta.ctime_nsec() as u32))
+}
+
+#[cfg(not(unix))]
+fn change_time(meta: &std::fs::Metadata) -> Option<std::time::SystemTime> {
+    meta.created().ok()
+}
+
+fn collect_files(
+    paths: &[PathBuf],
     recursive: bool,
     max_depth: Option<usize>,
     include_hidden: bool,
-    proxy: EventLoopProxy<AppEvent>,
-) {
-    thread::spawn(move || {
-        let mut files = Vec::new();
-        for p in paths {
-            if !Path::new(&p).exists() {
-                continue;
-            }
+) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for p in paths {
+        if crate::stdin::data(p).is_some() {
+            files.push(p.clone());
+            continue;
+        }
+        if !p.exists() {
+            continue;
+        }
+
+        let mut builder = WalkDir::new(p);
+        if let Some(d) = max_depth {
+            builder = builder.max_depth(d);
+        } else if !recursive {
+            builder = builder.max_depth(1);
+        }
 
-            let mut builder = WalkDir::new(&p);
-            if let Some(d) = max_depth {
-                builder = builder.max_depth(d);
-            } else if !recursive {
-                builder = builder.max_depth(1);
+        let walker = builder.into_iter().filter_entry(move |e| {
+            if include_hidden {
+                return true;
             }
+            // Always include the root path provided by the user, even if it is hidden
+            if e.depth() == 0 {
+                return true;
+            }
+            !e.file_name()
+                .to_str()
+                .map(|s| s.starts_with('.'))
+                .unwrap_or(false)
+        });
 
-            let walker = builder.into_iter().filter_entry(move |e| {
-                if include_hidden {
-                    return true;
-                }
-                // Always include the root path provided by the user, even if it is hidden
-                if e.depth() == 0 {
-                    return true;
+        for entry in walker
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().is_file() && !crate::save::is_backup(e.path()))
+        {
+            let path = entry.path();
+            if crate::archive::is_archive(path) {
+                // Archives are browsed like directories holding their entries
+                match crate::archive::entries(path) {
+                    Ok(entries) => files.extend(entries),
+                    Err(e) => crate::rsiv_warn!("Failed to read archive {:?}: {}", path, e),
                 }
-                !e.file_name()
-                    .to_str()
-                    .map(|s| s.starts_with('.'))
-                    .unwrap_or(false)
-            });
-
-            for entry in walker
-                .filter_map(|e| e.ok())
-                .filter(|e| e.path().is_file())
-            {
-                files.push(entry.path().to_path_buf());
+            } else {
+                files.push(path.to_path_buf());
             }
         }
-        files.sort();
+    }
+    files
+}
+
+/// Lists, sorts and probes the images under `paths`. `start` is listed even
+/// when the walk skips it, as a hidden file the user opened on its own.
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_discovery_worker(
+    paths: Vec<PathBuf>,
+    start: Option<PathBuf>,
+    recursive: bool,
+    max_depth: Option<usize>,
+    include_hidden: bool,
+    sort: (SortMode, bool),
+    generation: usize,
+    proxy: EventLoopProxy<AppEvent>,
+) {
+    thread::spawn(move || {
+        let mut files = collect_files(&paths, recursive, max_depth, include_hidden);
+        if let Some(start) = start.filter(|s| !files.contains(s)) {
+            files.push(start);
+        }
+        crate::sorting::sort_paths(&mut files, sort.0, sort.1);
 
         // Identify Format
         let tasks: Vec<(PathBuf, ImageFormat)> = files
@@ -135,36 +445,49 @@ pub fn spawn_discovery_worker(
             })
             .collect();
 
-        let _ = proxy.send_event(AppEvent::InitialCount(tasks.len()));
+        let _ = proxy.send_event(AppEvent::InitialCount(generation, tasks.len()));
 
         // Probe Dimensions
         tasks
             .into_par_iter()
             .enumerate()
-            .for_each(|(idx, (path, format))| match probe_image(&path, format) {
-                Ok((width, height)) => {
-                    let item = ImageItem {
-                        path,
-                        width,
-                        height,
-                        format,
-                    };
-                    let _ = proxy.send_event(AppEvent::MetadataLoaded(idx, item));
+            .for_each(|(idx, (path, format))| match probe_item(&path, format) {
+                Ok(item) => {
+                    let _ = proxy.send_event(AppEvent::MetadataLoaded(generation, idx, item));
                 }
                 Err(e) => {
-                    let _ = proxy.send_event(AppEvent::MetadataError(idx, path, e));
+                    let _ = proxy.send_event(AppEvent::MetadataError(generation, idx, path, e));
                 }
             });
 
-        let _ = proxy.send_event(AppEvent::DiscoveryComplete);
+        let _ = proxy.send_event(AppEvent::DiscoveryComplete(generation));
+    });
+}
+
+/// Adds images to a running session. Each one is inserted at its sorted
+/// position, like a file showing up in a watched directory.
+pub fn spawn_append_worker(paths: Vec<PathBuf>, recursive: bool, proxy: EventLoopProxy<AppEvent>) {
+    thread::spawn(move || {
+        collect_files(&paths, recursive, None, false)
+            .into_par_iter()
+            .filter_map(|path| {
+                let format = identify_format(&path).ok()?;
+                probe_item(&path, format).ok()
+            })
+            .for_each(|item| {
+                let _ = proxy.send_event(AppEvent::FileChanged(item));
+            });
     });
 }
 
 // Loading
 
+#[allow(clippy::enum_variant_names)]
 pub enum LoadRequest {
     LoadImage(PathBuf, ImageFormat),
     LoadThumbnail(PathBuf, ImageFormat, u32), // path, format, target_size
+    LoadTiles(Arc<TiledSource>, TileKey, Vec<TileKey>), // source, batch, tiles
+    LoadDetails(PathBuf, ImageFormat),
 }
 
 pub struct Loader {
@@ -198,6 +521,11 @@ impl Loader {
         let _ = self.urgent_tx.send(LoadRequest::LoadImage(path, format));
     }
 
+    /// Details are read for the image shown, so they skip the thumbnails.
+    pub fn request_details(&self, path: PathBuf, format: ImageFormat) {
+        let _ = self.urgent_tx.send(LoadRequest::LoadDetails(path, format));
+    }
+
     pub fn request_thumbnail(&self, path: PathBuf, format: ImageFormat, size: u32) {
         let (lock, cvar) = &*self.background_stack;
         let mut stack = lock.lock().unwrap();
@@ -205,19 +533,38 @@ impl Loader {
         // LIFO: Push to the front so the newest scroll target is handled first
         stack.push_front(LoadRequest::LoadThumbnail(path, format, size));
 
+        self.drop_oldest(&mut stack);
+
+        cvar.notify_one();
+    }
+
+    pub fn request_tiles(&self, source: Arc<TiledSource>, batch: TileKey, keys: Vec<TileKey>) {
+        let (lock, cvar) = &*self.background_stack;
+        let mut stack = lock.lock().unwrap();
+
+        // LIFO as well: tiles for the latest viewport come first
+        stack.push_front(LoadRequest::LoadTiles(source, batch, keys));
+        self.drop_oldest(&mut stack);
+
+        cvar.notify_one();
+    }
+
+    fn drop_oldest(&self, stack: &mut VecDeque<LoadRequest>) {
         // If the stack gets too huge (e.g. > 200), drop the oldest requests
         // Removing from the back drops the oldest (least priority) items
         if stack.len() > 200 {
             if let Some(dropped_req) = stack.pop_back() {
-                match dropped_req {
-                    LoadRequest::LoadThumbnail(p, _, _) | LoadRequest::LoadImage(p, _) => {
-                        let _ = self.proxy.send_event(AppEvent::LoadCancelled(p));
+                let event = match dropped_req {
+                    LoadRequest::LoadThumbnail(p, _, _)
+                    | LoadRequest::LoadImage(p, _)
+                    | LoadRequest::LoadDetails(p, _) => AppEvent::LoadCancelled(p),
+                    LoadRequest::LoadTiles(source, batch, _) => {
+                        AppEvent::TilesCancelled(source.path.clone(), batch)
                     }
-                }
+                };
+                let _ = self.proxy.send_event(event);
             }
         }
-
-        cvar.notify_one();
     }
 }
 
@@ -258,14 +605,26 @@ fn worker_loop(
 
 fn process_request(req: LoadRequest, proxy: &EventLoopProxy<AppEvent>) {
     match req {
-        LoadRequest::LoadImage(path, format) => match load_full_image(&path, format) {
-            Ok(img) => {
-                let _ = proxy.send_event(AppEvent::ImagePixelsLoaded(path, Arc::new(img)));
-            }
-            Err(e) => {
-                let _ = proxy.send_event(AppEvent::LoadError(path, e));
+        LoadRequest::LoadImage(path, format) => {
+            let result = if format == ImageFormat::Jxl {
+                // A progressive pass goes out as its own update, the full image replaces it
+                let (preview_path, preview_proxy) = (path.clone(), proxy.clone());
+                crate::jxl::decode(&path, move |preview| {
+                    let _ = preview_proxy
+                        .send_event(AppEvent::ImagePixelsLoaded(preview_path, Arc::new(preview)));
+                })
+            } else {
+                load_full_image(&path, format)
+            };
+            match result {
+                Ok(img) => {
+                    let _ = proxy.send_event(AppEvent::ImagePixelsLoaded(path, Arc::new(img)));
+                }
+                Err(e) => {
+                    let _ = proxy.send_event(AppEvent::LoadError(path, e));
+                }
             }
-        },
+        }
         LoadRequest::LoadThumbnail(path, format, size) => {
             match load_thumbnail(&path, format, size) {
                 Ok(thumb) => {
@@ -277,17 +636,53 @@ fn process_request(req: LoadRequest, proxy: &EventLoopProxy<AppEvent>) {
                 }
             }
         }
+        LoadRequest::LoadDetails(path, format) => {
+            let details = read_details(&path, format);
+            let _ = proxy.send_event(AppEvent::DetailsLoaded(path, details));
+        }
+        LoadRequest::LoadTiles(source, batch, keys) => {
+            let path = source.path.clone();
+            match source.decode_tiles(&keys) {
+                Ok(tiles) => {
+                    let tiles = tiles.into_iter().map(|(k, t)| (k, Arc::new(t))).collect();
+                    let _ = proxy.send_event(AppEvent::TilesLoaded(path, batch, tiles));
+                }
+                Err(e) => {
+                    let _ = proxy.send_event(AppEvent::TilesError(path, batch, e));
+                }
+            }
+        }
     }
 }
 
-fn load_full_image(path: &Path, format: ImageFormat) -> Result<LoadedImage, String> {
+/// Maps a file instead of reading it, so decoders that only look at parts of
+/// it (headers, tiles, embedded previews) don't pull in the rest.
+pub fn map_file(path: &Path) -> Result<Mmap, String> {
     let file = File::open(path).map_err(|e| e.to_string())?;
-    let mmap = unsafe { Mmap::map(&file).map_err(|e| e.to_string())? };
+    // The file may change while mapped, which decoders treat as corrupt data
+    unsafe { Mmap::map(&file).map_err(|e| e.to_string()) }
+}
+
+fn load_full_image(path: &Path, format: ImageFormat) -> Result<LoadedImage, String> {
+    if let Some(data) = crate::virtual_path::read(path) {
+        let data = data?;
+        return match format {
+            ImageFormat::Svg => decode_svg(&data, path),
+            ImageFormat::Psd => crate::psd::decode(&data),
+            _ => decode_raster(&data, path),
+        };
+    }
+
+    let mmap = map_file(path)?;
     let data = &mmap[..];
 
     match format {
         ImageFormat::Svg => decode_svg(data, path),
         ImageFormat::Raster => decode_raster(data, path),
+        ImageFormat::Heif => crate::heif::decode(data),
+        ImageFormat::Jxl => crate::jxl::decode(path, |_| {}),
+        ImageFormat::Raw => crate::raw::decode(path),
+        ImageFormat::Psd => crate::psd::decode(data),
     }
 }
 
@@ -296,28 +691,49 @@ fn load_thumbnail(
     format: ImageFormat,
     size: u32,
 ) -> Result<(u32, u32, Vec<u8>), String> {
-    if format == ImageFormat::Raster {
-        let file = File::open(path).map_err(|e| e.to_string())?;
-        let mmap = unsafe { Mmap::map(&file).map_err(|e| e.to_string())? };
-        let data = &mmap[..];
-
-        let mut reader = ImageReader::new(Cursor::new(data))
-            .with_guessed_format()
-            .map_err(|e| e.to_string())?;
-
-        let mut limits = image::Limits::default();
-        limits.max_image_width = Some(32768);
-        limits.max_image_height = Some(32768);
-        limits.max_alloc = Some(4 * 1024 * 1024 * 1024);
-        reader.limits(limits);
+    // Virtual images have no file to key the cache
+    if crate::virtual_path::is_virtual(path) {
+        return generate_thumbnail(path, format, size);
+    }
+    if let Some(thumb) = crate::thumbnail_cache::lookup(path, size) {
+        return Ok(thumb);
+    }
 
-        // Instantly decodes the first frame of animated GIFs/PNGs/WebPs
-        // without loading all frames into memory!
-        let img = reader.decode().map_err(|e| e.to_string())?;
-        let img = apply_exif_orientation(img, data);
+    // Generate at a standard size so other applications can reuse it
+    match crate::thumbnail_cache::bucket_size(size).filter(|_| crate::thumbnail_cache::is_enabled())
+    {
+        Some(bucket) => {
+            let thumb = generate_thumbnail(path, format, bucket)?;
+            crate::thumbnail_cache::store(path, bucket, &thumb);
+            Ok(crate::thumbnail_cache::shrink_to(thumb, size))
+        }
+        None => generate_thumbnail(path, format, size),
+    }
+}
 
-        let thumb = img.thumbnail(size, size);
-        return Ok((thumb.width(), thumb.height(), thumb.to_rgba8().into_raw()));
+fn generate_thumbnail(
+    path: &Path,
+    format: ImageFormat,
+    size: u32,
+) -> Result<(u32, u32, Vec<u8>), String> {
+    if format == ImageFormat::Heif {
+        return crate::heif::thumbnail(path, size);
+    }
+    if format == ImageFormat::Raw {
+        return crate::raw::thumbnail(path, size);
+    }
+    if format == ImageFormat::Raster {
+        if let Some(data) = crate::virtual_path::read(path) {
+            let data = data?;
+            if !crate::pages::is_cursor(&data) {
+                return raster_thumbnail(path, &data, size);
+            }
+        } else {
+            let mmap = map_file(path)?;
+            if !crate::pages::is_cursor(&mmap) {
+                return raster_thumbnail(path, &mmap, size);
+            }
+        }
     }
 
     let img = load_full_image(path, format)?;
@@ -349,24 +765,70 @@ fn load_thumbnail(
     Err("No frames".to_string())
 }
 
-fn apply_exif_orientation(img: image::DynamicImage, data: &[u8]) -> image::DynamicImage {
-    let mut reader = std::io::Cursor::new(data);
+fn raster_thumbnail(path: &Path, data: &[u8], size: u32) -> Result<(u32, u32, Vec<u8>), String> {
+    let mut reader = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?;
 
-    // Attempt to read EXIF data
-    let exif = match exif::Reader::new().read_from_container(&mut reader) {
-        Ok(e) => e,
-        Err(_) => return img, // No EXIF or error reading it -> return original
-    };
+    let mut limits = image::Limits::default();
+    limits.max_image_width = Some(32768);
+    limits.max_image_height = Some(32768);
+    limits.max_alloc = Some(4 * 1024 * 1024 * 1024);
+    reader.limits(limits);
 
-    // Get Orientation Tag
-    let orientation = match exif.get_field(Tag::Orientation, In::PRIMARY) {
-        Some(field) => match field.value.get_uint(0) {
-            Some(v @ 1..=8) => v,
-            _ => return img,
-        },
-        None => return img,
-    };
+    // Instantly decodes the first frame of animated GIFs/PNGs/WebPs
+    // without loading all frames into memory!
+    let (width, height) = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?
+        .into_dimensions()
+        .map_err(|e| e.to_string())?;
+    if crate::tiles::should_tile(width, height) {
+        // Archive entries and piped images have no file to tile
+        match TiledSource::open(path) {
+            Ok(source) => {
+                let overview = source.overview;
+                let img_buf = ImageBuffer::<Rgba<u8>, Vec<u8>>::from_raw(
+                    overview.width,
+                    overview.height,
+                    overview.pixels,
+                )
+                .ok_or("Invalid overview")?;
+                let thumb = image::DynamicImage::ImageRgba8(img_buf).thumbnail(size, size);
+                return Ok((thumb.width(), thumb.height(), thumb.to_rgba8().into_raw()));
+            }
+            Err(e) => crate::rsiv_warn!("Tiled decoding unavailable for {:?}: {}", path, e),
+        }
+    }
+    check_memory_before_decode(width, height, 1)?;
+
+    let img = reader.decode().map_err(|e| e.to_string())?;
+    let img = apply_exif_orientation(img, data);
+
+    let thumb = img.thumbnail(size, size);
+    Ok((thumb.width(), thumb.height(), thumb.to_rgba8().into_raw()))
+}
+
+fn apply_exif_orientation(img: image::DynamicImage, data: &[u8]) -> image::DynamicImage {
+    apply_orientation(img, exif_orientation(data))
+}
+
+/// The EXIF orientation value (1 to 8) of a file, 1 when it has none.
+pub fn exif_orientation(data: &[u8]) -> u32 {
+    exif::Reader::new()
+        .read_from_container(&mut std::io::Cursor::new(data))
+        .ok()
+        .and_then(|exif| {
+            exif.get_field(Tag::Orientation, In::PRIMARY)?
+                .value
+                .get_uint(0)
+        })
+        .filter(|o| (1..=8).contains(o))
+        .unwrap_or(1)
+}
 
+/// Applies an EXIF orientation value (1 to 8).
+pub fn apply_orientation(img: image::DynamicImage, orientation: u32) -> image::DynamicImage {
     // Apply Transformations
     use image::imageops::{flip_horizontal, flip_vertical, rotate180, rotate270, rotate90};
 
@@ -389,13 +851,12 @@ fn apply_exif_orientation(img: image::DynamicImage, data: &[u8]) -> image::Dynam
     }
 }
 
-fn check_memory_before_decode(
+pub fn check_memory_before_decode(
     required_width: u32,
     required_height: u32,
     frames: u32,
 ) -> Result<(), String> {
-    let required_bytes =
-        (required_width as u64) * (required_height as u64) * 4 * (frames as u64);
+    let required_bytes = (required_width as u64) * (required_height as u64) * 4 * (frames as u64);
 
     let mut sys = System::new();
     sys.refresh_memory();
@@ -431,17 +892,38 @@ fn decode_svg(file_data: &[u8], path_obj: &Path) -> Result<LoadedImage, String>
     let mut pixmap = Pixmap::new(width, height).ok_or("Failed to create pixmap")?;
     resvg::render(&tree, usvg::Transform::default(), &mut pixmap.as_mut());
 
-    Ok(LoadedImage {
+    Ok(LoadedImage::new(
         width,
         height,
-        frames: vec![FrameData {
+        vec![FrameData {
             pixels: pixmap.take(),
             delay: Duration::MAX,
         }],
-    })
+    ))
 }
 
-fn decode_raster(file_data: &[u8], _path: &Path) -> Result<LoadedImage, String> {
+/// Sized by the first frame, frames without a delay get 100ms.
+pub fn collect_animation(anim_frames: Vec<image::Frame>) -> LoadedImage {
+    let first = anim_frames[0].buffer();
+    let (width, height) = (first.width(), first.height());
+
+    let mut frames = Vec::with_capacity(anim_frames.len());
+    for f in anim_frames {
+        let (n, d) = f.delay().numer_denom_ms();
+        let delay = if d == 0 {
+            Duration::from_millis(100)
+        } else {
+            Duration::from_millis(n as u64 / d as u64)
+        };
+        frames.push(FrameData {
+            pixels: f.into_buffer().into_raw(),
+            delay,
+        });
+    }
+    LoadedImage::new(width, height, frames)
+}
+
+fn decode_raster(file_data: &[u8], path: &Path) -> Result<LoadedImage, String> {
     let cursor = Cursor::new(file_data);
     let format = ImageReader::new(cursor)
         .with_guessed_format()
@@ -477,27 +959,12 @@ fn decode_raster(file_data: &[u8], _path: &Path) -> Result<LoadedImage, String>
 
     // If it yielded more than 1 frame, it's an animation!
     if let Some(anim_frames) = frames_opt.filter(|f| f.len() > 1) {
-        let first = anim_frames[0].buffer();
-        let (width, height) = (first.width(), first.height());
-
-        let mut frames = Vec::with_capacity(anim_frames.len());
-        for f in anim_frames {
-            let (n, d) = f.delay().numer_denom_ms();
-            let delay = if d == 0 {
-                Duration::from_millis(100)
-            } else {
-                Duration::from_millis(n as u64 / d as u64)
-            };
-            frames.push(FrameData {
-                pixels: f.into_buffer().into_raw(),
-                delay,
-            });
-        }
-        return Ok(LoadedImage {
-            width,
-            height,
-            frames,
-        });
+        return Ok(collect_animation(anim_frames));
+    }
+
+    // Multi-page TIFFs and every size of an icon
+    if let Some(document) = crate::pages::decode(file_data, path)? {
+        return Ok(document);
     }
 
     // Static image (or a GIF/WebP with only 1 frame)
@@ -507,6 +974,12 @@ fn decode_raster(file_data: &[u8], _path: &Path) -> Result<LoadedImage, String>
         .map_err(|e| e.to_string())?;
 
     let (width, height) = reader.into_dimensions().map_err(|e| e.to_string())?;
+    if crate::tiles::should_tile(width, height) {
+        match TiledSource::open(path) {
+            Ok(source) => return Ok(LoadedImage::from_tiles(source)),
+            Err(e) => crate::rsiv_warn!("Tiled decoding unavailable for {:?}: {}", path, e),
+        }
+    }
     check_memory_before_decode(width, height, 1)?;
 
     let cursor = Cursor::new(file_data);
@@ -531,12 +1004,12 @@ fn decode_raster(file_data: &[u8], _path: &Path) -> Result<LoadedImage, String>
     let img = apply_exif_orientation(img, file_data);
     let (width, height) = (img.width(), img.height());
 
-    Ok(LoadedImage {
+    Ok(LoadedImage::new(
         width,
         height,
-        frames: vec![FrameData {
+        vec![FrameData {
             pixels: img.to_rgba8().into_raw(),
             delay: Duration::MAX,
         }],
-    })
+    ))
 }
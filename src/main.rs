@@ -1,21 +1,43 @@
+mod adjustments;
 mod app;
+mod archive;
 mod cache;
+mod compare;
 mod config;
+mod crop;
+mod file_ops;
 mod filtering;
 mod frame_buffer;
+mod heif;
 mod image_item;
+#[cfg(unix)]
+mod ipc;
+mod jxl;
 mod keybinds;
 mod loader;
+mod output;
+mod pages;
+mod psd;
+mod raw;
 mod renderer;
+mod resampling;
+mod save;
 mod script_handler;
+mod session;
+mod sorting;
 mod status_bar;
+mod stdin;
+mod thumbnail_cache;
+mod tiles;
 mod utils;
 mod view_mode;
+mod virtual_path;
 mod watcher;
 
 use app::{App, AppEvent};
 use clap::Parser;
-use std::io::{self, BufRead, IsTerminal};
+use sorting::SortMode;
+use std::path::{Path, PathBuf};
 use winit::event_loop::EventLoop;
 
 #[derive(Parser, Debug)]
@@ -33,6 +55,23 @@ struct Cli {
     #[arg(short, long)]
     output_marked: bool,
 
+    /// Picker mode: Enter prints the marked files, or else the current one, and
+    /// exits with 0. Escape or quit exits with 1 without printing
+    #[arg(long, conflicts_with_all = ["output_marked", "output_current"])]
+    pick: bool,
+
+    /// Print the current file on exit
+    #[arg(long)]
+    output_current: bool,
+
+    /// Format of what -o and --output-current print
+    #[arg(long, value_enum, default_value = "plain")]
+    output_format: output::OutputFormat,
+
+    /// Separate the paths read from stdin and printed by -o with NUL instead of newlines
+    #[arg(short = '0', long)]
+    null: bool,
+
     /// Quiet mode: Suppress warnings and non-fatal errors
     #[arg(short, long)]
     quiet: bool,
@@ -41,13 +80,45 @@ struct Cli {
     #[arg(short = 'H', long)]
     hidden: bool,
 
+    /// Private mode: Do not read or write the shared thumbnail cache
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Sort order of the images
+    #[arg(long, value_enum)]
+    sort: Option<SortMode>,
+
+    /// Reverse the sort order
+    #[arg(long)]
+    reverse: bool,
+
+    /// Open the directory of a single file argument, starting at that file
+    #[arg(short, long)]
+    expand: bool,
+
+    /// Listen for remote commands on a Unix socket
+    #[arg(long)]
+    ipc: bool,
+
+    /// Socket path for --ipc and --remote
+    #[arg(long, value_name = "PATH")]
+    socket: Option<PathBuf>,
+
+    /// Send a command to a running instance and print the reply
+    #[arg(long, value_name = "CMD")]
+    remote: Option<String>,
+
+    /// Restore a named session and save it on exit
+    #[arg(long, value_name = "NAME")]
+    session: Option<String>,
+
     /// Maximum recursion depth
     #[arg(short = 'd', long, requires = "recursive")]
     max_depth: Option<usize>,
 
     /// Image paths or directories
     #[arg(required = false)]
-    paths: Vec<String>,
+    paths: Vec<PathBuf>,
 }
 
 fn main() {
@@ -55,26 +126,57 @@ fn main() {
 
     crate::utils::set_quiet_mode(cli.quiet);
 
-    let mut raw_paths = cli.paths.clone();
-
-    if !io::stdin().is_terminal() {
-        let stdin = io::stdin();
-        let handle = stdin.lock();
+    if let Some(command) = &cli.remote {
+        std::process::exit(run_remote(cli.socket.clone(), command));
+    }
+    crate::thumbnail_cache::set_enabled(
+        !cli.no_cache && crate::config::AppConfig::get().options.thumbnail_cache,
+    );
 
-        for path_str in handle.lines().map_while(Result::ok) {
-            let trimmed = path_str.trim();
-            if !trimmed.is_empty() {
-                raw_paths.push(trimmed.to_string());
+    let session_file = match cli.session.as_deref().map(session::session_path) {
+        Some(Ok(path)) => Some(path),
+        Some(Err(e)) => {
+            crate::rsiv_err!("{}", e);
+            return;
+        }
+        None => None,
+    };
+    let saved_session = session_file
+        .as_ref()
+        .and_then(|path| match session::Session::load(path) {
+            Ok(session) => session,
+            Err(e) => {
+                crate::rsiv_warn!("Failed to read session {:?}: {}", path, e);
+                None
             }
+        });
+
+    // `-` reads an image from stdin
+    let (stdin_image, mut raw_paths): (Vec<PathBuf>, Vec<PathBuf>) =
+        cli.paths.iter().cloned().partition(|p| p == Path::new("-"));
+    let mut recursive = cli.recursive;
+
+    match stdin::read(!stdin_image.is_empty(), cli.null) {
+        Ok(paths) => raw_paths.extend(paths),
+        Err(e) => {
+            crate::rsiv_err!("{}", e);
+            return;
         }
     }
 
-    let canonical_paths: Vec<String> = raw_paths
+    // Without paths, a session reopens what it was browsing
+    if let Some(session) = saved_session.as_ref().filter(|_| raw_paths.is_empty()) {
+        raw_paths = session.paths.clone();
+        recursive |= session.recursive;
+    }
+
+    let mut canonical_paths: Vec<PathBuf> = raw_paths
         .iter()
         .filter_map(|p| match std::fs::canonicalize(p) {
-            Ok(path) => Some(path.to_string_lossy().into_owned()),
+            _ if stdin::data(p).is_some() => Some(p.clone()),
+            Ok(path) => Some(path),
             Err(e) => {
-                crate::rsiv_warn!("Skipping invalid path '{}': {}", p, e);
+                crate::rsiv_warn!("Skipping invalid path {:?}: {}", p, e);
                 None
             }
         })
@@ -85,25 +187,126 @@ fn main() {
         return;
     }
 
+    // A lone file is replaced by its directory, and shown first
+    let mut start_path = None;
+    let expand = cli.expand || crate::config::AppConfig::get().options.expand_single_file;
+    if expand && canonical_paths.len() == 1 {
+        let file = canonical_paths[0].clone();
+        // An archive on its own is already browsed like a directory
+        if file.is_file() && !crate::archive::is_archive(&file) {
+            if let Some(dir) = file.parent() {
+                canonical_paths = vec![dir.to_path_buf()];
+                start_path = Some(file);
+            }
+        }
+    }
+
     let event_loop = EventLoop::<AppEvent>::with_user_event().build().unwrap();
     let proxy = event_loop.create_proxy();
 
     let mut app = App::new(vec![], cli.thumbnail, proxy.clone());
+    if let Some(session) = &saved_session {
+        app.restore_session(session);
+    }
+    if let Some(mode) = cli.sort {
+        app.sort_mode = mode;
+    }
+    app.sort_reverse ^= cli.reverse;
+    app.picker = cli.pick;
+    app.include_hidden = cli.hidden;
+    if start_path.is_some() {
+        app.start_path = start_path.clone();
+        app.start_index = None;
+    }
 
     loader::spawn_discovery_worker(
         canonical_paths.clone(),
-        cli.recursive,
+        start_path,
+        recursive,
         cli.max_depth,
         cli.hidden,
+        (app.sort_mode, app.sort_reverse),
+        app.discovery,
         proxy.clone(),
     );
-    watcher::spawn_watcher(canonical_paths, cli.recursive, proxy.clone());
+    app.watcher = watcher::Watcher::spawn(proxy.clone());
+    if let Some(watcher) = &mut app.watcher {
+        watcher.watch(&canonical_paths, recursive);
+    }
+    watcher::spawn_config_watcher(proxy.clone());
+
+    #[cfg(unix)]
+    if cli.ipc || cli.socket.is_some() || crate::config::AppConfig::get().options.ipc {
+        let path = cli.socket.clone().unwrap_or_else(ipc::default_socket_path);
+        match ipc::spawn_listener(&path, proxy.clone()) {
+            Ok(()) => app.socket = Some(path),
+            Err(e) => crate::rsiv_warn!("Failed to listen on {:?}: {}", path, e),
+        }
+    }
+    #[cfg(not(unix))]
+    if cli.ipc || cli.socket.is_some() {
+        crate::rsiv_warn!("Remote commands need Unix domain sockets, not listening.");
+    }
 
     let _ = event_loop.run_app(&mut app);
 
-    if cli.output_marked {
-        for path in &app.marked_files {
-            println!("{}", path);
+    if let Some(path) = &app.socket {
+        let _ = std::fs::remove_file(path);
+    }
+
+    if let Some(path) = session_file {
+        if let Err(e) = app.to_session(canonical_paths, recursive).save(&path) {
+            crate::rsiv_err!("Failed to save session {:?}: {}", path, e);
+        }
+    }
+
+    if cli.pick {
+        if !app.picked {
+            std::process::exit(1);
+        }
+        let marked = !app.marked_files.is_empty();
+        print!(
+            "{}",
+            app.exit_output(marked, !marked, cli.output_format, cli.null)
+        );
+    } else if cli.output_marked || cli.output_current {
+        print!(
+            "{}",
+            app.exit_output(
+                cli.output_marked,
+                cli.output_current,
+                cli.output_format,
+                cli.null
+            )
+        );
+    }
+}
+
+#[cfg(not(unix))]
+fn run_remote(_socket: Option<PathBuf>, _command: &str) -> i32 {
+    crate::rsiv_err!("Remote commands need Unix domain sockets.");
+    1
+}
+
+#[cfg(unix)]
+fn run_remote(socket: Option<PathBuf>, command: &str) -> i32 {
+    let Some(socket) = socket.or_else(ipc::find_socket) else {
+        crate::rsiv_err!("No running rsiv instance found.");
+        return 1;
+    };
+
+    match ipc::send(&socket, command) {
+        Ok(response) => {
+            println!("{}", response);
+            if response["ok"].as_bool() == Some(true) {
+                0
+            } else {
+                1
+            }
+        }
+        Err(e) => {
+            crate::rsiv_err!("Remote command failed: {}", e);
+            1
         }
     }
 }
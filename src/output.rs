@@ -0,0 +1,100 @@
+use crate::app::App;
+use crate::image_item::ImageSlot;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// The order marked files are handed out in.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum MarkOrder {
+    List,   // Where they are in the image list
+    Marked, // In the order they were marked
+}
+
+/// How `--output-marked` and `--output-current` print on exit.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum OutputFormat {
+    Plain,
+    Json,
+}
+
+impl App {
+    /// The marked files in the configured `mark_order`. Marks that are not in
+    /// the list (anymore) come last.
+    pub fn marks_in_order(&self) -> Vec<String> {
+        let mut marks: Vec<String> = self.marked_files.iter().cloned().collect();
+        if crate::config::AppConfig::get().options.mark_order == MarkOrder::List {
+            let position = |mark: &String| {
+                self.all_images.iter().position(|slot| {
+                    matches!(slot, ImageSlot::MetadataLoaded(item) if item.path == Path::new(mark))
+                })
+            };
+            marks.sort_by_cached_key(|mark| position(mark).unwrap_or(usize::MAX));
+        }
+        marks
+    }
+
+    /// A file as printed in JSON. The index is its position in the shown
+    /// list starting at 1, like `query` over the socket.
+    fn output_entry(&self, path: &str) -> Value {
+        let found = self
+            .images
+            .iter()
+            .enumerate()
+            .find_map(|(i, slot)| match slot {
+                ImageSlot::MetadataLoaded(item) if item.path == Path::new(path) => Some((i, item)),
+                _ => None,
+            });
+        match found {
+            Some((i, item)) => json!({
+                "path": path,
+                "index": i + 1,
+                "width": item.width,
+                "height": item.height,
+            }),
+            None => json!({ "path": path, "index": null, "width": null, "height": null }),
+        }
+    }
+
+    /// What is printed on exit: the marks and/or the current file.
+    pub fn exit_output(
+        &self,
+        marked: bool,
+        current: bool,
+        format: OutputFormat,
+        null: bool,
+    ) -> String {
+        let marks = if marked {
+            self.marks_in_order()
+        } else {
+            Vec::new()
+        };
+        let current_path = self
+            .current_path()
+            .filter(|_| current)
+            .map(|p| p.to_string_lossy().into_owned());
+
+        match format {
+            OutputFormat::Plain => {
+                let separator = if null { '\0' } else { '\n' };
+                marks
+                    .iter()
+                    .chain(&current_path)
+                    .map(|path| format!("{}{}", path, separator))
+                    .collect()
+            }
+            OutputFormat::Json => {
+                let mut output = serde_json::Map::new();
+                if marked {
+                    let entries = marks.iter().map(|m| self.output_entry(m)).collect();
+                    output.insert("marked".into(), Value::Array(entries));
+                }
+                if current {
+                    let entry = current_path.map_or(Value::Null, |p| self.output_entry(&p));
+                    output.insert("current".into(), entry);
+                }
+                format!("{}\n", Value::Object(output))
+            }
+        }
+    }
+}
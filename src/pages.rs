@@ -0,0 +1,308 @@
+use crate::image_item::{LoadedImage, Page};
+use crate::tiles::tiff_layout;
+use image::RgbaImage;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::tags::Tag;
+
+const ICO_HEADER: usize = 6;
+const ICO_ENTRY: usize = 16;
+
+/// Windows cursors are icons with a hotspot, which the image crate doesn't sniff.
+pub fn is_cursor(data: &[u8]) -> bool {
+    data.len() >= ICO_HEADER && data[..4] == [0, 0, 2, 0] && data[4..6] != [0, 0]
+}
+
+fn is_icon(data: &[u8]) -> bool {
+    data.starts_with(&[0, 0, 1, 0]) || is_cursor(data)
+}
+
+fn is_tiff(data: &[u8]) -> bool {
+    data.starts_with(b"II*\0")
+        || data.starts_with(b"MM\0*")
+        || data.starts_with(b"II+\0")
+        || data.starts_with(b"MM\0+")
+}
+
+/// Where the pages of a document are in its file. Only the shown page and
+/// its neighbours are kept decoded, the others are read again when shown.
+#[derive(Debug)]
+pub struct Document {
+    path: PathBuf,
+    pages: Pages,
+}
+
+#[derive(Debug)]
+enum Pages {
+    /// Indices of the directories that are pages
+    Tiff(Vec<usize>),
+    /// Directory entries, best first
+    Icon(Vec<[u8; ICO_ENTRY]>),
+}
+
+impl Document {
+    pub fn len(&self) -> usize {
+        match &self.pages {
+            Pages::Tiff(ifds) => ifds.len(),
+            Pages::Icon(entries) => entries.len(),
+        }
+    }
+
+    /// Decodes a page from the file, or from memory for virtual images.
+    pub fn decode(&self, index: usize) -> Result<Page, String> {
+        match crate::virtual_path::read(&self.path) {
+            Some(data) => self.decode_from(&data?, index),
+            None => self.decode_from(&crate::loader::map_file(&self.path)?, index),
+        }
+    }
+
+    fn decode_from(&self, data: &[u8], index: usize) -> Result<Page, String> {
+        match &self.pages {
+            Pages::Tiff(ifds) => {
+                let ifd = *ifds.get(index).ok_or("No such page")?;
+                let mut decoder = Decoder::new(Cursor::new(data)).map_err(|e| e.to_string())?;
+                decoder.seek_to_image(ifd).map_err(|e| e.to_string())?;
+                tiff_page(&mut decoder)
+            }
+            Pages::Icon(entries) => icon_entry(data, entries.get(index).ok_or("No such page")?),
+        }
+    }
+}
+
+/// The first page of a multi-page TIFF or the best size of an icon, with
+/// where to find the others. `None` when there is only one. Cursors always go
+/// through here.
+pub fn decode(data: &[u8], path: &Path) -> Result<Option<LoadedImage>, String> {
+    let pages = if is_tiff(data) {
+        match tiff_pages(data)? {
+            Some(ifds) => Pages::Tiff(ifds),
+            None => return Ok(None),
+        }
+    } else if is_icon(data) {
+        Pages::Icon(icon_entries(data))
+    } else {
+        return Ok(None);
+    };
+
+    let document = Document {
+        path: path.to_path_buf(),
+        pages,
+    };
+    let count = document.len();
+    if count > 1 || (count == 1 && is_cursor(data)) {
+        let first = document.decode_from(data, 0)?;
+        Ok(Some(LoadedImage::from_document(document, first)))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Directories that are pages: reduced resolution copies and transparency
+/// masks are left out.
+fn tiff_page_ifds(decoder: &mut Decoder<Cursor<&[u8]>>) -> Vec<usize> {
+    let mut ifds = Vec::new();
+    let mut ifd = 0;
+    loop {
+        let subfile = decoder
+            .find_tag_unsigned::<u32>(Tag::NewSubfileType)
+            .ok()
+            .flatten()
+            .unwrap_or(0);
+        if subfile & 0b101 == 0 {
+            ifds.push(ifd);
+        }
+        if !decoder.more_images() || decoder.next_image().is_err() {
+            return ifds;
+        }
+        ifd += 1;
+    }
+}
+
+/// The page directories of a multi-page TIFF.
+fn tiff_pages(data: &[u8]) -> Result<Option<Vec<usize>>, String> {
+    let mut decoder = Decoder::new(Cursor::new(data)).map_err(|e| e.to_string())?;
+    let ifds = tiff_page_ifds(&mut decoder);
+    if ifds.len() < 2 {
+        return Ok(None);
+    }
+
+    // Documents this large are shown in tiles, first page only
+    decoder.seek_to_image(ifds[0]).map_err(|e| e.to_string())?;
+    let (width, height) = decoder.dimensions().map_err(|e| e.to_string())?;
+    if crate::tiles::should_tile(width, height) {
+        return Ok(None);
+    }
+    Ok(Some(ifds))
+}
+
+fn tiff_page(decoder: &mut Decoder<Cursor<&[u8]>>) -> Result<Page, String> {
+    let (width, height) = decoder.dimensions().map_err(|e| e.to_string())?;
+    crate::loader::check_memory_before_decode(width, height, 1)?;
+    let color = decoder.colortype().map_err(|e| e.to_string())?;
+    let orientation = decoder
+        .find_tag_unsigned::<u32>(Tag::Orientation)
+        .ok()
+        .flatten()
+        .unwrap_or(1);
+
+    let samples = match decoder.read_image().map_err(|e| e.to_string())? {
+        DecodingResult::U8(v) => v,
+        DecodingResult::U16(v) => v.iter().map(|s| (s >> 8) as u8).collect(),
+        _ => return Err("Unsupported sample format".to_string()),
+    };
+
+    let mut rgba = Vec::with_capacity(width as usize * height as usize * 4);
+    if color == tiff::ColorType::Gray(1) {
+        // Fax pages: packed rows, set bits are white
+        for row in samples.chunks(width.div_ceil(8) as usize) {
+            for x in 0..width as usize {
+                let v = if row[x / 8] & (0x80 >> (x % 8)) != 0 {
+                    255
+                } else {
+                    0
+                };
+                rgba.extend_from_slice(&[v, v, v, 255]);
+            }
+        }
+    } else {
+        let layout = tiff_layout(color).ok_or(format!("Unsupported color type {:?}", color))?;
+        layout.to_rgba(&samples, &mut rgba);
+    }
+
+    let img = RgbaImage::from_raw(width, height, rgba).ok_or("Unexpected page size")?;
+    let img = crate::loader::apply_orientation(image::DynamicImage::ImageRgba8(img), orientation);
+    Ok(Page {
+        width: img.width(),
+        height: img.height(),
+        pixels: img.to_rgba8().into_raw(),
+        ..Default::default()
+    })
+}
+
+/// Entries ordered like the image crate picks its single one, best first:
+/// by color depth, then size.
+fn icon_entries(data: &[u8]) -> Vec<[u8; ICO_ENTRY]> {
+    let count = data
+        .get(4..6)
+        .map_or(0, |c| u16::from_le_bytes([c[0], c[1]]) as usize);
+    let mut entries: Vec<[u8; ICO_ENTRY]> = (0..count)
+        .filter_map(|i| data.get(ICO_HEADER + i * ICO_ENTRY..ICO_HEADER + (i + 1) * ICO_ENTRY))
+        .filter_map(|e| e.try_into().ok())
+        .collect();
+    let bits = |e: &[u8]| {
+        if is_cursor(data) {
+            0
+        } else {
+            u16::from_le_bytes([e[6], e[7]])
+        }
+    };
+    entries.sort_by_key(|e| {
+        let (width, height) = entry_size(e);
+        std::cmp::Reverse((bits(e), width * height))
+    });
+    entries
+}
+
+/// Size from the directory, where 0 stands for 256.
+fn entry_size(entry: &[u8]) -> (u32, u32) {
+    let dimension = |b: u8| if b == 0 { 256 } else { b as u32 };
+    (dimension(entry[0]), dimension(entry[1]))
+}
+
+/// Size of the entry a cursor is listed with.
+pub fn cursor_size(data: &[u8]) -> Result<(u32, u32), String> {
+    icon_entries(data)
+        .first()
+        .map(|e| entry_size(e))
+        .ok_or("Empty cursor".to_string())
+}
+
+/// Decodes one entry by wrapping it in an icon of its own.
+fn icon_entry(data: &[u8], entry: &[u8]) -> Result<Page, String> {
+    let len = u32::from_le_bytes(entry[8..12].try_into().unwrap()) as usize;
+    let offset = u32::from_le_bytes(entry[12..16].try_into().unwrap()) as usize;
+    let image = data
+        .get(offset..offset + len)
+        .ok_or("Entry out of bounds")?;
+
+    let start = ICO_HEADER + ICO_ENTRY;
+    let mut single = Vec::with_capacity(start + len);
+    single.extend_from_slice(&[0, 0, 1, 0, 1, 0]);
+    single.extend_from_slice(&entry[..4]);
+    if is_cursor(data) {
+        // The hotspot sits where icons keep planes and depth
+        single.extend_from_slice(&[1, 0, 0, 0]);
+    } else {
+        single.extend_from_slice(&entry[4..8]);
+    }
+    single.extend_from_slice(&(len as u32).to_le_bytes());
+    single.extend_from_slice(&(start as u32).to_le_bytes());
+    single.extend_from_slice(image);
+
+    let img = image::load_from_memory_with_format(&single, image::ImageFormat::Ico)
+        .map_err(|e| e.to_string())?
+        .to_rgba8();
+    Ok(Page {
+        width: img.width(),
+        height: img.height(),
+        pixels: img.into_raw(),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An icon directory of `kind` (1 for icons, 2 for cursors) listing
+    /// `count` entries, of which only `entries` are present.
+    fn directory(kind: u8, count: u16, entries: &[(u8, u8, u16)]) -> Vec<u8> {
+        let mut data = vec![0, 0, kind, 0];
+        data.extend_from_slice(&count.to_le_bytes());
+        for &(width, height, bits) in entries {
+            let mut entry = [0u8; ICO_ENTRY];
+            entry[0] = width;
+            entry[1] = height;
+            entry[6..8].copy_from_slice(&bits.to_le_bytes());
+            data.extend_from_slice(&entry);
+        }
+        data
+    }
+
+    fn sizes(entries: &[[u8; ICO_ENTRY]]) -> Vec<(u32, u32, u16)> {
+        entries
+            .iter()
+            .map(|e| {
+                let (width, height) = entry_size(e);
+                (width, height, u16::from_le_bytes([e[6], e[7]]))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn icon_entries_sort_by_depth_then_size() {
+        let data = directory(1, 4, &[(16, 16, 32), (32, 32, 8), (0, 0, 32), (48, 48, 32)]);
+        assert_eq!(
+            sizes(&icon_entries(&data)),
+            [(256, 256, 32), (48, 48, 32), (16, 16, 32), (32, 32, 8)]
+        );
+    }
+
+    #[test]
+    fn cursor_entries_sort_by_size_only() {
+        // Cursors keep the hotspot where icons keep the depth
+        let data = directory(2, 3, &[(32, 32, 2), (64, 64, 1), (16, 16, 9)]);
+        assert_eq!(
+            sizes(&icon_entries(&data)),
+            [(64, 64, 1), (32, 32, 2), (16, 16, 9)]
+        );
+    }
+
+    #[test]
+    fn icon_entries_skip_entries_past_the_end() {
+        let data = directory(1, 3, &[(16, 16, 32), (32, 32, 32)]);
+        assert_eq!(sizes(&icon_entries(&data)), [(32, 32, 32), (16, 16, 32)]);
+        assert!(icon_entries(&data[..4]).is_empty());
+    }
+}
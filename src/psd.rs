@@ -0,0 +1,255 @@
+use crate::image_item::{FrameData, LoadedImage};
+use std::io::Read;
+use std::path::Path;
+use std::time::Duration;
+
+const HEADER: usize = 26;
+
+struct Header {
+    big: bool, // PSB, with wider lengths
+    channels: usize,
+    width: u32,
+    height: u32,
+    depth: u16,
+    mode: u16,
+}
+
+fn read_header(data: &[u8]) -> Result<Header, String> {
+    if data.len() < HEADER || !data.starts_with(b"8BPS") {
+        return Err("Not a Photoshop file".to_string());
+    }
+    let u16_at = |i: usize| u16::from_be_bytes([data[i], data[i + 1]]);
+    let u32_at = |i: usize| u32::from_be_bytes(data[i..i + 4].try_into().unwrap());
+    Ok(Header {
+        big: u16_at(4) == 2,
+        channels: u16_at(12) as usize,
+        height: u32_at(14),
+        width: u32_at(18),
+        depth: u16_at(22),
+        mode: u16_at(24),
+    })
+}
+
+pub fn probe(path: &Path) -> Result<(u32, u32), String> {
+    let mut data = [0; HEADER];
+    std::fs::File::open(path)
+        .and_then(|mut f| f.read_exact(&mut data))
+        .map_err(|e| e.to_string())?;
+    size(&data)
+}
+
+pub fn size(data: &[u8]) -> Result<(u32, u32), String> {
+    let header = read_header(data)?;
+    Ok((header.width, header.height))
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos.saturating_add(len))
+            .ok_or("Truncated Photoshop file")?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn uint(&mut self, size: usize) -> Result<usize, String> {
+        Ok(self
+            .take(size)?
+            .iter()
+            .fold(0, |v, &b| (v << 8) | b as usize))
+    }
+
+    /// A section preceded by its length.
+    fn section(&mut self, length_size: usize) -> Result<&'a [u8], String> {
+        let len = self.uint(length_size)?;
+        self.take(len)
+    }
+}
+
+/// PackBits, as used by the RLE compression.
+fn unpack_bits(mut src: &[u8], len: usize, out: &mut Vec<u8>) {
+    let end = out.len() + len;
+    while out.len() < end && !src.is_empty() {
+        let n = src[0] as i8;
+        src = &src[1..];
+        if n >= 0 {
+            let count = (n as usize + 1).min(src.len());
+            out.extend_from_slice(&src[..count]);
+            src = &src[count..];
+        } else if n != -128 && !src.is_empty() {
+            out.extend(std::iter::repeat_n(src[0], (1 - n as isize) as usize));
+            src = &src[1..];
+        }
+    }
+    out.resize(end, 0);
+}
+
+/// Reduces a plane to 8 bits per pixel.
+fn to_8bit(plane: &[u8], header: &Header) -> Vec<u8> {
+    match header.depth {
+        16 => plane.iter().step_by(2).copied().collect(),
+        32 => plane
+            .chunks_exact(4)
+            .map(|f| (f32::from_be_bytes(f.try_into().unwrap()).clamp(0.0, 1.0) * 255.0) as u8)
+            .collect(),
+        1 => {
+            // Bitmap mode: set bits are black
+            let row = (header.width as usize).div_ceil(8);
+            plane
+                .chunks(row)
+                .flat_map(|r| {
+                    (0..header.width as usize).map(move |x| {
+                        if r[x / 8] & (0x80 >> (x % 8)) != 0 {
+                            0
+                        } else {
+                            255
+                        }
+                    })
+                })
+                .collect()
+        }
+        _ => plane.to_vec(),
+    }
+}
+
+/// The flattened composite Photoshop stores after the layers.
+pub fn decode(data: &[u8]) -> Result<LoadedImage, String> {
+    let header = read_header(data)?;
+    let (width, height) = (header.width, header.height);
+    crate::loader::check_memory_before_decode(width, height, 1)?;
+    if ![1, 8, 16, 32].contains(&header.depth) {
+        return Err(format!("Unsupported depth {}", header.depth));
+    }
+
+    let mut r = Reader { data, pos: HEADER };
+    let palette = r.section(4)?;
+    r.section(4)?; // Image resources
+    let length_size = if header.big { 8 } else { 4 };
+    let layers = r.section(length_size)?;
+
+    // A negative layer count means the first extra channel is the transparency of the composite
+    let merged_alpha = layers
+        .get(length_size..length_size + 2)
+        .is_some_and(|c| i16::from_be_bytes([c[0], c[1]]) < 0);
+    let colors = match header.mode {
+        0 | 1 | 2 | 8 => 1, // Bitmap, grayscale, indexed and duotone
+        3 => 3,
+        4 => 4,
+        mode => return Err(format!("Unsupported color mode {}", mode)),
+    };
+    let wanted = if merged_alpha && header.channels > colors {
+        colors + 1
+    } else {
+        colors
+    };
+    if header.channels < colors {
+        return Err("Missing color channels".to_string());
+    }
+
+    let row_len = (width as usize * header.depth as usize).div_ceil(8);
+    let rows = height as usize;
+    let mut planes = Vec::with_capacity(wanted);
+    match r.uint(2)? {
+        0 => {
+            for _ in 0..wanted {
+                planes.push(to_8bit(r.take(row_len * rows)?, &header));
+            }
+        }
+        1 => {
+            let count_size = if header.big { 4 } else { 2 };
+            let counts = (0..header.channels * rows)
+                .map(|_| r.uint(count_size))
+                .collect::<Result<Vec<_>, _>>()?;
+            for channel in 0..wanted {
+                let mut plane = Vec::with_capacity(row_len * rows);
+                for &count in &counts[channel * rows..(channel + 1) * rows] {
+                    unpack_bits(r.take(count)?, row_len, &mut plane);
+                }
+                planes.push(to_8bit(&plane, &header));
+            }
+        }
+        _ => return Err("ZIP compressed Photoshop files are not supported".to_string()),
+    }
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+    for i in 0..width as usize * height as usize {
+        let alpha = planes.get(colors).map_or(255, |p| p[i]);
+        let rgb = match header.mode {
+            2 => {
+                // The palette holds all reds, then all greens, then all blues
+                let index = planes[0][i] as usize;
+                let channel = |c: usize| palette.get(c * 256 + index).copied().unwrap_or(0);
+                [channel(0), channel(1), channel(2)]
+            }
+            3 => [planes[0][i], planes[1][i], planes[2][i]],
+            4 => {
+                // Stored inverted, 255 is no ink
+                let k = planes[3][i] as u32;
+                let c = |p: &Vec<u8>| (p[i] as u32 * k / 255) as u8;
+                [c(&planes[0]), c(&planes[1]), c(&planes[2])]
+            }
+            _ => [planes[0][i]; 3],
+        };
+        pixels.extend_from_slice(&[rgb[0], rgb[1], rgb[2], alpha]);
+    }
+
+    Ok(LoadedImage::new(
+        width,
+        height,
+        vec![FrameData {
+            pixels,
+            delay: Duration::MAX,
+        }],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unpack(src: &[u8], len: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        unpack_bits(src, len, &mut out);
+        out
+    }
+
+    #[test]
+    fn unpack_bits_copies_literals_and_repeats_runs() {
+        // The example from Apple's PackBits technical note
+        let packed = [
+            0xFE, 0xAA, 0x02, 0x80, 0x00, 0x2A, 0xFD, 0xAA, 0x03, 0x80, 0x00, 0x2A, 0x22, 0xF7,
+            0xAA,
+        ];
+        let unpacked = [
+            0xAA, 0xAA, 0xAA, 0x80, 0x00, 0x2A, 0xAA, 0xAA, 0xAA, 0xAA, 0x80, 0x00, 0x2A, 0x22,
+            0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA,
+        ];
+        assert_eq!(unpack(&packed, unpacked.len()), unpacked);
+    }
+
+    #[test]
+    fn unpack_bits_skips_the_no_op() {
+        assert_eq!(unpack(&[0x80, 0x01, 1, 2, 0x80], 2), [1, 2]);
+    }
+
+    #[test]
+    fn unpack_bits_pads_and_truncates_to_the_length() {
+        assert_eq!(unpack(&[0x00, 7], 3), [7, 0, 0]);
+        assert_eq!(unpack(&[0xFD, 7], 2), [7, 7]);
+        // A literal run cut short by the end of the data
+        assert_eq!(unpack(&[0x05, 1, 2], 4), [1, 2, 0, 0]);
+    }
+
+    #[test]
+    fn unpack_bits_appends_to_the_output() {
+        let mut out = vec![9];
+        unpack_bits(&[0x01, 1, 2], 2, &mut out);
+        assert_eq!(out, [9, 1, 2]);
+    }
+}
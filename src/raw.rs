@@ -0,0 +1,417 @@
+use crate::image_item::{FrameData, LoadedImage};
+use crate::loader::map_file;
+use exif::{In, Tag};
+use image::DynamicImage;
+use std::io::Cursor;
+use std::ops::Range;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+const DCRAW: &str = "dcraw";
+const EXTENSIONS: &[&str] = &[
+    "3fr", "arw", "cr2", "cr3", "crw", "dcr", "dng", "erf", "iiq", "kdc", "mef", "mos", "nef",
+    "nrw", "orf", "pef", "raf", "raw", "rw2", "rwl", "sr2", "srf", "srw",
+];
+
+// TIFF tags pointing at previews
+const COMPRESSION: u16 = 0x0103;
+const STRIP_OFFSETS: u16 = 0x0111;
+const SUB_IFDS: u16 = 0x014A;
+const JPEG_INTERCHANGE_FORMAT: u16 = 0x0201;
+/// Panasonic's RW2 stores its preview as the value of this tag.
+const JPG_FROM_RAW: u16 = 0x002E;
+/// Old-style and new-style JPEG compression.
+const JPEG_COMPRESSION: [u32; 2] = [6, 7];
+/// Guards against IFDs pointing at each other.
+const MAX_IFDS: usize = 64;
+
+/// Most RAW formats are TIFF underneath, so they are told apart by extension.
+pub fn is_raw(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+/// A JPEG embedded in the RAW file.
+struct Preview {
+    offset: usize,
+    width: u32,
+    height: u32,
+}
+
+/// The JPEGs the file's own structure points at, largest first. Cameras
+/// store a full size one next to smaller thumbnails.
+fn previews(data: &[u8]) -> Vec<Preview> {
+    let offsets = if data.starts_with(b"FUJIFILMCCD-RAW") {
+        raf_preview(data).into_iter().collect()
+    } else if data.get(4..8) == Some(b"ftyp") {
+        cr3_previews(data)
+    } else if data.get(6..14) == Some(b"HEAPCCDR") {
+        crw_preview(data).into_iter().collect()
+    } else {
+        tiff_previews(data)
+    };
+
+    let mut found: Vec<Preview> = offsets
+        .into_iter()
+        .filter_map(|offset| {
+            let (width, height) = jpeg_size(data.get(offset..)?)?;
+            Some(Preview {
+                offset,
+                width,
+                height,
+            })
+        })
+        .collect();
+    found.sort_by_key(|p| {
+        (
+            std::cmp::Reverse(p.width as u64 * p.height as u64),
+            p.offset,
+        )
+    });
+    found.dedup_by_key(|p| p.offset);
+    found
+}
+
+/// Reads a TIFF structure in its byte order.
+struct Tiff<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl Tiff<'_> {
+    fn u16(&self, pos: usize) -> Option<u16> {
+        let bytes = self.data.get(pos..pos + 2)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, pos: usize) -> Option<u32> {
+        let bytes = self.data.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    /// The SHORT, LONG or IFD values of the entry at `entry`. They are stored
+    /// in the entry itself when they fit in 4 bytes.
+    fn values(&self, entry: usize) -> Vec<u32> {
+        let size = match self.u16(entry + 2) {
+            Some(3) => 2,
+            Some(4 | 13) => 4,
+            _ => return Vec::new(),
+        };
+        let count = self.u32(entry + 4).unwrap_or(0).min(MAX_IFDS as u32) as usize;
+        let start = if count * size <= 4 {
+            Some(entry + 8)
+        } else {
+            self.u32(entry + 8).map(|o| o as usize)
+        };
+        let Some(start) = start else {
+            return Vec::new();
+        };
+        (0..count)
+            .filter_map(|i| match size {
+                2 => self.u16(start + 2 * i).map(u32::from),
+                _ => self.u32(start + 4 * i),
+            })
+            .collect()
+    }
+}
+
+/// Walks IFD0, the IFDs chained after it and their SubIFDs. A preview is
+/// either a JPEGInterchangeFormat or the strips of a JPEG compressed IFD.
+/// Previews only referenced from maker notes are not found.
+fn tiff_previews(data: &[u8]) -> Vec<usize> {
+    let big_endian = match data.get(..2) {
+        Some(b"II") => false,
+        Some(b"MM") => true,
+        _ => return Vec::new(),
+    };
+    let tiff = Tiff { data, big_endian };
+
+    let mut found = Vec::new();
+    let mut queue: Vec<u32> = tiff.u32(4).into_iter().collect();
+    let mut seen = Vec::new();
+    while let Some(ifd) = queue.pop() {
+        if ifd == 0 || seen.contains(&ifd) || seen.len() >= MAX_IFDS {
+            continue;
+        }
+        seen.push(ifd);
+
+        let ifd = ifd as usize;
+        let Some(count) = tiff.u16(ifd) else {
+            continue;
+        };
+        let (mut compression, mut strip) = (None, None);
+        for entry in (0..count as usize).map(|i| ifd + 2 + 12 * i) {
+            match tiff.u16(entry) {
+                Some(JPEG_INTERCHANGE_FORMAT) => {
+                    found.extend(tiff.values(entry).first().map(|&o| o as usize))
+                }
+                Some(COMPRESSION) => compression = tiff.values(entry).first().copied(),
+                Some(STRIP_OFFSETS) => strip = tiff.values(entry).first().copied(),
+                Some(SUB_IFDS) => queue.extend(tiff.values(entry)),
+                // An UNDEFINED blob, always too long to be stored inline
+                Some(JPG_FROM_RAW) => found.extend(tiff.u32(entry + 8).map(|o| o as usize)),
+                _ => {}
+            }
+        }
+        if compression.is_some_and(|c| JPEG_COMPRESSION.contains(&c)) {
+            found.extend(strip.map(|o| o as usize));
+        }
+        queue.extend(tiff.u32(ifd + 2 + 12 * count as usize));
+    }
+    found
+}
+
+/// RAF starts with a big endian header, which has the offset of its JPEG.
+fn raf_preview(data: &[u8]) -> Option<usize> {
+    Some(u32::from_be_bytes(data.get(84..88)?.try_into().ok()?) as usize)
+}
+
+/// CRW is a CIFF heap: the offset of its record table is in its last 4
+/// bytes, and the records point inside the heap.
+fn crw_preview(data: &[u8]) -> Option<usize> {
+    let u16_at = |pos: usize| Some(u16::from_le_bytes(data.get(pos..pos + 2)?.try_into().ok()?));
+    let u32_at =
+        |pos: usize| Some(u32::from_le_bytes(data.get(pos..pos + 4)?.try_into().ok()?) as usize);
+
+    let heap = u32_at(2)?;
+    let table = heap.checked_add(u32_at(data.len().checked_sub(4)?)?)?;
+    (0..u16_at(table)? as usize)
+        .map(|i| table + 2 + 10 * i)
+        // The top bits of a tag are its storage and data type
+        .find(|&record| u16_at(record).is_some_and(|tag| tag & 0x3FFF == 0x2007))
+        .and_then(|record| heap.checked_add(u32_at(record + 6)?))
+}
+
+/// ISOBMFF boxes within `range` of the file, as (type, content range).
+fn boxes(data: &[u8], range: Range<usize>) -> impl Iterator<Item = ([u8; 4], Range<usize>)> + '_ {
+    let end = range.end.min(data.len());
+    let mut pos = range.start;
+    std::iter::from_fn(move || {
+        let size = u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?) as u64;
+        let kind: [u8; 4] = data.get(pos + 4..pos + 8)?.try_into().ok()?;
+        let (header, size) = match size {
+            0 => (8, (end - pos) as u64),
+            1 => (
+                16,
+                u64::from_be_bytes(data.get(pos + 8..pos + 16)?.try_into().ok()?),
+            ),
+            n => (8, n),
+        };
+        if size < header || size > (end - pos) as u64 {
+            return None;
+        }
+        let content = pos + header as usize..pos + size as usize;
+        pos = content.end;
+        Some((kind, content))
+    })
+}
+
+fn child(data: &[u8], range: Range<usize>, kind: &[u8; 4]) -> Option<Range<usize>> {
+    boxes(data, range).find_map(|(k, content)| (&k == kind).then_some(content))
+}
+
+/// A box kept in the uuid box of `moov`, where Canon stores its metadata
+/// (CMT1 to CMT4) and a thumbnail (THMB), after the 16 byte uuid.
+fn cr3_box(data: &[u8], kind: &[u8; 4]) -> Option<Range<usize>> {
+    let uuid = child(data, child(data, 0..data.len(), b"moov")?, b"uuid")?;
+    child(data, uuid.start + 16..uuid.end, kind)
+}
+
+/// CR3 previews: the full size JPEG is the first track, the PRVW box of
+/// the top level uuid box and the THMB box hold smaller ones.
+fn cr3_previews(data: &[u8]) -> Vec<usize> {
+    let mut found = Vec::new();
+    if let Some(moov) = child(data, 0..data.len(), b"moov") {
+        for (kind, trak) in boxes(data, moov) {
+            if &kind == b"trak" {
+                found.extend(track_offset(data, trak));
+            }
+        }
+    }
+
+    // PRVW follows the uuid and 8 unknown bytes
+    let prvw = boxes(data, 0..data.len())
+        .filter(|(kind, _)| kind == b"uuid")
+        .find_map(|(_, uuid)| child(data, uuid.start + 24..uuid.end, b"PRVW"));
+    // Both start with 16 bytes giving the size of the JPEG
+    for preview in prvw.into_iter().chain(cr3_box(data, b"THMB")) {
+        found.push(preview.start + 16);
+    }
+    found
+}
+
+/// Where the first sample of a track starts, from its chunk offsets.
+fn track_offset(data: &[u8], trak: Range<usize>) -> Option<usize> {
+    let minf = child(data, child(data, trak, b"mdia")?, b"minf")?;
+    let stbl = child(data, minf, b"stbl")?;
+    // After the version, flags and entry count
+    if let Some(co64) = child(data, stbl.clone(), b"co64") {
+        let bytes = data.get(co64.start + 8..co64.start + 16)?;
+        return Some(u64::from_be_bytes(bytes.try_into().ok()?) as usize);
+    }
+    let stco = child(data, stbl, b"stco")?;
+    let bytes = data.get(stco.start + 8..stco.start + 12)?;
+    Some(u32::from_be_bytes(bytes.try_into().ok()?) as usize)
+}
+
+/// Walks the markers up to the frame header. Lossless JPEG, which some
+/// formats use for the sensor data itself, is rejected.
+fn jpeg_size(data: &[u8]) -> Option<(u32, u32)> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut pos = 2;
+    loop {
+        if *data.get(pos)? != 0xFF {
+            return None;
+        }
+        let marker = *data.get(pos + 1)?;
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        let len = u16::from_be_bytes([*data.get(pos + 2)?, *data.get(pos + 3)?]) as usize;
+        match marker {
+            0xC0..=0xC2 => {
+                let height = u16::from_be_bytes([*data.get(pos + 5)?, *data.get(pos + 6)?]);
+                let width = u16::from_be_bytes([*data.get(pos + 7)?, *data.get(pos + 8)?]);
+                return (width > 0 && height > 0).then_some((width as u32, height as u32));
+            }
+            0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF | 0xD9 | 0xDA => return None,
+            _ if len < 2 => return None,
+            _ => pos += 2 + len,
+        }
+    }
+}
+
+/// The RAW's own metadata: TIFF based formats carry it directly, CR3 in its
+/// CMT1 box. Otherwise it comes from the embedded preview.
+fn read_exif(data: &[u8], previews: &[Preview]) -> Option<exif::Exif> {
+    let reader = exif::Reader::new();
+    if let Ok(exif) = reader.read_from_container(&mut Cursor::new(data)) {
+        return Some(exif);
+    }
+
+    if let Some(tiff) = cr3_box(data, b"CMT1").and_then(|range| data.get(range)) {
+        if let Ok(exif) = reader.read_raw(tiff.to_vec()) {
+            return Some(exif);
+        }
+    }
+
+    previews.iter().find_map(|p| {
+        reader
+            .read_from_container(&mut Cursor::new(&data[p.offset..]))
+            .ok()
+    })
+}
+
+fn orientation(exif: Option<&exif::Exif>) -> u32 {
+    exif.and_then(|e| e.get_field(Tag::Orientation, In::PRIMARY))
+        .and_then(|f| f.value.get_uint(0))
+        .unwrap_or(1)
+}
+
+/// The largest preview stands in for the sensor size, which needs dcraw. It is
+/// swapped when the EXIF orientation turns the picture on its side.
+pub fn probe(path: &Path) -> Result<(u32, u32, Option<exif::Exif>), String> {
+    let data = map_file(path)?;
+    let previews = previews(&data);
+    let largest = previews.first().ok_or("No embedded preview")?;
+    let exif = read_exif(&data, &previews);
+    let (width, height) = if [5, 6, 7, 8].contains(&orientation(exif.as_ref())) {
+        (largest.height, largest.width)
+    } else {
+        (largest.width, largest.height)
+    };
+    Ok((width, height, exif))
+}
+
+fn decode_preview(
+    data: &[u8],
+    previews: &[Preview],
+    preview: &Preview,
+) -> Result<DynamicImage, String> {
+    crate::loader::check_memory_before_decode(preview.width, preview.height, 1)?;
+    let img =
+        image::load_from_memory_with_format(&data[preview.offset..], image::ImageFormat::Jpeg)
+            .map_err(|e| e.to_string())?;
+    let exif = read_exif(data, previews);
+    Ok(crate::loader::apply_orientation(
+        img,
+        orientation(exif.as_ref()),
+    ))
+}
+
+/// The full size preview, or with `raw_demosaic` the sensor data developed
+/// by dcraw, falling back to the preview.
+pub fn decode(path: &Path) -> Result<LoadedImage, String> {
+    // The sensor is larger than the preview the item was listed with, its
+    // size is updated once loaded
+    let developed = if crate::config::AppConfig::get().options.raw_demosaic {
+        demosaic(path)
+            .map_err(|e| {
+                crate::rsiv_warn!("Demosaicing {:?} failed, showing the preview: {}", path, e)
+            })
+            .ok()
+    } else {
+        None
+    };
+
+    let img = match developed {
+        Some(img) => img,
+        None => {
+            let data = map_file(path)?;
+            let previews = previews(&data);
+            let largest = previews.first().ok_or("No embedded preview")?;
+            decode_preview(&data, &previews, largest)?
+        }
+    };
+
+    Ok(LoadedImage::new(
+        img.width(),
+        img.height(),
+        vec![FrameData {
+            pixels: img.to_rgba8().into_raw(),
+            delay: Duration::MAX,
+        }],
+    ))
+}
+
+/// dcraw writes a camera white balanced, rotated PPM to stdout.
+fn demosaic(path: &Path) -> Result<DynamicImage, String> {
+    let out = Command::new(DCRAW)
+        .args(["-c", "-w"])
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", DCRAW, e))?;
+    if !out.status.success() {
+        return Err(String::from_utf8_lossy(&out.stderr).trim().to_string());
+    }
+    image::load_from_memory_with_format(&out.stdout, image::ImageFormat::Pnm)
+        .map_err(|e| e.to_string())
+}
+
+/// Grid cells are usually covered by the small preview cameras store for
+/// their own screen, and the full size one is only decoded for large cells.
+pub fn thumbnail(path: &Path, size: u32) -> Result<(u32, u32, Vec<u8>), String> {
+    let data = map_file(path)?;
+    let previews = previews(&data);
+    let preview = previews
+        .iter()
+        .rfind(|p| p.width.max(p.height) >= size)
+        .or(previews.first())
+        .ok_or("No embedded preview")?;
+    let thumb = decode_preview(&data, &previews, preview)?.thumbnail(size, size);
+    Ok((thumb.width(), thumb.height(), thumb.to_rgba8().into_raw()))
+}
@@ -1,5 +1,8 @@
+use crate::adjustments::{Adjustments, ColorLut};
 use crate::cache::CacheManager;
 use crate::image_item::{ImageSlot, LoadedImage};
+use crate::resampling::{ResampleTarget, Resampling};
+use crate::tiles::{TileKey, TiledSource, TILE_SIZE};
 use rayon::prelude::*;
 
 pub struct GridColors {
@@ -12,11 +15,31 @@ pub struct GridColors {
 
 pub struct DrawImageParams<'a> {
     pub image: &'a LoadedImage,
+    pub cache: &'a CacheManager,
     pub frame_idx: usize,
     pub scale: f64,
     pub off_x: i32,
     pub off_y: i32,
     pub show_alpha: bool,
+    pub resampling: Resampling,
+    pub adjustments: Adjustments,
+    pub viewport: Viewport,     // The image is centered in it
+    pub clip: Option<Viewport>, // Drawn only inside it, the whole viewport if None
+}
+
+/// A rectangle of the window, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+}
+
+impl Viewport {
+    pub fn new(x: i32, y: i32, w: i32, h: i32) -> Self {
+        Self { x, y, w, h }
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -31,13 +54,210 @@ pub fn clear(frame: &mut [u8], color: (u8, u8, u8)) {
     });
 }
 
-pub fn draw_image(frame: &mut [u8], buf_w: i32, buf_h: i32, params: &DrawImageParams) {
+/// Fills a rectangle with a solid color, clipped to the frame.
+pub fn fill_rect(frame: &mut [u8], buf_w: i32, rect: Viewport, color: (u8, u8, u8)) {
+    let start_x = rect.x.clamp(0, buf_w) as usize;
+    let end_x = (rect.x + rect.w).clamp(0, buf_w) as usize;
+    if end_x <= start_x {
+        return;
+    }
+
+    frame
+        .par_chunks_exact_mut((buf_w * 4) as usize)
+        .enumerate()
+        .filter(|(y, _)| (*y as i32) >= rect.y && (*y as i32) < rect.y + rect.h)
+        .for_each(|(_, row_pixels)| {
+            for pixel in row_pixels[start_x * 4..end_x * 4].chunks_exact_mut(4) {
+                pixel.copy_from_slice(&[color.0, color.1, color.2, 255]);
+            }
+        });
+}
+
+/// Draws a one pixel outline just inside `rect`.
+pub fn outline_rect(frame: &mut [u8], buf_w: i32, rect: Viewport, color: (u8, u8, u8)) {
+    let (x, y, w, h) = (rect.x, rect.y, rect.w, rect.h);
+    fill_rect(frame, buf_w, Viewport::new(x, y, w, 1), color);
+    fill_rect(frame, buf_w, Viewport::new(x, y + h - 1, w, 1), color);
+    fill_rect(frame, buf_w, Viewport::new(x, y, 1, h), color);
+    fill_rect(frame, buf_w, Viewport::new(x + w - 1, y, 1, h), color);
+}
+
+/// Halves the brightness of the pixels inside `area` but outside `keep`.
+pub fn dim_outside(frame: &mut [u8], buf_w: i32, area: Viewport, keep: Viewport) {
+    let stride = (buf_w * 4) as usize;
+    let start_x = area.x.clamp(0, buf_w);
+    let end_x = (area.x + area.w).clamp(0, buf_w);
+
+    frame
+        .par_chunks_exact_mut(stride)
+        .enumerate()
+        .filter(|(y, _)| (*y as i32) >= area.y && (*y as i32) < area.y + area.h)
+        .for_each(|(y, row_pixels)| {
+            let row_kept = (y as i32) >= keep.y && (y as i32) < keep.y + keep.h;
+            for x in start_x..end_x {
+                if row_kept && x >= keep.x && x < keep.x + keep.w {
+                    continue;
+                }
+                let pixel = &mut row_pixels[x as usize * 4..x as usize * 4 + 3];
+                for channel in pixel {
+                    *channel /= 2;
+                }
+            }
+        });
+}
+
+/// Replaces the pixels inside `area` by their absolute difference with `other`,
+/// a frame of the same size. Identical pixels become black.
+pub fn difference(frame: &mut [u8], other: &[u8], buf_w: i32, area: Viewport) {
+    let stride = (buf_w * 4) as usize;
+    let start_x = area.x.clamp(0, buf_w) as usize;
+    let end_x = (area.x + area.w).clamp(0, buf_w) as usize;
+
+    frame
+        .par_chunks_exact_mut(stride)
+        .zip(other.par_chunks_exact(stride))
+        .enumerate()
+        .filter(|(y, _)| (*y as i32) >= area.y && (*y as i32) < area.y + area.h)
+        .for_each(|(_, (row_pixels, other_row))| {
+            for (dest, src) in row_pixels[start_x * 4..end_x * 4]
+                .chunks_exact_mut(4)
+                .zip(other_row[start_x * 4..end_x * 4].chunks_exact(4))
+            {
+                dest[0] = dest[0].abs_diff(src[0]);
+                dest[1] = dest[1].abs_diff(src[1]);
+                dest[2] = dest[2].abs_diff(src[2]);
+                dest[3] = 255;
+            }
+        });
+}
+
+struct Checkerboard {
+    size: i32,
+    color_1: (u8, u8, u8),
+    color_2: (u8, u8, u8),
+}
+
+impl Checkerboard {
+    fn color_at(&self, x: i32, y: i32) -> (u8, u8, u8) {
+        let is_dark = ((x / self.size) + (y / self.size)) % 2 == 0;
+        if is_dark {
+            self.color_2
+        } else {
+            self.color_1
+        }
+    }
+}
+
+/// Blends one straight-alpha source pixel over the destination at screen position (x, y).
+fn composite_pixel(
+    dest_pixel: &mut [u8],
+    src_p: &[u8],
+    x: i32,
+    y: i32,
+    checker: Option<&Checkerboard>,
+) {
+    let src_a = src_p[3] as u32;
+
+    if src_a == 255 {
+        // Opaque
+        dest_pixel.copy_from_slice(src_p);
+    } else if src_a > 0 {
+        // Transparent
+
+        // Determine background color (Checkerboard or Window BG)
+        let (bg_r, bg_g, bg_b) = if let Some(checker) = checker {
+            // Calculate checkerboard based on screen coordinates
+            let (r, g, b) = checker.color_at(x, y);
+            (r as u32, g as u32, b as u32)
+        } else {
+            // Use existing background color
+            (
+                dest_pixel[0] as u32,
+                dest_pixel[1] as u32,
+                dest_pixel[2] as u32,
+            )
+        };
+
+        let inv_a = 255 - src_a;
+
+        // Blend
+        dest_pixel[0] = ((src_p[0] as u32 * src_a + bg_r * inv_a) / 255) as u8;
+        dest_pixel[1] = ((src_p[1] as u32 * src_a + bg_g * inv_a) / 255) as u8;
+        dest_pixel[2] = ((src_p[2] as u32 * src_a + bg_b * inv_a) / 255) as u8;
+        dest_pixel[3] = 255;
+    }
+    // If src_a == 0, we do nothing (leave existing background),
+    // UNLESS we want to force draw the checkerboard over the cleared bg
+    else if let Some(checker) = checker {
+        let (r, g, b) = checker.color_at(x, y);
+        dest_pixel[0] = r;
+        dest_pixel[1] = g;
+        dest_pixel[2] = b;
+        dest_pixel[3] = 255;
+    }
+}
+
+/// How source pixels become screen pixels.
+#[derive(Clone, Copy)]
+struct Shading<'a> {
+    resampling: Resampling,
+    checker: Option<&'a Checkerboard>,
+    colors: Option<&'a ColorLut>,
+}
+
+impl Shading<'_> {
+    #[inline]
+    fn composite(&self, dest_pixel: &mut [u8], src_p: &[u8], x: i32, y: i32) {
+        match self.colors {
+            Some(lut) => composite_pixel(dest_pixel, &lut.apply(src_p), x, y, self.checker),
+            None => composite_pixel(dest_pixel, src_p, x, y, self.checker),
+        }
+    }
+}
+
+/// Source pixels and where their top-left corner lands on screen.
+struct Placement<'a> {
+    pixels: &'a [u8],
+    width: u32,
+    height: u32,
+    tl_x: f64,
+    tl_y: f64,
+    scale: f64,
+}
+
+/// Screen area to fill, end exclusive.
+#[derive(Clone, Copy)]
+struct Clip {
+    start_x: i32,
+    start_y: i32,
+    end_x: i32,
+    end_y: i32,
+}
+
+/// Where the top-left corner of an image lands on screen, centered in `view`
+/// and moved by the pan offsets.
+pub fn image_top_left(
+    width: u32,
+    height: u32,
+    scale: f64,
+    (off_x, off_y): (i32, i32),
+    view: Viewport,
+) -> (f64, f64) {
+    let scaled_w = width as f64 * scale;
+    let scaled_h = height as f64 * scale;
+    (
+        view.x as f64 + (view.w as f64 / 2.0) - (scaled_w / 2.0) + off_x as f64,
+        view.y as f64 + (view.h as f64 / 2.0) - (scaled_h / 2.0) + off_y as f64,
+    )
+}
+
+/// Draws and returns the tiles missing from the cache when the image is tiled.
+pub fn draw_image(frame: &mut [u8], buf_w: i32, params: &DrawImageParams) -> Vec<TileKey> {
     let image = params.image;
     let frame_idx = params.frame_idx;
     let scale = params.scale;
     let off_x = params.off_x;
     let off_y = params.off_y;
-    let show_alpha = params.show_alpha;
     let config = crate::config::AppConfig::get();
 
     let img_w = image.width as f64;
@@ -46,37 +266,227 @@ pub fn draw_image(frame: &mut [u8], buf_w: i32, buf_h: i32, params: &DrawImagePa
     let scaled_w = img_w * scale;
     let scaled_h = img_h * scale;
 
-    let tl_x = (buf_w as f64 / 2.0) - (scaled_w / 2.0) + off_x as f64;
-    let tl_y = (buf_h as f64 / 2.0) - (scaled_h / 2.0) + off_y as f64;
+    let view = params.viewport;
+    let (tl_x, tl_y) = image_top_left(image.width, image.height, scale, (off_x, off_y), view);
 
-    let start_x = tl_x.max(0.0) as i32;
-    let start_y = tl_y.max(0.0) as i32;
-    let end_x = (tl_x + scaled_w).min(buf_w as f64) as i32;
-    let end_y = (tl_y + scaled_h).min(buf_h as f64) as i32;
+    let area = params.clip.unwrap_or(view);
+    let start_x = tl_x.max(area.x as f64) as i32;
+    let start_y = tl_y.max(area.y as f64) as i32;
+    let end_x = (tl_x + scaled_w).min((area.x + area.w).min(buf_w) as f64) as i32;
+    let end_y = (tl_y + scaled_h).min((area.y + area.h) as f64) as i32;
 
     if end_x <= start_x || end_y <= start_y {
-        return;
+        return Vec::new();
     }
+    let clip = Clip {
+        start_x,
+        start_y,
+        end_x,
+        end_y,
+    };
 
-    let inv_scale = 1.0 / scale;
-    let src_width = image.width as i32;
-    let src_height = image.height as i32;
+    // Checkerboard colors
+    let checker = Checkerboard {
+        size: config.ui.checkerboard_size.max(1) as i32,
+        color_1: crate::utils::parse_color(&config.ui.checkerboard_color_1),
+        color_2: crate::utils::parse_color(&config.ui.checkerboard_color_2),
+    };
+    let colors = (!params.adjustments.is_identity()).then(|| ColorLut::new(&params.adjustments));
+    let shading = Shading {
+        resampling: params.resampling,
+        checker: params.show_alpha.then_some(&checker),
+        colors: colors.as_ref(),
+    };
+
+    if let Some(source) = &image.tiled {
+        return draw_tiled(frame, buf_w, params, source, (tl_x, tl_y), &clip, shading);
+    }
 
     // Safety check for empty frames
     if image.frames.is_empty() {
-        return;
+        if let Some(level) = &image.preview {
+            let placement = Placement {
+                pixels: &level.pixels,
+                width: level.width,
+                height: level.height,
+                tl_x,
+                tl_y,
+                scale: scale * (1u64 << level.shift) as f64,
+            };
+            draw_pixels(frame, buf_w, &placement, &clip, shading);
+        }
+        return Vec::new();
     }
 
     // Safety check for frame index
     let safe_frame_idx = frame_idx % image.frames.len();
-    let current_pixels = &image.frames[safe_frame_idx].pixels;
 
-    let global_src_x_start_f = (start_x as f64 - tl_x) * inv_scale;
+    // Sample from the closest halved level when shrinking by more than 2x
+    let mip = image.mip_level(safe_frame_idx, scale);
+    let placement = match &mip {
+        Some(level) => Placement {
+            pixels: &level.pixels,
+            width: level.width,
+            height: level.height,
+            tl_x,
+            tl_y,
+            scale: scale * (1u64 << level.shift) as f64,
+        },
+        None => Placement {
+            pixels: &image.frames[safe_frame_idx].pixels,
+            width: image.width,
+            height: image.height,
+            tl_x,
+            tl_y,
+            scale,
+        },
+    };
 
-    // Checkerboard colors
-    let check_size = config.ui.checkerboard_size.max(1) as i32;
-    let check_color_1 = crate::utils::parse_color(&config.ui.checkerboard_color_1);
-    let check_color_2 = crate::utils::parse_color(&config.ui.checkerboard_color_2);
+    draw_pixels(frame, buf_w, &placement, &clip, shading);
+    Vec::new()
+}
+
+fn draw_tiled(
+    frame: &mut [u8],
+    buf_w: i32,
+    params: &DrawImageParams,
+    source: &TiledSource,
+    (tl_x, tl_y): (f64, f64),
+    clip: &Clip,
+    shading: Shading,
+) -> Vec<TileKey> {
+    let overview = Placement {
+        pixels: &source.overview.pixels,
+        width: source.overview.width,
+        height: source.overview.height,
+        tl_x,
+        tl_y,
+        scale: params.scale * (1u64 << source.overview_level) as f64,
+    };
+
+    let level = source.level_for_scale(params.scale);
+    if level == source.overview_level {
+        draw_pixels(frame, buf_w, &overview, clip, shading);
+        return Vec::new();
+    }
+
+    let level_scale = params.scale * (1u64 << level) as f64;
+    let tile_span = TILE_SIZE as f64 * level_scale;
+    let (cols, rows) = source.tile_grid(level);
+
+    let first_col = ((clip.start_x as f64 - tl_x) / tile_span).floor().max(0.0) as u32;
+    let last_col = (((clip.end_x as f64 - tl_x) / tile_span).ceil().max(0.0) as u32).min(cols);
+    let first_row = ((clip.start_y as f64 - tl_y) / tile_span).floor().max(0.0) as u32;
+    let last_row = (((clip.end_y as f64 - tl_y) / tile_span).ceil().max(0.0) as u32).min(rows);
+
+    let mut missing = Vec::new();
+    for ty in first_row..last_row {
+        for tx in first_col..last_col {
+            let left = tl_x + tx as f64 * tile_span;
+            let top = tl_y + ty as f64 * tile_span;
+
+            // Neighbours share edges computed the same way, so there are no seams
+            let tile_clip = Clip {
+                start_x: (left.floor() as i32).max(clip.start_x),
+                start_y: (top.floor() as i32).max(clip.start_y),
+                end_x: ((left + tile_span).floor() as i32).min(clip.end_x),
+                end_y: ((top + tile_span).floor() as i32).min(clip.end_y),
+            };
+            if tile_clip.end_x <= tile_clip.start_x || tile_clip.end_y <= tile_clip.start_y {
+                continue;
+            }
+
+            let key = TileKey {
+                level,
+                x: tx,
+                y: ty,
+            };
+            match params.cache.get_tile(&source.path, key) {
+                Some(tile) => {
+                    let placement = Placement {
+                        pixels: &tile.pixels,
+                        width: tile.width,
+                        height: tile.height,
+                        tl_x: left,
+                        tl_y: top,
+                        scale: level_scale,
+                    };
+                    draw_pixels(frame, buf_w, &placement, &tile_clip, shading);
+                }
+                None => {
+                    // Blurry overview until the tile arrives
+                    missing.push(key);
+                    draw_pixels(frame, buf_w, &overview, &tile_clip, shading);
+                }
+            }
+        }
+    }
+    missing
+}
+
+fn draw_pixels(frame: &mut [u8], buf_w: i32, src: &Placement, clip: &Clip, shading: Shading) {
+    let Clip {
+        start_x,
+        start_y,
+        end_x,
+        end_y,
+    } = *clip;
+    let (tl_x, tl_y, scale) = (src.tl_x, src.tl_y, src.scale);
+    let current_pixels = src.pixels;
+
+    let inv_scale = 1.0 / scale;
+    let src_width = src.width as i32;
+    let src_height = src.height as i32;
+
+    // 1:1 mapping on whole pixels is identical for every filter
+    let pixel_aligned = scale == 1.0 && tl_x.fract() == 0.0 && tl_y.fract() == 0.0;
+
+    let resampling = shading.resampling;
+    if resampling != Resampling::Nearest && !pixel_aligned {
+        let target = ResampleTarget {
+            tl_x,
+            tl_y,
+            scale,
+            start_x,
+            end_x,
+            start_y,
+            end_y,
+        };
+        let region =
+            crate::resampling::resample(current_pixels, src.width, src.height, &target, resampling);
+        let region_stride = (end_x - start_x) as usize * 4;
+
+        frame
+            .par_chunks_exact_mut((buf_w * 4) as usize)
+            .enumerate()
+            .for_each(|(y, row_pixels)| {
+                let y = y as i32;
+                if y < start_y || y >= end_y {
+                    return;
+                }
+
+                let draw_slice_start = (start_x as usize) * 4;
+                let draw_slice_end = (end_x as usize) * 4;
+                if draw_slice_end > row_pixels.len() {
+                    return;
+                }
+
+                let src_row_start = (y - start_y) as usize * region_stride;
+                let src_row = &region[src_row_start..src_row_start + region_stride];
+                let dest_slice = &mut row_pixels[draw_slice_start..draw_slice_end];
+
+                for (i, (dest_pixel, src_p)) in dest_slice
+                    .chunks_exact_mut(4)
+                    .zip(src_row.chunks_exact(4))
+                    .enumerate()
+                {
+                    shading.composite(dest_pixel, src_p, start_x + i as i32, y);
+                }
+            });
+        return;
+    }
+
+    let global_src_x_start_f = (start_x as f64 - tl_x) * inv_scale;
 
     frame
         .par_chunks_exact_mut((buf_w * 4) as usize)
@@ -111,61 +521,7 @@ pub fn draw_image(frame: &mut [u8], buf_w: i32, buf_h: i32, params: &DrawImagePa
                         let src_idx = src_row_start + (src_x as usize * 4);
                         if src_idx + 4 <= current_pixels.len() {
                             let src_p = &current_pixels[src_idx..src_idx + 4];
-                            let src_a = src_p[3] as u32;
-
-                            if src_a == 255 {
-                                // Opaque
-                                dest_pixel.copy_from_slice(src_p);
-                            } else if src_a > 0 {
-                                // Transparent
-
-                                // Determine background color (Checkerboard or Window BG)
-                                let (bg_r, bg_g, bg_b) = if show_alpha {
-                                    // Calculate checkerboard based on screen coordinates
-                                    let is_dark =
-                                        ((current_screen_x / check_size) + (y / check_size)) % 2
-                                            == 0;
-                                    let (r, g, b) = if is_dark {
-                                        check_color_2
-                                    } else {
-                                        check_color_1
-                                    };
-                                    (r as u32, g as u32, b as u32)
-                                } else {
-                                    // Use existing background color
-                                    (
-                                        dest_pixel[0] as u32,
-                                        dest_pixel[1] as u32,
-                                        dest_pixel[2] as u32,
-                                    )
-                                };
-
-                                let inv_a = 255 - src_a;
-
-                                // Blend
-                                dest_pixel[0] =
-                                    ((src_p[0] as u32 * src_a + bg_r * inv_a) / 255) as u8;
-                                dest_pixel[1] =
-                                    ((src_p[1] as u32 * src_a + bg_g * inv_a) / 255) as u8;
-                                dest_pixel[2] =
-                                    ((src_p[2] as u32 * src_a + bg_b * inv_a) / 255) as u8;
-                                dest_pixel[3] = 255;
-                            }
-                            // If src_a == 0, we do nothing (leave existing background),
-                            // UNLESS we want to force draw the checkerboard over the cleared bg
-                            else if show_alpha {
-                                let is_dark =
-                                    ((current_screen_x / check_size) + (y / check_size)) % 2 == 0;
-                                let (r, g, b) = if is_dark {
-                                    check_color_2
-                                } else {
-                                    check_color_1
-                                };
-                                dest_pixel[0] = r;
-                                dest_pixel[1] = g;
-                                dest_pixel[2] = b;
-                                dest_pixel[3] = 255;
-                            }
+                            shading.composite(dest_pixel, src_p, current_screen_x, y);
                         }
                     }
                     src_x_f += inv_scale;
@@ -174,35 +530,92 @@ pub fn draw_image(frame: &mut [u8], buf_w: i32, buf_h: i32, params: &DrawImagePa
         });
 }
 
-pub fn draw_grid(
-    frame: &mut [u8],
-    buf_w: i32,
-    buf_h: i32,
-    images: &[ImageSlot],
-    cache: &CacheManager,
-    selected_idx: usize,
-    colors: &GridColors,
-    marked_paths: &std::collections::HashSet<String>,
-) {
+pub struct DrawGridParams<'a> {
+    pub images: &'a [ImageSlot],
+    pub cache: &'a CacheManager,
+    pub selected_idx: usize,
+    pub colors: &'a GridColors,
+    pub marked_paths: &'a indexmap::IndexSet<String>,
+}
+
+/// Cell layout of the thumbnail grid, shared by drawing and mouse hit-testing.
+pub struct GridLayout {
+    pub cols: u32,
+    pub cell_size: u32,
+    pub padding: u32,
+    pub margin_x: u32,
+    pub scroll_y: i32,
+}
+
+impl GridLayout {
+    pub fn new(buf_w: i32, buf_h: i32, selected_idx: usize) -> Self {
+        let config = crate::config::AppConfig::get();
+        let padding = config.options.grid_padding;
+        let cell_size = config.options.thumbnail_size + padding;
+
+        let cols = (buf_w as u32 / cell_size).max(1);
+
+        let grid_width = cols * cell_size;
+        let margin_x = (buf_w as u32).saturating_sub(grid_width) / 2 + padding / 2;
+
+        let current_row = (selected_idx as u32) / cols;
+        let scroll_y = if current_row * cell_size > buf_h as u32 / 2 {
+            (current_row * cell_size) as i32 - (buf_h / 2) + (cell_size as i32 / 2)
+        } else {
+            0
+        };
+
+        Self {
+            cols,
+            cell_size,
+            padding,
+            margin_x,
+            scroll_y,
+        }
+    }
+
+    /// Top-left corner of the thumbnail area of cell `idx`.
+    pub fn cell_origin(&self, idx: usize) -> (i32, i32) {
+        let col = (idx as u32) % self.cols;
+        let row = (idx as u32) / self.cols;
+        let x_cell = (self.margin_x + col * self.cell_size) as i32;
+        let y_cell = (row * self.cell_size + self.padding / 2) as i32 - self.scroll_y;
+        (x_cell, y_cell)
+    }
+
+    /// Returns the index of the cell under the screen position, if any.
+    pub fn hit_test(&self, x: f64, y: f64, count: usize) -> Option<usize> {
+        let grid_x = x - (self.margin_x - self.padding / 2) as f64;
+        let grid_y = y + self.scroll_y as f64;
+        if grid_x < 0.0 || grid_y < 0.0 {
+            return None;
+        }
+
+        let col = (grid_x / self.cell_size as f64) as u32;
+        let row = (grid_y / self.cell_size as f64) as u32;
+        if col >= self.cols {
+            return None;
+        }
+
+        let idx = (row * self.cols + col) as usize;
+        (idx < count).then_some(idx)
+    }
+}
+
+pub fn draw_grid(frame: &mut [u8], buf_w: i32, buf_h: i32, params: &DrawGridParams) {
+    let images = params.images;
+    let cache = params.cache;
+    let selected_idx = params.selected_idx;
+    let colors = params.colors;
+    let marked_paths = params.marked_paths;
     let config = crate::config::AppConfig::get();
     let thumb_size = config.options.thumbnail_size;
-    let padding = config.options.grid_padding;
-    let cell_size = thumb_size + padding;
     let border_gap = config.ui.selected_border_padding as i32;
     let border_thickness = config.ui.selected_border_width as i32;
     let mark_size = config.ui.mark_indicator_size as i32;
+    let cell_size = thumb_size + config.options.grid_padding;
 
-    let cols = (buf_w as u32 / cell_size).max(1);
-
-    let grid_width = cols * cell_size;
-    let margin_x = (buf_w as u32 - grid_width) / 2 + padding / 2;
-
-    let current_row = (selected_idx as u32) / cols;
-    let scroll_y = if current_row * cell_size > buf_h as u32 / 2 {
-        (current_row * cell_size) as i32 - (buf_h / 2) + (cell_size as i32 / 2)
-    } else {
-        0
-    };
+    let layout = GridLayout::new(buf_w, buf_h, selected_idx);
 
     clear(frame, colors.bg);
 
@@ -211,11 +624,7 @@ pub fn draw_grid(
         .iter()
         .enumerate()
         .filter_map(|(i, slot)| {
-            let col = (i as u32) % cols;
-            let row = (i as u32) / cols;
-
-            let x_cell = (margin_x + col * cell_size) as i32;
-            let y_cell = (row * cell_size + padding / 2) as i32 - scroll_y;
+            let (x_cell, y_cell) = layout.cell_origin(i);
 
             if y_cell + (cell_size as i32) < 0 || y_cell > buf_h {
                 return None;
@@ -448,10 +857,10 @@ pub fn draw_grid(
                         (p_w, p_h, *base_t_x, *base_t_y)
                     };
 
-                    let m_x = target_x + target_w + border_gap + border_thickness / 2
-                        - mark_size / 2;
-                    let m_y = target_y + target_h + border_gap + border_thickness / 2
-                        - mark_size / 2;
+                    let m_x =
+                        target_x + target_w + border_gap + border_thickness / 2 - mark_size / 2;
+                    let m_y =
+                        target_y + target_h + border_gap + border_thickness / 2 - mark_size / 2;
 
                     if y >= m_y && y < m_y + mark_size {
                         let start_draw_x = m_x.max(0);
@@ -0,0 +1,204 @@
+use rayon::prelude::*;
+use serde::Deserialize;
+
+/// Output rows resampled together. Each band only runs the horizontal pass
+/// over the source rows it needs, which keeps the scratch buffer small.
+const BAND_ROWS: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum Resampling {
+    Nearest,
+    Bilinear,
+    Area,
+    Lanczos,
+}
+
+/// Source pixels contributing to one destination pixel along a single axis.
+struct Contribution {
+    start: usize,
+    weights: Vec<f32>,
+}
+
+/// Where a region of the screen maps onto the source image.
+pub struct ResampleTarget {
+    pub tl_x: f64,
+    pub tl_y: f64,
+    pub scale: f64,
+    pub start_x: i32,
+    pub end_x: i32,
+    pub start_y: i32,
+    pub end_y: i32,
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-8 {
+        1.0
+    } else {
+        let a = x * std::f64::consts::PI;
+        a.sin() / a
+    }
+}
+
+fn kernel(filter: Resampling, x: f64) -> f64 {
+    let x = x.abs();
+    match filter {
+        Resampling::Bilinear => (1.0 - x).max(0.0),
+        Resampling::Lanczos if x < 3.0 => sinc(x) * sinc(x / 3.0),
+        _ => 0.0,
+    }
+}
+
+fn contributions(
+    filter: Resampling,
+    dst_start: i32,
+    dst_end: i32,
+    tl: f64,
+    scale: f64,
+    src_len: usize,
+) -> Vec<Contribution> {
+    let inv_scale = 1.0 / scale;
+    let src_max = src_len as f64;
+
+    (dst_start..dst_end)
+        .map(|d| {
+            let mut start = usize::MAX;
+            let mut weights = Vec::new();
+
+            if filter == Resampling::Area {
+                // Exact coverage of the destination pixel footprint
+                let lo = ((d as f64 - tl) * inv_scale).clamp(0.0, src_max);
+                let hi = ((d as f64 + 1.0 - tl) * inv_scale).clamp(0.0, src_max);
+                let first = lo.floor() as usize;
+                let last = (hi.ceil() as usize).min(src_len);
+                for i in first..last {
+                    let overlap = (hi.min(i as f64 + 1.0) - lo.max(i as f64)).max(0.0);
+                    if overlap > 0.0 {
+                        start = start.min(i);
+                        weights.push(overlap as f32);
+                    }
+                }
+            } else {
+                // Widen the kernel when downscaling so every source pixel contributes
+                let filter_scale = inv_scale.max(1.0);
+                let radius = if filter == Resampling::Lanczos {
+                    3.0
+                } else {
+                    1.0
+                };
+                let support = radius * filter_scale;
+                let center = (d as f64 + 0.5 - tl) * inv_scale;
+                let first = (center - support).floor().max(0.0) as usize;
+                let last = ((center + support).ceil().max(0.0) as usize).min(src_len);
+                for i in first..last {
+                    let w = kernel(filter, (i as f64 + 0.5 - center) / filter_scale);
+                    if start == usize::MAX {
+                        start = i;
+                    }
+                    weights.push(w as f32);
+                }
+            }
+
+            let sum: f32 = weights.iter().sum();
+            if sum.abs() > f32::EPSILON {
+                weights.iter_mut().for_each(|w| *w /= sum);
+            } else {
+                weights.clear();
+            }
+
+            Contribution {
+                start: if start == usize::MAX { 0 } else { start },
+                weights,
+            }
+        })
+        .collect()
+}
+
+/// Resamples the visible part of an RGBA image with a separable filter.
+///
+/// Returns `(end_x - start_x) * (end_y - start_y)` straight-alpha RGBA pixels.
+/// Colors are filtered premultiplied to avoid dark fringes around transparency.
+pub fn resample(
+    pixels: &[u8],
+    src_w: u32,
+    src_h: u32,
+    target: &ResampleTarget,
+    filter: Resampling,
+) -> Vec<u8> {
+    let dst_w = (target.end_x - target.start_x).max(0) as usize;
+    let dst_h = (target.end_y - target.start_y).max(0) as usize;
+    let mut out = vec![0u8; dst_w * dst_h * 4];
+    if dst_w == 0 || dst_h == 0 || pixels.len() < src_w as usize * src_h as usize * 4 {
+        return out;
+    }
+
+    let cols = contributions(
+        filter,
+        target.start_x,
+        target.end_x,
+        target.tl_x,
+        target.scale,
+        src_w as usize,
+    );
+    let rows = contributions(
+        filter,
+        target.start_y,
+        target.end_y,
+        target.tl_y,
+        target.scale,
+        src_h as usize,
+    );
+    let src_stride = src_w as usize * 4;
+
+    out.par_chunks_mut(dst_w * 4 * BAND_ROWS)
+        .enumerate()
+        .for_each(|(band, band_out)| {
+            let band_rows =
+                &rows[band * BAND_ROWS..(band * BAND_ROWS + band_out.len() / (dst_w * 4))];
+
+            let r0 = band_rows.iter().map(|c| c.start).min().unwrap_or(0);
+            let r1 = band_rows
+                .iter()
+                .map(|c| c.start + c.weights.len())
+                .max()
+                .unwrap_or(r0)
+                .max(r0);
+
+            // Horizontal pass over the source rows this band touches
+            let mut tmp = vec![[0f32; 4]; (r1 - r0) * dst_w];
+            for (r, tmp_row) in tmp.chunks_exact_mut(dst_w).enumerate() {
+                let src_row = &pixels[(r0 + r) * src_stride..(r0 + r + 1) * src_stride];
+                for (acc, col) in tmp_row.iter_mut().zip(&cols) {
+                    for (k, &w) in col.weights.iter().enumerate() {
+                        let p = &src_row[(col.start + k) * 4..(col.start + k) * 4 + 4];
+                        let a = p[3] as f32 * w;
+                        acc[0] += p[0] as f32 * a;
+                        acc[1] += p[1] as f32 * a;
+                        acc[2] += p[2] as f32 * a;
+                        acc[3] += a;
+                    }
+                }
+            }
+
+            // Vertical pass into the output band
+            for (row, out_row) in band_rows.iter().zip(band_out.chunks_exact_mut(dst_w * 4)) {
+                for (x, dest) in out_row.chunks_exact_mut(4).enumerate() {
+                    let mut acc = [0f32; 4];
+                    for (k, &w) in row.weights.iter().enumerate() {
+                        let t = &tmp[(row.start + k - r0) * dst_w + x];
+                        acc[0] += t[0] * w;
+                        acc[1] += t[1] * w;
+                        acc[2] += t[2] * w;
+                        acc[3] += t[3] * w;
+                    }
+                    if acc[3] > 0.0 {
+                        dest[0] = (acc[0] / acc[3]).round().clamp(0.0, 255.0) as u8;
+                        dest[1] = (acc[1] / acc[3]).round().clamp(0.0, 255.0) as u8;
+                        dest[2] = (acc[2] / acc[3]).round().clamp(0.0, 255.0) as u8;
+                        dest[3] = acc[3].round().clamp(0.0, 255.0) as u8;
+                    }
+                }
+            }
+        });
+
+    out
+}
@@ -0,0 +1,637 @@
+use crate::app::{App, AppEvent, InputMode};
+use crate::image_item::{ImageFormat, ImageSlot, Transform};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageEncoder, ImageReader};
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const ORIENTATION_TAG: u16 = 0x0112;
+
+/// How a transformed image is written back to its file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SaveMethod {
+    OrientationTag, // JPEG only, the pixels are left untouched
+    Reencode,
+}
+
+/// Where the original is kept when an image is re-encoded.
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push("~");
+    PathBuf::from(name)
+}
+
+/// Backups (and editor backups) are not listed next to their original.
+/// Other files ending in `~` are listed as usual.
+pub fn is_backup(path: &Path) -> bool {
+    let Some(name) = path.file_name() else {
+        return false;
+    };
+    name.as_encoded_bytes()
+        .strip_suffix(b"~")
+        .is_some_and(|original| {
+            // SAFETY: cutting an ASCII character off the end leaves valid encoded bytes
+            let original = unsafe { OsStr::from_encoded_bytes_unchecked(original) };
+            !original.is_empty() && path.with_file_name(original).exists()
+        })
+}
+
+/// Writes `transform` into the file at `path`. Runs off the UI thread.
+pub fn save(path: &Path, transform: Transform, method: SaveMethod) -> Result<(), String> {
+    let data = fs::read(path).map_err(|e| e.to_string())?;
+    match method {
+        SaveMethod::OrientationTag => {
+            let mut data = data;
+            write_jpeg_orientation(&mut data, transform)?;
+            replace_file(path, &data, false)
+        }
+        SaveMethod::Reencode => {
+            let encoded = reencode(&data, transform)?;
+            replace_file(path, &encoded, true)
+        }
+    }
+}
+
+/// Writes a virtual image with `transform` to a new file where it would be as
+/// a file, e.g. "stdin.png", then "stdin-2.png". Runs off the UI thread.
+pub fn save_copy(path: &Path, transform: Transform) -> Result<PathBuf, String> {
+    let mut data = crate::virtual_path::read(path).ok_or("Not a virtual image")??;
+    if !transform.is_identity() {
+        if image::guess_format(&data).ok() == Some(image::ImageFormat::Jpeg) {
+            write_jpeg_orientation(&mut data, transform)?;
+        } else {
+            data = reencode(&data, transform)?;
+        }
+    }
+
+    let path = crate::virtual_path::beside(path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    write_unique(
+        path.parent().unwrap_or(Path::new("")),
+        &stem,
+        "",
+        &extension,
+        &data,
+    )
+}
+
+/// Creates a file that did not exist yet, named like "photo-crop.jpg" and then
+/// "photo-crop-2.jpg". `create_new` makes sure nothing is overwritten, even by
+/// a file that appears meanwhile.
+fn create_unique(
+    dir: &Path,
+    stem: &str,
+    suffix: &str,
+    extension: &str,
+) -> Result<(PathBuf, fs::File), String> {
+    let extension = match extension {
+        "" => String::new(),
+        e => format!(".{}", e),
+    };
+    let mut n = 1;
+    loop {
+        let name = match n {
+            1 => format!("{}{}{}", stem, suffix, extension),
+            n => format!("{}{}-{}{}", stem, suffix, n, extension),
+        };
+        let dest = dir.join(name);
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&dest)
+        {
+            Ok(file) => return Ok((dest, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => n += 1,
+            Err(e) => return Err(format!("Failed to create {:?}: {}", dest, e)),
+        }
+    }
+}
+
+/// Writes `data` to a new file named by `create_unique`, removed again if
+/// writing fails.
+pub fn write_unique(
+    dir: &Path,
+    stem: &str,
+    suffix: &str,
+    extension: &str,
+    data: &[u8],
+) -> Result<PathBuf, String> {
+    let (dest, mut file) = create_unique(dir, stem, suffix, extension)?;
+    if let Err(e) = file.write_all(data) {
+        let _ = fs::remove_file(&dest);
+        return Err(e.to_string());
+    }
+    Ok(dest)
+}
+
+/// Decodes the image, bakes the file orientation and `transform` into the pixels,
+/// and encodes it in the same format. EXIF and ICC data are kept where supported.
+fn reencode(data: &[u8], transform: Transform) -> Result<Vec<u8>, String> {
+    let format = image::guess_format(data).map_err(|e| e.to_string())?;
+    let mut decoder = ImageReader::with_format(Cursor::new(data), format)
+        .into_decoder()
+        .map_err(|e| e.to_string())?;
+    let mut exif = decoder.exif_metadata().ok().flatten();
+    let icc = decoder.icc_profile().ok().flatten();
+    let mut img = DynamicImage::from_decoder(decoder).map_err(|e| e.to_string())?;
+
+    // The pixels are stored upright, so the tag must not rotate them again
+    let file_orientation = exif
+        .as_mut()
+        .and_then(|exif| Orientation::remove_from_exif_chunk(exif))
+        .unwrap_or(Orientation::NoTransforms);
+    img.apply_orientation(file_orientation);
+    img.apply_orientation(transform.to_orientation());
+
+    encode(&img, format, exif, icc)
+}
+
+/// Encodes `img` in `format`. EXIF and ICC data are written for JPEG and PNG.
+pub fn encode(
+    img: &DynamicImage,
+    format: image::ImageFormat,
+    exif: Option<Vec<u8>>,
+    icc: Option<Vec<u8>>,
+) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let result = match format {
+        image::ImageFormat::Jpeg => {
+            let quality = crate::config::AppConfig::get().options.jpeg_quality;
+            let mut encoder = JpegEncoder::new_with_quality(&mut out, quality.clamp(1, 100));
+            keep_metadata(&mut encoder, exif, icc);
+            // JPEG has no alpha channel
+            if img.color().has_alpha() {
+                DynamicImage::ImageRgb8(img.to_rgb8()).write_with_encoder(encoder)
+            } else {
+                img.write_with_encoder(encoder)
+            }
+        }
+        image::ImageFormat::Png => {
+            let mut encoder = PngEncoder::new(&mut out);
+            keep_metadata(&mut encoder, exif, icc);
+            img.write_with_encoder(encoder)
+        }
+        _ => img.write_to(&mut Cursor::new(&mut out), format),
+    };
+    result.map_err(|e| e.to_string())?;
+    Ok(out)
+}
+
+fn keep_metadata(encoder: &mut impl ImageEncoder, exif: Option<Vec<u8>>, icc: Option<Vec<u8>>) {
+    if let Some(exif) = exif {
+        let _ = encoder.set_exif_metadata(exif);
+    }
+    if let Some(icc) = icc {
+        let _ = encoder.set_icc_profile(icc);
+    }
+}
+
+/// Sets the EXIF orientation of a JPEG so it shows with `transform` applied.
+/// A file without EXIF data gets a minimal EXIF segment, and EXIF data
+/// without an orientation gets the entry added.
+fn write_jpeg_orientation(data: &mut Vec<u8>, transform: Transform) -> Result<(), String> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return Err("Not a JPEG file".into());
+    }
+
+    let mut pos = 2;
+    let mut insert_at = 2;
+    while pos + 4 <= data.len() && data[pos] == 0xFF {
+        let marker = data[pos + 1];
+        // Start of scan: no metadata after this
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        // The length counts its own two bytes
+        if len < 2 || pos + 2 + len > data.len() {
+            return Err("Corrupt JPEG segment".into());
+        }
+        let end = pos + 2 + len;
+
+        if marker == 0xE1 && data[pos + 4..end].starts_with(EXIF_HEADER) {
+            let tiff_start = pos + 4 + EXIF_HEADER.len();
+            let tiff = &data[tiff_start..end];
+            let ifd = Ifd0::read(tiff).ok_or("Unreadable EXIF data, re-encode instead")?;
+            let Some(entry) = ifd.find(tiff, ORIENTATION_TAG) else {
+                let value = transform.to_orientation().to_exif() as u16;
+                return add_orientation(data, pos, end, &ifd, value);
+            };
+            // A single SHORT, stored in the entry itself
+            if read_u16(&tiff[entry + 2..], ifd.big_endian) != 3 {
+                return Err("The EXIF orientation is not a SHORT, re-encode instead".into());
+            }
+            let value_at = tiff_start + entry + 8;
+
+            let current = read_u16(&data[value_at..], ifd.big_endian);
+            let file_orientation = Orientation::from_exif(current as u8)
+                .map(Transform::from_orientation)
+                .unwrap_or_default();
+            let value = file_orientation.then(transform).to_orientation().to_exif() as u16;
+            data[value_at..value_at + 2].copy_from_slice(&u16_bytes(value, ifd.big_endian));
+            return Ok(());
+        }
+
+        // A new EXIF segment goes after the JFIF header
+        if marker == 0xE0 && pos == 2 {
+            insert_at = end;
+        }
+        pos = end;
+    }
+
+    let value = transform.to_orientation().to_exif() as u16;
+    data.splice(insert_at..insert_at, exif_segment(value));
+    Ok(())
+}
+
+/// The first directory of the TIFF structure in EXIF data, `offset` bytes
+/// into it and holding `count` entries.
+struct Ifd0 {
+    big_endian: bool,
+    offset: usize,
+    count: usize,
+}
+
+impl Ifd0 {
+    fn read(tiff: &[u8]) -> Option<Self> {
+        let big_endian = match tiff.get(..4)? {
+            [0x4D, 0x4D, 0, 42] => true,
+            [0x49, 0x49, 42, 0] => false,
+            _ => return None,
+        };
+        let offset = read_u32(tiff.get(4..8)?, big_endian) as usize;
+        let count = read_u16(tiff.get(offset..offset + 2)?, big_endian) as usize;
+        // The entries, then the offset of the next directory
+        tiff.get(offset + 2..offset + 2 + count * 12 + 4)?;
+        Some(Self {
+            big_endian,
+            offset,
+            count,
+        })
+    }
+
+    fn entries<'a>(&self, tiff: &'a [u8]) -> &'a [u8] {
+        &tiff[self.offset + 2..self.offset + 2 + self.count * 12]
+    }
+
+    /// Offset of the entry for `tag`.
+    fn find(&self, tiff: &[u8], tag: u16) -> Option<usize> {
+        self.entries(tiff)
+            .chunks_exact(12)
+            .position(|entry| read_u16(entry, self.big_endian) == tag)
+            .map(|i| self.offset + 2 + i * 12)
+    }
+}
+
+/// Adds an orientation entry to IFD0 of the EXIF segment from `pos` to `end`.
+///
+/// The directory is copied to the end of the segment with the entry added, and
+/// the header pointed at the copy. Nothing else in the TIFF structure moves, so
+/// the offsets it holds stay right.
+fn add_orientation(
+    data: &mut Vec<u8>,
+    pos: usize,
+    end: usize,
+    ifd: &Ifd0,
+    value: u16,
+) -> Result<(), String> {
+    let tiff_start = pos + 4 + EXIF_HEADER.len();
+    let tiff = &data[tiff_start..end];
+    let big_endian = ifd.big_endian;
+    let count = u16::try_from(ifd.count + 1).map_err(|_| "Too many EXIF entries")?;
+
+    let mut entry = Vec::with_capacity(12);
+    entry.extend_from_slice(&u16_bytes(ORIENTATION_TAG, big_endian));
+    entry.extend_from_slice(&u16_bytes(3, big_endian)); // SHORT
+    entry.extend_from_slice(&u32_bytes(1, big_endian));
+    entry.extend_from_slice(&u16_bytes(value, big_endian));
+    entry.extend_from_slice(&[0, 0]);
+
+    // Directories start on a word boundary and keep their entries sorted by tag
+    let pad = tiff.len() % 2;
+    let mut directory = vec![0; pad];
+    directory.extend_from_slice(&u16_bytes(count, big_endian));
+    let entries = ifd.entries(tiff);
+    let before = entries
+        .chunks_exact(12)
+        .take_while(|e| read_u16(e, big_endian) < ORIENTATION_TAG)
+        .count()
+        * 12;
+    directory.extend_from_slice(&entries[..before]);
+    directory.extend_from_slice(&entry);
+    directory.extend_from_slice(&entries[before..]);
+    let next = ifd.offset + 2 + ifd.count * 12;
+    directory.extend_from_slice(&tiff[next..next + 4]);
+
+    let len = u16::try_from(end - pos - 2 + directory.len())
+        .map_err(|_| "The EXIF segment is full, re-encode instead")?;
+    let offset = (tiff.len() + pad) as u32;
+    data[tiff_start + 4..tiff_start + 8].copy_from_slice(&u32_bytes(offset, big_endian));
+    data[pos + 2..pos + 4].copy_from_slice(&len.to_be_bytes());
+    data.splice(end..end, directory);
+    Ok(())
+}
+
+fn read_u16(bytes: &[u8], big_endian: bool) -> u16 {
+    if big_endian {
+        u16::from_be_bytes([bytes[0], bytes[1]])
+    } else {
+        u16::from_le_bytes([bytes[0], bytes[1]])
+    }
+}
+
+fn read_u32(bytes: &[u8], big_endian: bool) -> u32 {
+    let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+    if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    }
+}
+
+fn u16_bytes(value: u16, big_endian: bool) -> [u8; 2] {
+    if big_endian {
+        value.to_be_bytes()
+    } else {
+        value.to_le_bytes()
+    }
+}
+
+fn u32_bytes(value: u32, big_endian: bool) -> [u8; 4] {
+    if big_endian {
+        value.to_be_bytes()
+    } else {
+        value.to_le_bytes()
+    }
+}
+
+/// An APP1 segment holding only the orientation.
+fn exif_segment(orientation: u16) -> Vec<u8> {
+    let mut tiff = vec![0x4D, 0x4D, 0, 42, 0, 0, 0, 8]; // Big endian, IFD0 right after
+    tiff.extend_from_slice(&1u16.to_be_bytes());
+    tiff.extend_from_slice(&ORIENTATION_TAG.to_be_bytes());
+    tiff.extend_from_slice(&3u16.to_be_bytes()); // SHORT
+    tiff.extend_from_slice(&1u32.to_be_bytes());
+    tiff.extend_from_slice(&orientation.to_be_bytes());
+    tiff.extend_from_slice(&[0, 0]);
+    tiff.extend_from_slice(&0u32.to_be_bytes()); // No next IFD
+
+    let len = (2 + EXIF_HEADER.len() + tiff.len()) as u16;
+    let mut segment = vec![0xFF, 0xE1];
+    segment.extend_from_slice(&len.to_be_bytes());
+    segment.extend_from_slice(EXIF_HEADER);
+    segment.extend_from_slice(&tiff);
+    segment
+}
+
+/// Replaces the file through a temporary one, so it is never left half written.
+/// With `backup`, the original is kept unless an older backup already exists.
+fn replace_file(path: &Path, data: &[u8], backup: bool) -> Result<(), String> {
+    let backup_file = backup_path(path);
+    if backup && !backup_file.exists() {
+        fs::copy(path, &backup_file)
+            .map_err(|e| format!("Failed to back up to {:?}: {}", backup_file, e))?;
+    }
+
+    let name = path.file_name().ok_or("Not a file")?.to_string_lossy();
+    let temp = path.with_file_name(format!(".{}.rsiv-{}.tmp", name, std::process::id()));
+    let result = (|| -> Result<(), String> {
+        fs::write(&temp, data).map_err(|e| e.to_string())?;
+        if let Ok(meta) = fs::metadata(path) {
+            let _ = fs::set_permissions(&temp, meta.permissions());
+        }
+        fs::rename(&temp, path).map_err(|e| e.to_string())
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+impl App {
+    /// Asks how to save the rotations and flips of the current image.
+    pub fn request_save(&mut self) -> bool {
+        let Some(ImageSlot::MetadataLoaded(item)) = self.images.get(self.current_index) else {
+            return false;
+        };
+        let Some(image) = self.cache.get_image(&item.path) else {
+            return false;
+        };
+
+        // Virtual images are written out as they are, or with their transform
+        let copy = crate::virtual_path::is_virtual(&item.path);
+        let refusal = if copy && image.transform.is_identity() {
+            None
+        } else if item.format == ImageFormat::Svg {
+            Some("SVG images can't be saved")
+        } else if item.format == ImageFormat::Heif {
+            Some("HEIF and AVIF images can't be saved")
+        } else if item.format == ImageFormat::Jxl {
+            Some("JPEG XL images can't be saved")
+        } else if item.format == ImageFormat::Raw {
+            Some("RAW images can't be saved")
+        } else if item.format == ImageFormat::Psd {
+            Some("Photoshop files can't be saved")
+        } else if image.frames.len() > 1 {
+            Some("Animated images can't be saved")
+        } else if image.page_count() > 1 {
+            Some("Multi-page images can't be saved")
+        } else if image.transform.is_identity() {
+            Some("Nothing to save")
+        } else {
+            None
+        };
+        if let Some(message) = refusal {
+            self.status_message = Some(message.to_string());
+            return true;
+        }
+        if copy {
+            let (path, transform) = (item.path.clone(), image.transform);
+            let proxy = self.proxy.clone();
+            std::thread::spawn(move || {
+                let result = save_copy(&path, transform);
+                let _ = proxy.send_event(AppEvent::SaveFinished(path, result));
+            });
+            return true;
+        }
+
+        // The header is enough to tell a JPEG
+        let mut head = Vec::new();
+        let _ = fs::File::open(&item.path).and_then(|f| f.take(16).read_to_end(&mut head));
+        let jpeg = item.format == ImageFormat::Raster
+            && image::guess_format(&head).ok() == Some(image::ImageFormat::Jpeg);
+        self.input_mode = InputMode::ConfirmSave {
+            path: item.path.clone(),
+            jpeg,
+        };
+        true
+    }
+
+    /// Answer to the save prompt.
+    pub fn confirm_save(&mut self, key: &str) {
+        let InputMode::ConfirmSave { path, jpeg } =
+            std::mem::replace(&mut self.input_mode, InputMode::Normal)
+        else {
+            return;
+        };
+        let method = match (key, jpeg) {
+            ("o", true) => SaveMethod::OrientationTag,
+            ("r", true) | ("y", false) => SaveMethod::Reencode,
+            _ => return,
+        };
+        let Some(image) = self.cache.get_image(&path) else {
+            return;
+        };
+
+        let transform = image.transform;
+        let proxy = self.proxy.clone();
+        std::thread::spawn(move || {
+            let result = save(&path, transform, method).map(|()| path.clone());
+            let _ = proxy.send_event(AppEvent::SaveFinished(path, result));
+        });
+    }
+
+    pub fn finish_save(&mut self, path: PathBuf, result: Result<PathBuf, String>) {
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        match result {
+            Ok(dest) if dest != path => {
+                let dest = dest.file_name().unwrap_or_default().to_string_lossy();
+                self.status_message = Some(format!("Saved as {}", dest));
+            }
+            Ok(_) => {
+                // Reloaded from the file, which now holds the transform
+                self.cache.remove(&path);
+                self.pending.remove(&path);
+                self.status_message = Some(format!("Saved {}", name));
+            }
+            Err(e) => {
+                crate::rsiv_err!("Failed to save {:?}: {}", path, e);
+                self.status_message = Some(format!("Failed to save {}: {}", name, e));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exif::{In, Tag, Value};
+
+    const MAKE_TAG: u16 = 0x010F;
+
+    fn jpeg() -> Vec<u8> {
+        let mut data = Vec::new();
+        let pixels = [128u8; 4 * 2 * 3];
+        JpegEncoder::new(&mut data)
+            .write_image(&pixels, 4, 2, image::ExtendedColorType::Rgb8)
+            .unwrap();
+        data
+    }
+
+    /// A JPEG whose EXIF data holds the make "rsiv", stored past IFD0, and an
+    /// orientation when given.
+    fn jpeg_with_exif(big_endian: bool, orientation: Option<u16>) -> Vec<u8> {
+        let count = 1 + orientation.is_some() as u16;
+        let make_at = 8 + 2 + count as u32 * 12 + 4;
+        let mut tiff = match big_endian {
+            true => vec![0x4D, 0x4D, 0, 42],
+            false => vec![0x49, 0x49, 42, 0],
+        };
+        tiff.extend_from_slice(&u32_bytes(8, big_endian));
+        tiff.extend_from_slice(&u16_bytes(count, big_endian));
+        tiff.extend_from_slice(&u16_bytes(MAKE_TAG, big_endian));
+        tiff.extend_from_slice(&u16_bytes(2, big_endian)); // ASCII
+        tiff.extend_from_slice(&u32_bytes(5, big_endian));
+        tiff.extend_from_slice(&u32_bytes(make_at, big_endian));
+        if let Some(orientation) = orientation {
+            tiff.extend_from_slice(&u16_bytes(ORIENTATION_TAG, big_endian));
+            tiff.extend_from_slice(&u16_bytes(3, big_endian));
+            tiff.extend_from_slice(&u32_bytes(1, big_endian));
+            tiff.extend_from_slice(&u16_bytes(orientation, big_endian));
+            tiff.extend_from_slice(&[0, 0]);
+        }
+        tiff.extend_from_slice(&[0; 4]);
+        tiff.extend_from_slice(b"rsiv\0");
+
+        let mut segment = vec![0xFF, 0xE1];
+        let len = (2 + EXIF_HEADER.len() + tiff.len()) as u16;
+        segment.extend_from_slice(&len.to_be_bytes());
+        segment.extend_from_slice(EXIF_HEADER);
+        segment.extend_from_slice(&tiff);
+
+        let mut data = jpeg();
+        data.splice(2..2, segment);
+        data
+    }
+
+    fn make(data: &[u8]) -> Option<Vec<u8>> {
+        let exif = exif::Reader::new()
+            .read_from_container(&mut Cursor::new(data))
+            .ok()?;
+        match &exif.get_field(Tag::Make, In::PRIMARY)?.value {
+            Value::Ascii(values) => values.first().cloned(),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn adds_an_exif_segment_to_a_jpeg_without_one() {
+        let mut data = jpeg();
+        write_jpeg_orientation(&mut data, Transform::ROTATE_CW).unwrap();
+        assert_eq!(crate::loader::exif_orientation(&data), 6);
+        assert!(image::load_from_memory(&data).is_ok());
+    }
+
+    #[test]
+    fn updates_the_orientation_in_both_byte_orders() {
+        for big_endian in [true, false] {
+            let mut data = jpeg_with_exif(big_endian, Some(3));
+            let len = data.len();
+            write_jpeg_orientation(&mut data, Transform::ROTATE_CW).unwrap();
+            // Upside down, then a quarter turn clockwise
+            assert_eq!(crate::loader::exif_orientation(&data), 8);
+            assert_eq!(data.len(), len);
+            assert_eq!(make(&data).as_deref(), Some(&b"rsiv"[..]));
+        }
+    }
+
+    #[test]
+    fn adds_the_orientation_to_exif_data_without_one() {
+        for big_endian in [true, false] {
+            let mut data = jpeg_with_exif(big_endian, None);
+            write_jpeg_orientation(&mut data, Transform::FLIP_HORIZONTAL).unwrap();
+            assert_eq!(crate::loader::exif_orientation(&data), 2);
+            assert_eq!(make(&data).as_deref(), Some(&b"rsiv"[..]));
+            assert!(image::load_from_memory(&data).is_ok());
+        }
+    }
+
+    #[test]
+    fn rejects_corrupt_segment_lengths() {
+        let mut jpeg = jpeg();
+        // A length shorter than its own two bytes
+        let mut short = jpeg.clone();
+        short.splice(2..2, [0xFF, 0xE1, 0x00, 0x01]);
+        let original = short.clone();
+        assert!(write_jpeg_orientation(&mut short, Transform::ROTATE_CW).is_err());
+        assert_eq!(short, original);
+
+        // A segment running past the end of the file
+        jpeg.truncate(20);
+        jpeg[4..6].copy_from_slice(&0x0100u16.to_be_bytes());
+        let original = jpeg.clone();
+        assert!(write_jpeg_orientation(&mut jpeg, Transform::ROTATE_CW).is_err());
+        assert_eq!(jpeg, original);
+    }
+}
@@ -1,5 +1,7 @@
 use crate::app::{App, InputMode};
 use crate::image_item::ImageSlot;
+use crate::virtual_path::Extracted;
+use std::path::{Path, PathBuf};
 
 impl App {
     pub fn execute_handler(&mut self, handler_key: &str, on_marked: bool) {
@@ -27,7 +29,9 @@ impl App {
             };
 
         let paths: Vec<String> = if on_marked {
-            self.marked_files.drain().collect()
+            let marks = self.marks_in_order();
+            self.marked_files.clear();
+            marks
         } else if current_path_str.is_empty() {
             vec![]
         } else {
@@ -40,6 +44,22 @@ impl App {
         }
 
         let is_bulk = cmd_args.iter().any(|arg| arg.contains("%M"));
+        // Each file gets its own `%c`, only the current one can have a selection
+        let geometry_of = |path: &str| match cmd_args.iter().any(|arg| arg.contains("%c")) {
+            true => self.crop_geometry(Path::new(path)),
+            false => String::new(),
+        };
+        let geometry = geometry_of(&current_path_str);
+        let paths: Vec<(String, String)> = match is_bulk {
+            true => paths.into_iter().map(|p| (p, String::new())).collect(),
+            false => paths
+                .into_iter()
+                .map(|p| {
+                    let geometry = geometry_of(&p);
+                    (p, geometry)
+                })
+                .collect(),
+        };
 
         // Set state
         self.is_handler_running = true;
@@ -49,10 +69,19 @@ impl App {
 
         let cancel_flag = self.handler_cancel_flag.clone();
         let proxy = self.proxy.clone();
+        #[cfg(unix)]
+        let socket = self.socket.clone();
 
         std::thread::spawn(move || {
             let run_interruptible = |program: &str, args: &[String]| {
-                if let Ok(mut child) = std::process::Command::new(program).args(args).spawn() {
+                let mut command = std::process::Command::new(program);
+                command.args(args);
+                // Lets handlers drive this instance with `rsiv --remote`
+                #[cfg(unix)]
+                if let Some(socket) = &socket {
+                    command.env(crate::ipc::SOCKET_ENV, socket);
+                }
+                if let Ok(mut child) = command.spawn() {
                     loop {
                         // Cancellation
                         if cancel_flag.load(std::sync::atomic::Ordering::Relaxed) {
@@ -71,12 +100,21 @@ impl App {
                 }
             };
 
+            // Removes the files extracted from archives once the handler is done
+            let mut extracted = Vec::new();
+
             if is_bulk {
-                let current_path_obj = std::path::Path::new(&current_path_str);
+                let (current_file, current_path_obj) =
+                    handler_path(&current_path_str, &mut extracted);
+                let paths: Vec<String> = paths
+                    .iter()
+                    .map(|(p, _)| handler_path(p, &mut extracted).0)
+                    .collect();
                 let mut final_args = Vec::with_capacity(cmd_args.len() + paths.len());
 
                 for arg in &cmd_args {
-                    let formatted = format_command_arg(arg, &current_path_str, current_path_obj);
+                    let formatted =
+                        format_command_arg(arg, &current_file, &current_path_obj, &geometry);
 
                     if formatted == "%M" {
                         final_args.extend(paths.iter().cloned());
@@ -93,23 +131,25 @@ impl App {
                     run_interruptible(program, args);
                 }
             } else {
-                for path_str in paths {
+                for (path_str, geometry) in paths {
                     if cancel_flag.load(std::sync::atomic::Ordering::Relaxed) {
                         break; // Stop processing the queue if cancelled
                     }
 
-                    let path_obj = std::path::Path::new(&path_str);
+                    let (file, path_obj) = handler_path(&path_str, &mut extracted);
 
                     let final_args: Vec<String> = cmd_args
                         .iter()
-                        .map(|arg| format_command_arg(arg, &path_str, path_obj))
+                        .map(|arg| format_command_arg(arg, &file, &path_obj, &geometry))
                         .collect();
 
                     if let Some((program, args)) = final_args.split_first() {
                         run_interruptible(program, args);
                     }
+                    extracted.clear();
                 }
             }
+            drop(extracted);
 
             // Release the UI lock
             let _ = proxy.send_event(crate::app::AppEvent::HandlerFinished);
@@ -150,12 +190,38 @@ impl App {
                     }
                 }
             }
-            InputMode::Normal | InputMode::Filtering => {}
+            InputMode::ConfirmSave { .. } => self.confirm_save(key),
+            InputMode::Normal | InputMode::Filtering | InputMode::EnteringPath { .. } => {}
+        }
+    }
+}
+
+/// Virtual images reach programs as temporary files for `%f`, while the
+/// other placeholders describe them as if they were files.
+fn handler_path(path_str: &str, extracted: &mut Vec<Extracted>) -> (String, PathBuf) {
+    let path = Path::new(path_str);
+    if !crate::virtual_path::is_virtual(path) {
+        return (path_str.to_string(), path.to_path_buf());
+    }
+    match Extracted::new(path) {
+        Ok(entry) => {
+            let file = entry.path.to_string_lossy().into_owned();
+            extracted.push(entry);
+            (file, crate::virtual_path::beside(path))
+        }
+        Err(e) => {
+            crate::rsiv_warn!("Failed to extract {:?}: {}", path, e);
+            (path_str.to_string(), crate::virtual_path::beside(path))
         }
     }
 }
 
-fn format_command_arg(arg: &str, path_str: &str, path_obj: &std::path::Path) -> String {
+fn format_command_arg(
+    arg: &str,
+    path_str: &str,
+    path_obj: &std::path::Path,
+    geometry: &str,
+) -> String {
     if !arg.contains('%') {
         return arg.to_string();
     }
@@ -187,6 +253,10 @@ fn format_command_arg(arg: &str, path_str: &str, path_obj: &std::path::Path) ->
                     res.push_str(&path_obj.extension().unwrap_or_default().to_string_lossy());
                     chars.next();
                 }
+                Some(&'c') => {
+                    res.push_str(geometry);
+                    chars.next();
+                }
                 Some(&'F') => {
                     res.push_str(&path_obj.file_name().unwrap_or_default().to_string_lossy());
                     chars.next();
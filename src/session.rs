@@ -0,0 +1,129 @@
+use crate::app::App;
+use crate::sorting::SortMode;
+use crate::view_mode::ViewMode;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// State saved by `--session <name>` on exit and restored on the next launch.
+/// Files are rediscovered from `paths`, so added and deleted ones are picked up.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Session {
+    pub paths: Vec<PathBuf>,
+    pub recursive: bool,
+    pub current: Option<PathBuf>,
+    pub current_index: usize,
+    pub marks: Vec<String>,
+    pub filter: String,
+    pub grid_mode: bool,
+    pub sort: SortMode,
+    pub sort_reverse: bool,
+    pub slideshow_on: bool,
+    pub slideshow_delay: u64,
+    pub view: ViewMode,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        let config = crate::config::AppConfig::get();
+        let options = &config.options;
+        Self {
+            paths: Vec::new(),
+            recursive: false,
+            current: None,
+            current_index: 0,
+            marks: Vec::new(),
+            filter: String::new(),
+            grid_mode: false,
+            sort: options.sort,
+            sort_reverse: options.sort_reverse,
+            slideshow_on: false,
+            slideshow_delay: options.slideshow_default_delay,
+            view: options.default_view,
+        }
+    }
+}
+
+fn state_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/state")))
+        .map(|p| p.join("rsiv"))
+}
+
+pub fn session_path(name: &str) -> Result<PathBuf, String> {
+    if name.is_empty() || name.contains('/') || name.starts_with('.') {
+        return Err(format!("Invalid session name: {:?}", name));
+    }
+    let dir = state_dir().ok_or("Could not find the state directory")?;
+    Ok(dir.join(format!("{}.toml", name)))
+}
+
+impl Session {
+    /// Reads a saved session. A missing file is a new session.
+    pub fn load(path: &Path) -> Result<Option<Self>, String> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.to_string()),
+        };
+        toml::from_str(&contents)
+            .map(Some)
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = toml::to_string(self).map_err(|e| e.to_string())?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        // Renamed into place, so a crash never leaves a truncated session
+        let temp = path.with_extension("toml.tmp");
+        std::fs::write(&temp, contents).map_err(|e| e.to_string())?;
+        std::fs::rename(&temp, path).map_err(|e| e.to_string())
+    }
+}
+
+impl App {
+    pub fn to_session(&self, paths: Vec<PathBuf>, recursive: bool) -> Session {
+        // Saved in mark order, so it survives the restore
+        let marks: Vec<String> = self.marked_files.iter().cloned().collect();
+
+        Session {
+            paths,
+            recursive,
+            current: self.current_path(),
+            current_index: self.current_index,
+            marks,
+            filter: self.filter_text.clone(),
+            grid_mode: self.grid_mode,
+            sort: self.sort_mode,
+            sort_reverse: self.sort_reverse,
+            slideshow_on: self.slideshow_on,
+            slideshow_delay: self.slideshow_delay.as_secs(),
+            view: self.mode,
+        }
+    }
+
+    /// Applies a saved session before discovery starts. The current image is
+    /// selected once found, and marks on files that are gone are dropped.
+    pub fn restore_session(&mut self, session: &Session) {
+        self.start_path = session.current.clone();
+        self.start_index = Some(session.current_index);
+        self.marked_files = session
+            .marks
+            .iter()
+            .filter(|p| Path::new(p).exists() || crate::archive::split(Path::new(p)).is_some())
+            .cloned()
+            .collect();
+        self.filter_text = session.filter.clone();
+        self.grid_mode = session.grid_mode;
+        self.sort_mode = session.sort;
+        self.sort_reverse = session.sort_reverse;
+        self.slideshow_on = session.slideshow_on;
+        self.slideshow_delay = Duration::from_secs(session.slideshow_delay.max(1));
+        self.mode = session.view;
+    }
+}
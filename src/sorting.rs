@@ -0,0 +1,335 @@
+use crate::app::App;
+use crate::image_item::{ImageItem, ImageSlot};
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, clap::ValueEnum)]
+pub enum SortMode {
+    Natural,
+    Name,
+    Mtime,
+    Ctime,
+    Size,
+    Exif,
+    Pixels,
+    Random,
+}
+
+impl SortMode {
+    const ALL: [SortMode; 8] = [
+        SortMode::Natural,
+        SortMode::Name,
+        SortMode::Mtime,
+        SortMode::Ctime,
+        SortMode::Size,
+        SortMode::Exif,
+        SortMode::Pixels,
+        SortMode::Random,
+    ];
+
+    pub fn next(self) -> Self {
+        let idx = Self::ALL.iter().position(|&m| m == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    pub fn prev(self) -> Self {
+        let idx = Self::ALL.iter().position(|&m| m == self).unwrap_or(0);
+        Self::ALL[(idx + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+
+    /// Whether the order depends on more than the path, and so can only be
+    /// applied once the metadata of every file has been probed.
+    pub fn needs_metadata(self) -> bool {
+        !matches!(self, SortMode::Natural | SortMode::Name | SortMode::Random)
+    }
+}
+
+impl fmt::Display for SortMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SortMode::Natural => "natural",
+            SortMode::Name => "name",
+            SortMode::Mtime => "mtime",
+            SortMode::Ctime => "ctime",
+            SortMode::Size => "size",
+            SortMode::Exif => "exif",
+            SortMode::Pixels => "pixels",
+            SortMode::Random => "random",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Compares strings like version numbers: digit runs are compared by value,
+/// so "img2" sorts before "img10".
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() && j < b.len() {
+        if a[i].is_ascii_digit() && b[j].is_ascii_digit() {
+            let start_a = i;
+            let start_b = j;
+            while i < a.len() && a[i].is_ascii_digit() {
+                i += 1;
+            }
+            while j < b.len() && b[j].is_ascii_digit() {
+                j += 1;
+            }
+
+            let num_a = trim_zeros(&a[start_a..i]);
+            let num_b = trim_zeros(&b[start_b..j]);
+            let ord = num_a
+                .len()
+                .cmp(&num_b.len())
+                .then_with(|| num_a.cmp(num_b))
+                // "01" after "1"
+                .then_with(|| (i - start_a).cmp(&(j - start_b)));
+            if ord != Ordering::Equal {
+                return ord;
+            }
+        } else {
+            if a[i] != b[j] {
+                return a[i].cmp(&b[j]);
+            }
+            i += 1;
+            j += 1;
+        }
+    }
+
+    (a.len() - i).cmp(&(b.len() - j))
+}
+
+fn trim_zeros(digits: &[u8]) -> &[u8] {
+    let first = digits
+        .iter()
+        .position(|&d| d != b'0')
+        .unwrap_or(digits.len());
+    &digits[first..]
+}
+
+fn natural_path_cmp(a: &Path, b: &Path) -> Ordering {
+    natural_cmp(&a.to_string_lossy(), &b.to_string_lossy())
+}
+
+/// Ascending order of two items. Ties fall back to natural path order.
+fn compare(a: &ImageItem, b: &ImageItem, mode: SortMode) -> Ordering {
+    let ord = match mode {
+        SortMode::Natural | SortMode::Random => Ordering::Equal,
+        SortMode::Name => a.path.cmp(&b.path),
+        SortMode::Mtime => a.mtime.cmp(&b.mtime),
+        SortMode::Ctime => a.ctime.cmp(&b.ctime),
+        SortMode::Size => a.file_size.cmp(&b.file_size),
+        // Images without a date go after the dated ones
+        SortMode::Exif => match (&a.date_taken, &b.date_taken) {
+            (Some(x), Some(y)) => x.cmp(y),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        },
+        SortMode::Pixels => {
+            (a.width as u64 * a.height as u64).cmp(&(b.width as u64 * b.height as u64))
+        }
+    };
+    ord.then_with(|| natural_path_cmp(&a.path, &b.path))
+}
+
+fn directed(ord: Ordering, reverse: bool) -> Ordering {
+    if reverse {
+        ord.reverse()
+    } else {
+        ord
+    }
+}
+
+/// Initial order of the discovered files, before any metadata is known.
+/// Metadata based modes start in name order and are sorted once probed.
+pub fn sort_paths(paths: &mut [PathBuf], mode: SortMode, reverse: bool) {
+    match mode {
+        SortMode::Random => paths.shuffle(&mut rand::rng()),
+        SortMode::Natural => paths.sort_by(|a, b| directed(natural_path_cmp(a, b), reverse)),
+        SortMode::Name => paths.sort_by(|a, b| directed(a.cmp(b), reverse)),
+        _ => paths.sort(),
+    }
+}
+
+/// Sorts the slots in place. Pending and failed slots are kept at the end.
+pub fn sort_slots(slots: &mut [ImageSlot], mode: SortMode, reverse: bool) {
+    if mode == SortMode::Random {
+        slots.shuffle(&mut rand::rng());
+    }
+
+    slots.sort_by(|a, b| match (a, b) {
+        (ImageSlot::MetadataLoaded(x), ImageSlot::MetadataLoaded(y)) => {
+            if mode == SortMode::Random {
+                Ordering::Equal
+            } else {
+                directed(compare(x, y, mode), reverse)
+            }
+        }
+        (ImageSlot::MetadataLoaded(_), _) => Ordering::Less,
+        (_, ImageSlot::MetadataLoaded(_)) => Ordering::Greater,
+        _ => Ordering::Equal,
+    });
+}
+
+/// Where `item` goes in an already sorted list. New files are appended in random mode.
+pub fn insert_position(
+    slots: &[ImageSlot],
+    item: &ImageItem,
+    mode: SortMode,
+    reverse: bool,
+) -> usize {
+    if mode == SortMode::Random {
+        return slots.len();
+    }
+    slots.partition_point(|slot| match slot {
+        ImageSlot::MetadataLoaded(other) => {
+            directed(compare(other, item, mode), reverse) != Ordering::Greater
+        }
+        _ => false,
+    })
+}
+
+impl App {
+    /// Re-sorts the image list, keeping the current image selected.
+    pub fn apply_sort(&mut self) {
+        let current = self.current_path();
+
+        sort_slots(&mut self.all_images, self.sort_mode, self.sort_reverse);
+        self.apply_filter();
+
+        if let Some(path) = current {
+            self.select_path(&path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(path: &str, file_size: u64) -> ImageItem {
+        ImageItem {
+            path: PathBuf::from(path),
+            width: 1,
+            height: 1,
+            format: crate::image_item::ImageFormat::Raster,
+            file_size,
+            mtime: None,
+            ctime: None,
+            date_taken: None,
+            details: Default::default(),
+        }
+    }
+
+    fn loaded(path: &str, file_size: u64) -> ImageSlot {
+        ImageSlot::MetadataLoaded(item(path, file_size))
+    }
+
+    fn paths(slots: &[ImageSlot]) -> Vec<String> {
+        slots
+            .iter()
+            .map(|slot| match slot {
+                ImageSlot::MetadataLoaded(item) => item.path.to_string_lossy().into_owned(),
+                ImageSlot::PendingMetadata => "pending".to_string(),
+                ImageSlot::Error(e) => format!("error {}", e),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn natural_cmp_compares_digit_runs_by_value() {
+        assert_eq!(natural_cmp("img2", "img10"), Ordering::Less);
+        assert_eq!(natural_cmp("img10", "img9"), Ordering::Greater);
+        assert_eq!(natural_cmp("a1b2", "a1b10"), Ordering::Less);
+        assert_eq!(natural_cmp("007", "10"), Ordering::Less);
+        assert_eq!(
+            natural_cmp("99999999999999999999999", "100000000000000000000000"),
+            Ordering::Less
+        );
+        assert_eq!(natural_cmp("img", "img1"), Ordering::Less);
+        assert_eq!(natural_cmp("img2.png", "img2.png"), Ordering::Equal);
+    }
+
+    #[test]
+    fn natural_cmp_puts_leading_zeros_after() {
+        assert_eq!(natural_cmp("1", "01"), Ordering::Less);
+        assert_eq!(natural_cmp("01", "1"), Ordering::Greater);
+        assert_eq!(natural_cmp("img01", "img1"), Ordering::Greater);
+        assert_eq!(natural_cmp("img01", "img2"), Ordering::Less);
+        assert_eq!(natural_cmp("0", "00"), Ordering::Less);
+    }
+
+    #[test]
+    fn sort_slots_reverses_ties_too() {
+        let mut slots = vec![
+            loaded("b", 1),
+            loaded("a", 2),
+            loaded("c", 1),
+            loaded("d", 2),
+        ];
+        sort_slots(&mut slots, SortMode::Size, false);
+        assert_eq!(paths(&slots), ["b", "c", "a", "d"]);
+
+        sort_slots(&mut slots, SortMode::Size, true);
+        assert_eq!(paths(&slots), ["d", "a", "c", "b"]);
+    }
+
+    #[test]
+    fn sort_slots_keeps_pending_and_failed_slots_at_the_end() {
+        for reverse in [false, true] {
+            let mut slots = vec![
+                ImageSlot::PendingMetadata,
+                loaded("img10", 0),
+                ImageSlot::Error("broken".to_string()),
+                loaded("img2", 0),
+            ];
+            sort_slots(&mut slots, SortMode::Natural, reverse);
+            let (first, second) = match reverse {
+                false => ("img2", "img10"),
+                true => ("img10", "img2"),
+            };
+            assert_eq!(paths(&slots), [first, second, "pending", "error broken"]);
+        }
+    }
+
+    #[test]
+    fn insert_position_goes_after_equals_and_before_pending() {
+        let slots = vec![
+            loaded("a", 1),
+            loaded("c", 2),
+            loaded("e", 3),
+            ImageSlot::PendingMetadata,
+        ];
+        assert_eq!(
+            insert_position(&slots, &item("b", 2), SortMode::Size, false),
+            1
+        );
+        assert_eq!(
+            insert_position(&slots, &item("d", 2), SortMode::Size, false),
+            2
+        );
+        assert_eq!(
+            insert_position(&slots, &item("f", 9), SortMode::Size, false),
+            3
+        );
+        assert_eq!(
+            insert_position(&slots, &item("f", 9), SortMode::Random, false),
+            4
+        );
+
+        let reversed = vec![loaded("e", 3), loaded("c", 2), loaded("a", 1)];
+        assert_eq!(
+            insert_position(&reversed, &item("d", 2), SortMode::Size, true),
+            1
+        );
+        assert_eq!(
+            insert_position(&reversed, &item("b", 2), SortMode::Size, true),
+            2
+        );
+    }
+}
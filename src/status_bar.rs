@@ -1,6 +1,10 @@
+use crate::adjustments::Adjustments;
 use crate::app::InputMode;
+use crate::compare::Compare;
 use crate::config::AppConfig;
 use crate::frame_buffer::FrameBuffer;
+use crate::image_item::ImageItem;
+use crate::sorting::SortMode;
 use crate::utils;
 use cosmic_text::{Attrs, Buffer, Color, Family, FontSystem, Metrics, Shaping, SwashCache};
 use std::fmt::Write;
@@ -20,6 +24,63 @@ enum StatusToken {
     Index,
     Mark,
     Frames,
+    Pages,
+    Sort,
+    Adjustments,
+    Compare,
+    Name,
+    FileSize,
+    Dimensions,
+    Mime,
+    Mtime(String), // strftime format
+    MarkCount,
+    Filter,
+    Exif(ExifField),
+}
+
+/// Camera settings shown by `%e{...}`.
+#[derive(Clone, Copy)]
+enum ExifField {
+    Camera,
+    Lens,
+    Exposure,
+    Aperture,
+    Iso,
+    FocalLength,
+    Date,
+}
+
+impl ExifField {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "camera" => Self::Camera,
+            "lens" => Self::Lens,
+            "exposure" => Self::Exposure,
+            "aperture" => Self::Aperture,
+            "iso" => Self::Iso,
+            "focal" => Self::FocalLength,
+            "date" => Self::Date,
+            _ => return None,
+        })
+    }
+}
+
+const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// Sizes in powers of 1024, e.g. "2.4 MB".
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
 }
 
 pub struct StatusContext<'a> {
@@ -35,8 +96,17 @@ pub struct StatusContext<'a> {
     pub filter_text: &'a str,
     pub current_frame: usize,
     pub total_frames: usize,
+    pub current_page: usize,
+    pub total_pages: usize,
     pub spinner_frame: usize,
     pub is_handler_running: bool,
+    pub sort_mode: SortMode,
+    pub sort_reverse: bool,
+    pub message: Option<&'a str>,
+    pub adjustments: Adjustments,
+    pub compare: Option<&'a Compare>,
+    pub item: Option<&'a ImageItem>,
+    pub marked_count: usize,
 }
 
 pub struct StatusBar {
@@ -46,6 +116,7 @@ pub struct StatusBar {
     background_color: (u8, u8, u8),
     left_buffer: Buffer,
     right_buffer: Buffer,
+    label_buffer: Buffer,
 
     // COMPILED INSTRUCTIONS
     left_tokens: Vec<StatusToken>,
@@ -81,9 +152,11 @@ impl StatusBar {
 
         let mut left_buffer = Buffer::new(&mut font_system, metrics);
         let mut right_buffer = Buffer::new(&mut font_system, metrics);
+        let mut label_buffer = Buffer::new(&mut font_system, metrics);
 
         left_buffer.set_size(&mut font_system, None, Some(height as f32));
         right_buffer.set_size(&mut font_system, None, Some(height as f32));
+        label_buffer.set_size(&mut font_system, None, Some(height as f32));
 
         // Compile the formats from config
         let left_tokens = Self::compile_format(&config.ui.status_format_left);
@@ -96,6 +169,7 @@ impl StatusBar {
             background_color: utils::parse_color(&config.ui.status_bar_bg),
             left_buffer,
             right_buffer,
+            label_buffer,
             left_tokens,
             right_tokens,
             scratch_buffer: String::with_capacity(128),
@@ -128,6 +202,34 @@ impl StatusBar {
                         'i' => tokens.push(StatusToken::Index),
                         'm' => tokens.push(StatusToken::Mark),
                         'f' => tokens.push(StatusToken::Frames),
+                        'g' => tokens.push(StatusToken::Pages),
+                        'o' => tokens.push(StatusToken::Sort),
+                        'a' => tokens.push(StatusToken::Adjustments),
+                        'c' => tokens.push(StatusToken::Compare),
+                        'n' => tokens.push(StatusToken::Name),
+                        'S' => tokens.push(StatusToken::FileSize),
+                        'd' => tokens.push(StatusToken::Dimensions),
+                        'T' => tokens.push(StatusToken::Mime),
+                        'M' => tokens.push(StatusToken::MarkCount),
+                        'q' => tokens.push(StatusToken::Filter),
+                        't' => {
+                            let format = Self::argument(&mut chars)
+                                .unwrap_or_else(|| DEFAULT_TIME_FORMAT.to_string());
+                            if chrono::format::StrftimeItems::new(&format).parse().is_ok() {
+                                tokens.push(StatusToken::Mtime(format));
+                            } else {
+                                let _ = write!(literal_buffer, "%t{{{}}}", format);
+                            }
+                        }
+                        'e' => {
+                            let name = Self::argument(&mut chars).unwrap_or_default();
+                            match ExifField::from_name(&name) {
+                                Some(field) => tokens.push(StatusToken::Exif(field)),
+                                None => {
+                                    let _ = write!(literal_buffer, "%e{{{}}}", name);
+                                }
+                            }
+                        }
                         '%' => literal_buffer.push('%'), // Escaped %% becomes literal %
                         c => {
                             // Unknown specifier, treat as literal text
@@ -152,6 +254,27 @@ impl StatusBar {
         tokens
     }
 
+    /// The `{...}` right after a token, if any.
+    fn argument(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<String> {
+        if chars.peek() != Some(&'{') {
+            return None;
+        }
+        chars.next();
+        Some(chars.by_ref().take_while(|&c| c != '}').collect())
+    }
+
+    /// Whether the formats show details read from the file, see `ImageItem::details`.
+    pub fn needs_details(&self) -> bool {
+        self.left_tokens
+            .iter()
+            .chain(&self.right_tokens)
+            .any(|token| match token {
+                StatusToken::Mime => true,
+                StatusToken::Exif(field) => !matches!(field, ExifField::Date),
+                _ => false,
+            })
+    }
+
     pub fn set_scale(&mut self, scale: f32) {
         if (self.scale_factor - scale).abs() < f32::EPSILON {
             return;
@@ -167,11 +290,14 @@ impl StatusBar {
 
         self.left_buffer.set_metrics(&mut font_system, metrics);
         self.right_buffer.set_metrics(&mut font_system, metrics);
+        self.label_buffer.set_metrics(&mut font_system, metrics);
 
         self.left_buffer
             .set_size(&mut font_system, None, Some(self.height as f32));
         self.right_buffer
             .set_size(&mut font_system, None, Some(self.height as f32));
+        self.label_buffer
+            .set_size(&mut font_system, None, Some(self.height as f32));
 
         // Invalidate cache
         self.cached_max_width = 0;
@@ -212,7 +338,89 @@ impl StatusBar {
                         let _ = write!(target, "[{}/{}]", ctx.current_frame, ctx.total_frames);
                     }
                 }
+                StatusToken::Pages => {
+                    if ctx.total_pages > 1 {
+                        let _ = write!(target, "p{}/{}", ctx.current_page, ctx.total_pages);
+                    }
+                }
+                StatusToken::Adjustments => {
+                    let _ = write!(target, "{}", ctx.adjustments);
+                }
+                StatusToken::Compare => {
+                    if let Some(compare) = ctx.compare {
+                        let _ = write!(target, "{}", compare);
+                    }
+                }
+                StatusToken::Sort => {
+                    let _ = write!(target, "{}", ctx.sort_mode);
+                    if ctx.sort_reverse {
+                        let _ = write!(target, " rev");
+                    }
+                }
+                StatusToken::MarkCount => {
+                    if ctx.marked_count > 0 {
+                        let _ = write!(target, "{}", ctx.marked_count);
+                    }
+                }
+                StatusToken::Filter => {
+                    let _ = write!(target, "{}", ctx.filter_text);
+                }
+                token => {
+                    if let Some(item) = ctx.item {
+                        Self::render_item_token(target, token, item);
+                    }
+                }
+            }
+        }
+    }
+
+    fn render_item_token(target: &mut String, token: &StatusToken, item: &ImageItem) {
+        match token {
+            StatusToken::Name => {
+                let name = item.path.file_name().unwrap_or_default();
+                let _ = write!(target, "{}", name.to_string_lossy());
+            }
+            StatusToken::FileSize => {
+                let _ = write!(target, "{}", human_size(item.file_size));
+            }
+            StatusToken::Dimensions => {
+                let _ = write!(target, "{}x{}", item.width, item.height);
+            }
+            StatusToken::Mime => {
+                if let Some(mime) = item.details().and_then(|d| d.mime.as_ref()) {
+                    let _ = write!(target, "{}", mime);
+                }
+            }
+            StatusToken::Mtime(format) => {
+                if let Some(mtime) = item.mtime {
+                    let time = chrono::DateTime::<chrono::Local>::from(mtime);
+                    let _ = write!(target, "{}", time.format(format));
+                }
+            }
+            StatusToken::Exif(ExifField::Date) => {
+                // Stored as "YYYY:MM:DD HH:MM:SS"
+                if let Some(date) = &item.date_taken {
+                    let _ = write!(target, "{}", date.replacen(':', "-", 2));
+                }
+            }
+            StatusToken::Exif(field) => {
+                let Some(details) = item.details() else {
+                    return;
+                };
+                let value = match field {
+                    ExifField::Camera => &details.camera,
+                    ExifField::Lens => &details.lens,
+                    ExifField::Exposure => &details.exposure,
+                    ExifField::Aperture => &details.aperture,
+                    ExifField::Iso => &details.iso,
+                    ExifField::FocalLength => &details.focal_length,
+                    ExifField::Date => &None,
+                };
+                if let Some(value) = value {
+                    let _ = write!(target, "{}", value);
+                }
             }
+            _ => {}
         }
     }
 
@@ -278,8 +486,27 @@ impl StatusBar {
                         "[Target] (c)urrent/(m)arked? (Esc to cancel)"
                     );
                 }
+                InputMode::ConfirmSave { jpeg: true, .. } => {
+                    let _ = write!(
+                        self.scratch_buffer,
+                        "[Save] update the (o)rientation tag/(r)e-encode? (Esc to cancel)"
+                    );
+                }
+                InputMode::ConfirmSave { jpeg: false, .. } => {
+                    let _ = write!(
+                        self.scratch_buffer,
+                        "[Save] Re-encode, keeping the original as a backup? (y/n)"
+                    );
+                }
+                InputMode::EnteringPath { prompt, text } => {
+                    let _ = write!(self.scratch_buffer, "[{}] {}█", prompt, text);
+                }
                 InputMode::Normal => {
-                    Self::render_tokens(&mut self.scratch_buffer, &self.left_tokens, &ctx);
+                    if let Some(message) = ctx.message {
+                        let _ = write!(self.scratch_buffer, "{}", message);
+                    } else {
+                        Self::render_tokens(&mut self.scratch_buffer, &self.left_tokens, &ctx);
+                    }
                 }
             }
         }
@@ -378,6 +605,36 @@ impl StatusBar {
         );
     }
 
+    /// Draws `text` on a status bar colored box at (x, y), kept inside the window.
+    pub fn draw_label(&mut self, target: &mut FrameBuffer, text: &str, x: i32, y: i32) {
+        let mut font_system = UI_FONT_SYSTEM.get().unwrap().lock().unwrap();
+        let mut swash_cache = UI_SWASH_CACHE
+            .get_or_init(|| Mutex::new(SwashCache::new()))
+            .lock()
+            .unwrap();
+
+        let config = AppConfig::get();
+        let attrs = Attrs::new().family(Family::Name(&config.ui.font_family));
+        self.label_buffer
+            .set_text(&mut font_system, text, &attrs, Shaping::Advanced, None);
+        self.label_buffer
+            .shape_until_scroll(&mut font_system, false);
+
+        let padding = 4;
+        let box_w = Self::measure_width(&self.label_buffer) as u32 + 2 * padding;
+        let x = x.min(target.width as i32 - box_w as i32).max(0);
+        target.draw_rect(x, y, box_w, self.height, self.background_color);
+        Self::draw_buffer(
+            &mut font_system,
+            &mut swash_cache,
+            target,
+            &self.label_buffer,
+            x + padding as i32,
+            y,
+            utils::parse_color(&config.ui.status_bar_fg),
+        );
+    }
+
     fn measure_width(buffer: &Buffer) -> f32 {
         buffer
             .layout_runs()
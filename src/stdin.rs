@@ -0,0 +1,83 @@
+use image::ImageReader;
+use std::io::{self, Cursor, IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// An image piped to stdin, listed under a made up name like `stdin.png`.
+static IMAGE: OnceLock<(PathBuf, Vec<u8>)> = OnceLock::new();
+
+/// Reads what was piped in: an image with `image`, or when the data reads as
+/// one, and else a list of paths, one per line or NUL separated with `null`.
+pub fn read(image: bool, null: bool) -> Result<Vec<PathBuf>, String> {
+    let stdin = io::stdin();
+    if stdin.is_terminal() {
+        return match image {
+            true => Err("No image piped to stdin".to_string()),
+            false => Ok(Vec::new()),
+        };
+    }
+
+    let mut data = Vec::new();
+    stdin
+        .lock()
+        .read_to_end(&mut data)
+        .map_err(|e| e.to_string())?;
+
+    if !image && !is_image(&data) {
+        // Names are bytes, not always UTF-8. They may contain spaces and
+        // newlines, only NUL ends them
+        let paths: Vec<&[u8]> = if null {
+            data.split(|&b| b == 0).collect()
+        } else {
+            data.split(|&b| b == b'\n')
+                .map(<[u8]>::trim_ascii)
+                .collect()
+        };
+        return Ok(paths
+            .into_iter()
+            .filter(|path| !path.is_empty())
+            .map(path_from_bytes)
+            .collect());
+    }
+    if data.is_empty() {
+        return Err("Nothing was piped to stdin".to_string());
+    }
+
+    let name = match infer::get(&data) {
+        Some(kind) => format!("stdin.{}", kind.extension()),
+        None => "stdin".to_string(),
+    };
+    let path = IMAGE.get_or_init(|| (PathBuf::from(name), data)).0.clone();
+    Ok(vec![path])
+}
+
+#[cfg(unix)]
+fn path_from_bytes(path: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(path))
+}
+
+/// Names only have to be bytes on Unix, elsewhere they are read as UTF-8.
+#[cfg(not(unix))]
+fn path_from_bytes(path: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(path).into_owned())
+}
+
+/// Whether piped data is an image rather than a list of paths.
+///
+/// Signatures alone can't tell, a BMP only needs to start with "BM" like
+/// `BMW_0001.jpg` does, so the header has to decode.
+fn is_image(data: &[u8]) -> bool {
+    crate::psd::size(data).is_ok()
+        || ImageReader::new(Cursor::new(data))
+            .with_guessed_format()
+            .is_ok_and(|reader| reader.into_dimensions().is_ok())
+}
+
+/// The piped image, if `path` is its name.
+pub fn data(path: &Path) -> Option<&'static [u8]> {
+    IMAGE
+        .get()
+        .filter(|(name, _)| name == path)
+        .map(|(_, data)| data.as_slice())
+}
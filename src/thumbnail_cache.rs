@@ -0,0 +1,278 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::UNIX_EPOCH;
+
+/// Shared thumbnail store from the freedesktop.org thumbnail specification.
+/// Each directory holds thumbnails fitting in its size, named after the MD5 of the file URI.
+const SIZE_DIRS: [(u32, &str); 4] = [
+    (128, "normal"),
+    (256, "large"),
+    (512, "x-large"),
+    (1024, "xx-large"),
+];
+
+pub static CACHE_ENABLED: AtomicBool = AtomicBool::new(true);
+
+pub fn set_enabled(enabled: bool) {
+    CACHE_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    CACHE_ENABLED.load(Ordering::Relaxed)
+}
+
+fn cache_root() -> Option<PathBuf> {
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".cache")))
+        .map(|p| p.join("thumbnails"))
+}
+
+/// The smallest standard size holding a `size` thumbnail, if any.
+pub fn bucket_size(size: u32) -> Option<u32> {
+    SIZE_DIRS.iter().map(|(s, _)| *s).find(|&s| s >= size)
+}
+
+/// Canonical `file://` URI, escaped the same way GLib does it.
+fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for &b in path.as_os_str().as_encoded_bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~!$&'()*+,=:@/".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", b));
+        }
+    }
+    uri
+}
+
+fn mtime_secs(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs())
+}
+
+fn thumb_name(uri: &str) -> String {
+    format!("{:x}.png", md5::compute(uri.as_bytes()))
+}
+
+/// Whether `path` can use the shared cache. Thumbnails of thumbnails are never stored.
+fn usable(path: &Path, root: &Path) -> bool {
+    is_enabled() && path.is_absolute() && !path.starts_with(root)
+}
+
+/// Reads a valid cached thumbnail of at least `size`, downscaled to fit in `size`.
+pub fn lookup(path: &Path, size: u32) -> Option<(u32, u32, Vec<u8>)> {
+    let root = cache_root()?;
+    if !usable(path, &root) {
+        return None;
+    }
+    let uri = file_uri(path);
+    let name = thumb_name(&uri);
+    let mtime = mtime_secs(path)?;
+
+    SIZE_DIRS
+        .iter()
+        .filter(|(s, _)| *s >= size)
+        .find_map(|(_, dir)| read_thumbnail(&root.join(dir).join(&name), &uri, mtime))
+        .map(|thumb| shrink_to(thumb, size))
+}
+
+fn read_thumbnail(file: &Path, uri: &str, mtime: u64) -> Option<(u32, u32, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(file).ok()?));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().ok()?;
+    let mut buf = vec![0; reader.output_buffer_size()?];
+    let frame = reader.next_frame(&mut buf).ok()?;
+    // Text chunks may follow the image data
+    reader.finish().ok()?;
+
+    let info = reader.info();
+    let text = |key: &str| -> Option<String> {
+        info.uncompressed_latin1_text
+            .iter()
+            .find(|c| c.keyword == key)
+            .map(|c| c.text.clone())
+            .or_else(|| {
+                info.utf8_text
+                    .iter()
+                    .find(|c| c.keyword == key)
+                    .and_then(|c| c.get_text().ok())
+            })
+    };
+
+    // Stale or colliding entries are regenerated
+    if text("Thumb::URI")? != uri {
+        return None;
+    }
+    let thumb_mtime: f64 = text("Thumb::MTime")?.trim().parse().ok()?;
+    if thumb_mtime as u64 != mtime {
+        return None;
+    }
+
+    let (w, h) = (frame.width, frame.height);
+    let pixels = &buf[..frame.buffer_size()];
+    let rgba = match frame.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        png::ColorType::Indexed => return None,
+    };
+    Some((w, h, rgba))
+}
+
+/// Stores a thumbnail generated at `bucket` size. Failures are only logged.
+pub fn store(path: &Path, bucket: u32, thumb: &(u32, u32, Vec<u8>)) {
+    let Some(root) = cache_root() else {
+        return;
+    };
+    if !usable(path, &root) {
+        return;
+    }
+    let Some((_, dir)) = SIZE_DIRS.iter().find(|(s, _)| *s == bucket) else {
+        return;
+    };
+    let Some(mtime) = mtime_secs(path) else {
+        return;
+    };
+
+    if let Err(e) = write_thumbnail(&root.join(dir), path, mtime, thumb) {
+        crate::rsiv_warn!("Failed to cache thumbnail for {:?}: {}", path, e);
+    }
+}
+
+fn write_thumbnail(
+    dir: &Path,
+    path: &Path,
+    mtime: u64,
+    thumb: &(u32, u32, Vec<u8>),
+) -> Result<(), String> {
+    create_private_dir(dir).map_err(|e| e.to_string())?;
+
+    let uri = file_uri(path);
+    let target = dir.join(thumb_name(&uri));
+    // Written next to the target and renamed, so readers never see a partial file
+    let temp = dir.join(format!(
+        "{}.rsiv-{}.tmp",
+        thumb_name(&uri),
+        std::process::id()
+    ));
+
+    let result = (|| -> Result<(), String> {
+        let file = create_private_file(&temp).map_err(|e| e.to_string())?;
+        let mut out = BufWriter::new(file);
+        let mut encoder = png::Encoder::new(&mut out, thumb.0, thumb.1);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        for (key, value) in [
+            ("Thumb::URI", uri.clone()),
+            ("Thumb::MTime", mtime.to_string()),
+            ("Software", "rsiv".to_string()),
+        ] {
+            encoder
+                .add_text_chunk(key.to_string(), value)
+                .map_err(|e| e.to_string())?;
+        }
+
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+        writer
+            .write_image_data(&thumb.2)
+            .map_err(|e| e.to_string())?;
+        writer.finish().map_err(|e| e.to_string())?;
+        out.flush().map_err(|e| e.to_string())?;
+
+        fs::rename(&temp, &target).map_err(|e| e.to_string())
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+#[cfg(unix)]
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dir)
+}
+
+#[cfg(unix)]
+fn create_private_file(path: &Path) -> std::io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+}
+
+#[cfg(not(unix))]
+fn create_private_file(path: &Path) -> std::io::Result<File> {
+    File::create(path)
+}
+
+/// Downscales a thumbnail so it fits in `size`, leaving smaller ones untouched.
+pub fn shrink_to(thumb: (u32, u32, Vec<u8>), size: u32) -> (u32, u32, Vec<u8>) {
+    let (w, h, pixels) = thumb;
+    if w <= size && h <= size {
+        return (w, h, pixels);
+    }
+    match image::RgbaImage::from_raw(w, h, pixels) {
+        Some(buf) => {
+            let small = image::DynamicImage::ImageRgba8(buf).thumbnail(size, size);
+            (small.width(), small.height(), small.to_rgba8().into_raw())
+        }
+        None => (0, 0, Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_uri_keeps_safe_characters() {
+        assert_eq!(
+            file_uri(Path::new("/home/me/a-b_c.~1/(x)+y,z=w:v@u!$&'*.png")),
+            "file:///home/me/a-b_c.~1/(x)+y,z=w:v@u!$&'*.png"
+        );
+    }
+
+    #[test]
+    fn file_uri_escapes_other_bytes_as_uppercase_hex() {
+        assert_eq!(
+            file_uri(Path::new("/tmp/my photo #1?%.jpg")),
+            "file:///tmp/my%20photo%20%231%3F%25.jpg"
+        );
+        assert_eq!(
+            file_uri(Path::new("/tmp/été.jpg")),
+            "file:///tmp/%C3%A9t%C3%A9.jpg"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn file_uri_escapes_names_that_are_not_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+        let path = Path::new(std::ffi::OsStr::from_bytes(b"/tmp/\xFF\x80.jpg"));
+        assert_eq!(file_uri(path), "file:///tmp/%FF%80.jpg");
+    }
+}
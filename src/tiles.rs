@@ -0,0 +1,881 @@
+use memmap2::Mmap;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+pub const TILE_SIZE: u32 = 512;
+
+/// Largest side of the low resolution copy decoded when the image is opened.
+const OVERVIEW_SIZE: u32 = 2048;
+
+/// Side length past which the regular decoders refuse an image.
+const MAX_DECODE_SIZE: u32 = 32768;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TileKey {
+    pub level: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+#[derive(Debug)]
+pub struct Tile {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Container {
+    Tiff,
+    Png,
+    Jpeg,
+}
+
+/// A TIFF directory holding the image at `width >> shift` (pyramidal TIFFs store several).
+#[derive(Debug)]
+struct TiffLevel {
+    ifd: usize,
+    width: u32,
+    height: u32,
+    shift: u32,
+}
+
+/// Rows of a JPEG decoded at `1 / 2^dct_shift` of its size, in its own color layout.
+///
+/// `top` is the first row held, in the same scale.
+#[derive(Debug)]
+struct JpegScale {
+    dct_shift: u32,
+    top: u32,
+    width: u32,
+    height: u32,
+    layout: Layout,
+    samples: Vec<u8>,
+}
+
+/// Places where the scan of a sequential JPEG can be cut into bands of rows.
+///
+/// Decoding restarts at every restart marker, so a band starting on a marker
+/// that falls at the start of an MCU row decodes on its own once the headers
+/// are put in front of it.
+#[derive(Debug)]
+struct JpegBands {
+    // Segments before the scan, and where the frame height is stored in them
+    header_len: usize,
+    height_at: usize,
+    // Source rows in each band, and restart intervals in each band
+    band_height: u32,
+    band_intervals: u32,
+    // Entropy coded data of each band, without the marker that ends it
+    bands: Vec<(usize, usize)>,
+}
+
+/// The mapped file, for readers kept between batches.
+#[derive(Debug)]
+struct SharedData(Arc<Mmap>);
+
+impl AsRef<[u8]> for SharedData {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// A PNG reader left after the last batch, `next_row` being the row it reads next.
+struct PngRows {
+    next_row: u32,
+    reader: png::Reader<Cursor<SharedData>>,
+}
+
+impl std::fmt::Debug for PngRows {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PngRows")
+            .field("next_row", &self.next_row)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An image decoded region by region instead of all at once.
+///
+/// Level `n` is the image downscaled by `2^n`. Each level is split into
+/// `TILE_SIZE` tiles that are decoded on demand and cached separately.
+/// `width`, `height`, levels and tiles are all in displayed orientation,
+/// after the EXIF orientation of the file.
+#[derive(Debug)]
+pub struct TiledSource {
+    pub path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    pub overview: Tile,
+    pub overview_level: u32,
+    orientation: u32,
+    src_width: u32,
+    src_height: u32,
+    container: Container,
+    tiff_levels: Vec<TiffLevel>,
+    jpeg_bands: Option<JpegBands>,
+    // JPEGs without restart markers have no random access, the last scale
+    // decoded is kept for the next tiles
+    jpeg_scale: Mutex<Option<Arc<JpegScale>>>,
+    // PNGs have no random access either, batches further down carry on from
+    // the last one instead of inflating from the top again
+    png_rows: Mutex<Option<PngRows>>,
+    data: Arc<Mmap>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Layout {
+    Gray,
+    GrayAlpha,
+    Rgb,
+    Rgba,
+    Cmyk,
+}
+
+impl Layout {
+    pub fn channels(self) -> usize {
+        match self {
+            Layout::Gray => 1,
+            Layout::GrayAlpha => 2,
+            Layout::Rgb => 3,
+            Layout::Rgba | Layout::Cmyk => 4,
+        }
+    }
+
+    pub fn to_rgba(self, samples: &[u8], out: &mut Vec<u8>) {
+        out.clear();
+        for s in samples.chunks_exact(self.channels()) {
+            let p = match self {
+                Layout::Gray => [s[0], s[0], s[0], 255],
+                Layout::GrayAlpha => [s[0], s[0], s[0], s[1]],
+                Layout::Rgb => [s[0], s[1], s[2], 255],
+                Layout::Rgba => [s[0], s[1], s[2], s[3]],
+                Layout::Cmyk => {
+                    let k = 255 - s[3] as u32;
+                    [
+                        ((255 - s[0] as u32) * k / 255) as u8,
+                        ((255 - s[1] as u32) * k / 255) as u8,
+                        ((255 - s[2] as u32) * k / 255) as u8,
+                        255,
+                    ]
+                }
+            };
+            out.extend_from_slice(&p);
+        }
+    }
+}
+
+/// Box-filters source rows into a rectangle of a downscaled level.
+struct Accumulator {
+    x0: u32,
+    y0: u32,
+    width: u32,
+    height: u32,
+    shift: u32,
+    // Premultiplied RGB, alpha and sample count per output pixel
+    sums: Vec<[f32; 5]>,
+}
+
+impl Accumulator {
+    fn new(rect: (u32, u32, u32, u32), shift: u32) -> Self {
+        let (x0, y0, width, height) = rect;
+        Self {
+            x0,
+            y0,
+            width,
+            height,
+            shift,
+            sums: vec![[0.0; 5]; width as usize * height as usize],
+        }
+    }
+
+    fn src_cols(&self) -> (u32, u32) {
+        (self.x0 << self.shift, (self.x0 + self.width) << self.shift)
+    }
+
+    fn src_rows(&self) -> (u32, u32) {
+        (self.y0 << self.shift, (self.y0 + self.height) << self.shift)
+    }
+
+    /// Adds a row of RGBA source pixels whose first pixel sits at column `src_x`.
+    fn add_row(&mut self, src_y: u32, src_x: u32, rgba: &[u8]) {
+        let (row_start, row_end) = self.src_rows();
+        if src_y < row_start || src_y >= row_end {
+            return;
+        }
+        let (col_start, col_end) = self.src_cols();
+        let first = col_start.max(src_x);
+        let last = col_end.min(src_x + (rgba.len() / 4) as u32);
+        if first >= last {
+            return;
+        }
+
+        let dy = (src_y >> self.shift) - self.y0;
+        let row = &mut self.sums[(dy * self.width) as usize..((dy + 1) * self.width) as usize];
+        let pixels = &rgba[((first - src_x) * 4) as usize..((last - src_x) * 4) as usize];
+        for (sx, p) in (first..last).zip(pixels.chunks_exact(4)) {
+            let acc = &mut row[((sx >> self.shift) - self.x0) as usize];
+            let a = p[3] as f32;
+            acc[0] += p[0] as f32 * a;
+            acc[1] += p[1] as f32 * a;
+            acc[2] += p[2] as f32 * a;
+            acc[3] += a;
+            acc[4] += 1.0;
+        }
+    }
+
+    fn finish(self) -> Tile {
+        let mut pixels = vec![0u8; self.sums.len() * 4];
+        for (dest, acc) in pixels.chunks_exact_mut(4).zip(&self.sums) {
+            if acc[3] > 0.0 {
+                dest[0] = (acc[0] / acc[3]).round() as u8;
+                dest[1] = (acc[1] / acc[3]).round() as u8;
+                dest[2] = (acc[2] / acc[3]).round() as u8;
+                dest[3] = (acc[3] / acc[4]).round() as u8;
+            }
+        }
+        Tile {
+            width: self.width,
+            height: self.height,
+            pixels,
+        }
+    }
+}
+
+/// Source rows and columns needed by a set of accumulators, clamped to the source size.
+fn union_bounds(accs: &[Accumulator], src_w: u32, src_h: u32) -> ((u32, u32), (u32, u32)) {
+    let cols = accs.iter().map(|a| a.src_cols());
+    let rows = accs.iter().map(|a| a.src_rows());
+    let col_start = cols.clone().map(|c| c.0).min().unwrap_or(0);
+    let col_end = cols.map(|c| c.1).max().unwrap_or(0).min(src_w);
+    let row_start = rows.clone().map(|r| r.0).min().unwrap_or(0);
+    let row_end = rows.map(|r| r.1).max().unwrap_or(0).min(src_h);
+    ((col_start, col_end), (row_start, row_end))
+}
+
+/// Whether an image is too large to be decoded in one piece.
+pub fn should_tile(width: u32, height: u32) -> bool {
+    let config = crate::config::AppConfig::get();
+    let megapixels = width as u64 * height as u64 / 1_000_000;
+
+    width > MAX_DECODE_SIZE
+        || height > MAX_DECODE_SIZE
+        || (config.options.tile_threshold > 0 && megapixels >= config.options.tile_threshold)
+        || crate::loader::check_memory_before_decode(width, height, 1).is_err()
+}
+
+fn detect_container(data: &[u8]) -> Option<Container> {
+    if data.starts_with(b"II*\0")
+        || data.starts_with(b"MM\0*")
+        || data.starts_with(b"II+\0")
+        || data.starts_with(b"MM\0+")
+    {
+        Some(Container::Tiff)
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some(Container::Png)
+    } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some(Container::Jpeg)
+    } else {
+        None
+    }
+}
+
+/// Finds the bands a baseline JPEG with restart markers can be decoded in.
+///
+/// Progressive, arithmetic coded and multi-scan files, and files whose markers
+/// only line up with MCU rows every `TILE_SIZE` rows or more, give `None`.
+fn jpeg_bands(data: &[u8], width: u32, height: u32) -> Option<JpegBands> {
+    let mut pos = 2;
+    let mut frame = None;
+    let mut interval = 0u32;
+    let scan_start = loop {
+        if *data.get(pos)? != 0xFF {
+            return None;
+        }
+        let marker = *data.get(pos + 1)?;
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        let len = u16::from_be_bytes([*data.get(pos + 2)?, *data.get(pos + 3)?]) as usize;
+        let segment = data.get(pos + 4..pos + 2 + len)?;
+        match marker {
+            0xC0 | 0xC1 => {
+                let components = *segment.get(5)? as usize;
+                let sampling = segment.get(6..6 + components * 3)?;
+                let h = sampling.chunks_exact(3).map(|c| c[1] >> 4).max()? as u32;
+                let v = sampling.chunks_exact(3).map(|c| c[1] & 15).max()? as u32;
+                // A single component scan has one block per MCU whatever its sampling
+                let mcu = if components == 1 {
+                    (8, 8)
+                } else {
+                    (8 * h, 8 * v)
+                };
+                frame = Some((pos + 5, components, mcu));
+            }
+            0xC2 | 0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => return None,
+            0xDD => interval = u16::from_be_bytes([*segment.first()?, *segment.get(1)?]) as u32,
+            0xDA => {
+                let (_, components, _) = frame?;
+                if *segment.first()? as usize != components {
+                    return None;
+                }
+                break pos + 2 + len;
+            }
+            _ => {}
+        }
+        pos += 2 + len;
+    };
+
+    let (height_at, _, (mcu_w, mcu_h)) = frame?;
+    if interval == 0 || mcu_w == 0 || mcu_h == 0 || height == 0 {
+        return None;
+    }
+    let mcus_per_row = width.div_ceil(mcu_w);
+    let mut lcm = interval;
+    while !lcm.is_multiple_of(mcus_per_row) && lcm / mcus_per_row * mcu_h < TILE_SIZE {
+        lcm += interval;
+    }
+    let band_height = lcm / mcus_per_row * mcu_h;
+    let band_intervals = lcm / interval;
+    if !lcm.is_multiple_of(mcus_per_row) || band_height >= TILE_SIZE {
+        return None;
+    }
+
+    let mut bands = Vec::with_capacity(height.div_ceil(band_height) as usize);
+    let mut start = scan_start;
+    let mut markers = 0u32;
+    let mut i = scan_start;
+    let end = loop {
+        let ff = i + data.get(i..)?.iter().position(|&b| b == 0xFF)?;
+        match *data.get(ff + 1)? {
+            0x00 => i = ff + 2,
+            0xFF => i = ff + 1,
+            0xD0..=0xD7 => {
+                markers += 1;
+                if markers.is_multiple_of(band_intervals) {
+                    bands.push((start, ff));
+                    start = ff + 2;
+                }
+                i = ff + 2;
+            }
+            0xD9 => break ff,
+            _ => return None,
+        }
+    };
+    if start < end {
+        bands.push((start, end));
+    }
+
+    (bands.len() == height.div_ceil(band_height) as usize).then_some(JpegBands {
+        header_len: scan_start,
+        height_at,
+        band_height,
+        band_intervals,
+        bands,
+    })
+}
+
+pub fn tiff_layout(color: tiff::ColorType) -> Option<Layout> {
+    match color {
+        tiff::ColorType::Gray(8 | 16) => Some(Layout::Gray),
+        tiff::ColorType::GrayA(8 | 16) => Some(Layout::GrayAlpha),
+        tiff::ColorType::RGB(8 | 16) => Some(Layout::Rgb),
+        tiff::ColorType::RGBA(8 | 16) => Some(Layout::Rgba),
+        tiff::ColorType::CMYK(8) => Some(Layout::Cmyk),
+        _ => None,
+    }
+}
+
+impl TiledSource {
+    /// Opens a TIFF, PNG or JPEG file for tiled decoding and builds its overview.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let data = crate::loader::map_file(path)?;
+        let container =
+            detect_container(&data).ok_or("Tiled decoding supports TIFF, PNG and JPEG")?;
+
+        let mut tiff_levels = Vec::new();
+        let (width, height) = match container {
+            Container::Tiff => {
+                let mut decoder = tiff::decoder::Decoder::new(Cursor::new(&data[..]))
+                    .map_err(|e| e.to_string())?;
+                let (width, height) = decoder.dimensions().map_err(|e| e.to_string())?;
+                tiff_levels.push(TiffLevel {
+                    ifd: 0,
+                    width,
+                    height,
+                    shift: 0,
+                });
+
+                // Reduced resolution copies let coarse levels skip the full image
+                let mut ifd = 0;
+                while decoder.more_images() && decoder.next_image().is_ok() {
+                    ifd += 1;
+                    let Ok((w, h)) = decoder.dimensions() else {
+                        continue;
+                    };
+                    let supported = decoder.colortype().ok().and_then(tiff_layout).is_some();
+                    let shift = (1..32).find(|&s| {
+                        (w as i64 - (width >> s) as i64).abs() <= 1
+                            && (h as i64 - (height >> s) as i64).abs() <= 1
+                    });
+                    if let (true, Some(shift)) = (supported, shift) {
+                        tiff_levels.push(TiffLevel {
+                            ifd,
+                            width: w,
+                            height: h,
+                            shift,
+                        });
+                    }
+                }
+                tiff_levels.sort_by_key(|l| l.shift);
+                (width, height)
+            }
+            Container::Png => {
+                let decoder = png::Decoder::new(Cursor::new(&data[..]));
+                let reader = decoder.read_info().map_err(|e| e.to_string())?;
+                (reader.info().width, reader.info().height)
+            }
+            Container::Jpeg => {
+                let mut decoder = jpeg_decoder::Decoder::new(Cursor::new(&data[..]));
+                decoder.read_info().map_err(|e| e.to_string())?;
+                let info = decoder.info().ok_or("Missing JPEG header")?;
+                (info.width as u32, info.height as u32)
+            }
+        };
+
+        let orientation = crate::loader::exif_orientation(&data);
+        let (src_width, src_height) = (width, height);
+        let (width, height) = if orientation >= 5 {
+            (height, width)
+        } else {
+            (width, height)
+        };
+
+        let mut overview_level = 0;
+        while width
+            .div_ceil(1 << overview_level)
+            .max(height.div_ceil(1 << overview_level))
+            > OVERVIEW_SIZE
+        {
+            overview_level += 1;
+        }
+
+        let mut source = Self {
+            path: path.to_path_buf(),
+            width,
+            height,
+            overview: Tile {
+                width: 0,
+                height: 0,
+                pixels: Vec::new(),
+            },
+            overview_level,
+            orientation,
+            src_width,
+            src_height,
+            container,
+            tiff_levels,
+            jpeg_bands: match container {
+                Container::Jpeg => jpeg_bands(&data, src_width, src_height),
+                _ => None,
+            },
+            jpeg_scale: Mutex::new(None),
+            png_rows: Mutex::new(None),
+            data: Arc::new(data),
+        };
+
+        let (w, h) = source.level_size(overview_level);
+        source.overview = source
+            .decode_oriented(overview_level, &[(0, 0, w, h)])?
+            .pop()
+            .ok_or("Empty overview")?;
+        Ok(source)
+    }
+
+    pub fn level_size(&self, level: u32) -> (u32, u32) {
+        (
+            self.width.div_ceil(1 << level),
+            self.height.div_ceil(1 << level),
+        )
+    }
+
+    pub fn tile_grid(&self, level: u32) -> (u32, u32) {
+        let (w, h) = self.level_size(level);
+        (w.div_ceil(TILE_SIZE), h.div_ceil(TILE_SIZE))
+    }
+
+    /// The coarsest level that still has at least one level pixel per screen pixel.
+    pub fn level_for_scale(&self, scale: f64) -> u32 {
+        if scale >= 1.0 || scale <= 0.0 {
+            return 0;
+        }
+        ((1.0 / scale).log2().floor() as u32).min(self.overview_level)
+    }
+
+    /// Tiles sharing a batch key are decoded together.
+    ///
+    /// PNG and JPEG rows can only be read in order, so a whole row of tiles is
+    /// decoded at once.
+    pub fn batch_key(&self, key: TileKey) -> TileKey {
+        match self.container {
+            Container::Tiff => key,
+            Container::Png | Container::Jpeg => TileKey { x: 0, ..key },
+        }
+    }
+
+    /// Decodes the given tiles, which must all belong to the same level.
+    pub fn decode_tiles(&self, keys: &[TileKey]) -> Result<Vec<(TileKey, Tile)>, String> {
+        let Some(level) = keys.first().map(|k| k.level) else {
+            return Ok(Vec::new());
+        };
+        let (level_w, level_h) = self.level_size(level);
+
+        let rects: Vec<(u32, u32, u32, u32)> = keys
+            .iter()
+            .map(|k| {
+                let x0 = k.x * TILE_SIZE;
+                let y0 = k.y * TILE_SIZE;
+                (
+                    x0,
+                    y0,
+                    TILE_SIZE.min(level_w.saturating_sub(x0)),
+                    TILE_SIZE.min(level_h.saturating_sub(y0)),
+                )
+            })
+            .collect();
+
+        let tiles = self.decode_oriented(level, &rects)?;
+        Ok(keys.iter().copied().zip(tiles).collect())
+    }
+
+    /// Decodes rectangles given in displayed orientation.
+    fn decode_oriented(
+        &self,
+        level: u32,
+        rects: &[(u32, u32, u32, u32)],
+    ) -> Result<Vec<Tile>, String> {
+        let src_rects: Vec<_> = rects.iter().map(|&r| self.source_rect(level, r)).collect();
+        let tiles = self.decode_rects(level, &src_rects)?;
+        if self.orientation == 1 {
+            return Ok(tiles);
+        }
+
+        tiles
+            .into_iter()
+            .map(|tile| {
+                let buf = image::RgbaImage::from_raw(tile.width, tile.height, tile.pixels)
+                    .ok_or("Invalid tile")?;
+                let oriented = crate::loader::apply_orientation(
+                    image::DynamicImage::ImageRgba8(buf),
+                    self.orientation,
+                )
+                .into_rgba8();
+                Ok(Tile {
+                    width: oriented.width(),
+                    height: oriented.height(),
+                    pixels: oriented.into_raw(),
+                })
+            })
+            .collect()
+    }
+
+    /// Maps a rectangle of a displayed level back to the file's own orientation.
+    fn source_rect(&self, level: u32, (x, y, w, h): (u32, u32, u32, u32)) -> (u32, u32, u32, u32) {
+        let sw = self.src_width.div_ceil(1 << level);
+        let sh = self.src_height.div_ceil(1 << level);
+        match self.orientation {
+            2 => (sw - x - w, y, w, h),
+            3 => (sw - x - w, sh - y - h, w, h),
+            4 => (x, sh - y - h, w, h),
+            5 => (y, x, h, w),
+            6 => (y, sh - x - w, h, w),
+            7 => (sw - y - h, sh - x - w, h, w),
+            8 => (sw - y - h, x, h, w),
+            _ => (x, y, w, h),
+        }
+    }
+
+    fn decode_rects(
+        &self,
+        level: u32,
+        rects: &[(u32, u32, u32, u32)],
+    ) -> Result<Vec<Tile>, String> {
+        match self.container {
+            Container::Tiff => self.decode_tiff(level, rects),
+            Container::Png => self.decode_png(level, rects),
+            Container::Jpeg => self.decode_jpeg(level, rects),
+        }
+    }
+
+    fn decode_tiff(&self, level: u32, rects: &[(u32, u32, u32, u32)]) -> Result<Vec<Tile>, String> {
+        let source = self
+            .tiff_levels
+            .iter()
+            .rev()
+            .find(|l| l.shift <= level)
+            .ok_or("Missing TIFF image")?;
+        let shift = level - source.shift;
+        let mut accs: Vec<Accumulator> =
+            rects.iter().map(|&r| Accumulator::new(r, shift)).collect();
+
+        let mut decoder =
+            tiff::decoder::Decoder::new(Cursor::new(&self.data[..])).map_err(|e| e.to_string())?;
+        decoder
+            .seek_to_image(source.ifd)
+            .map_err(|e| e.to_string())?;
+
+        let layout = decoder
+            .colortype()
+            .ok()
+            .and_then(tiff_layout)
+            .ok_or("Unsupported TIFF color type for tiled decoding")?;
+        let planar = decoder
+            .find_tag_unsigned::<u16>(tiff::tags::Tag::PlanarConfiguration)
+            .map_err(|e| e.to_string())?;
+        if planar == Some(2) {
+            return Err("Planar TIFFs can't be decoded in tiles".to_string());
+        }
+
+        let (chunk_w, chunk_h) = decoder.chunk_dimensions();
+        let chunks_across = match decoder.get_chunk_type() {
+            tiff::decoder::ChunkType::Tile => source.width.div_ceil(chunk_w),
+            tiff::decoder::ChunkType::Strip => 1,
+        };
+        let ((col_start, col_end), (row_start, row_end)) =
+            union_bounds(&accs, source.width, source.height);
+        let channels = layout.channels();
+        let mut rgba = Vec::new();
+
+        for cy in row_start / chunk_h..row_end.div_ceil(chunk_h) {
+            for cx in col_start / chunk_w..col_end.div_ceil(chunk_w) {
+                let index = cy * chunks_across + cx;
+                let (data_w, data_h) = decoder.chunk_data_dimensions(index);
+                let samples = match decoder.read_chunk(index).map_err(|e| e.to_string())? {
+                    tiff::decoder::DecodingResult::U8(v) => v,
+                    tiff::decoder::DecodingResult::U16(v) => {
+                        v.iter().map(|s| (s >> 8) as u8).collect()
+                    }
+                    _ => return Err("Unsupported TIFF sample format".to_string()),
+                };
+
+                let stride = data_w as usize * channels;
+                for (row, line) in samples
+                    .chunks_exact(stride)
+                    .take(data_h as usize)
+                    .enumerate()
+                {
+                    let src_y = cy * chunk_h + row as u32;
+                    if src_y < row_start || src_y >= row_end {
+                        continue;
+                    }
+                    layout.to_rgba(line, &mut rgba);
+                    for acc in &mut accs {
+                        acc.add_row(src_y, cx * chunk_w, &rgba);
+                    }
+                }
+            }
+        }
+
+        Ok(accs.into_iter().map(Accumulator::finish).collect())
+    }
+
+    fn decode_png(&self, level: u32, rects: &[(u32, u32, u32, u32)]) -> Result<Vec<Tile>, String> {
+        let mut accs: Vec<Accumulator> =
+            rects.iter().map(|&r| Accumulator::new(r, level)).collect();
+
+        let ((col_start, col_end), (row_start, row_end)) =
+            union_bounds(&accs, self.src_width, self.src_height);
+        let cached = self.png_rows.lock().ok().and_then(|mut rows| rows.take());
+        let mut rows = match cached.filter(|rows| rows.next_row <= row_start) {
+            Some(rows) => rows,
+            None => {
+                let mut decoder = png::Decoder::new(Cursor::new(SharedData(self.data.clone())));
+                decoder.set_transformations(png::Transformations::normalize_to_color8());
+                let reader = decoder.read_info().map_err(|e| e.to_string())?;
+                if reader.info().interlaced {
+                    return Err("Interlaced PNGs can't be decoded in tiles".to_string());
+                }
+                PngRows {
+                    next_row: 0,
+                    reader,
+                }
+            }
+        };
+
+        let layout = match rows.reader.output_color_type().0 {
+            png::ColorType::Grayscale => Layout::Gray,
+            png::ColorType::GrayscaleAlpha => Layout::GrayAlpha,
+            png::ColorType::Rgb => Layout::Rgb,
+            png::ColorType::Rgba => Layout::Rgba,
+            png::ColorType::Indexed => return Err("Unexpected indexed PNG output".to_string()),
+        };
+        let channels = layout.channels();
+        let mut rgba = Vec::new();
+
+        // Rows above the region still have to be inflated, but are not converted
+        while rows.next_row < row_end {
+            let src_y = rows.next_row;
+            let Some(row) = rows.reader.next_row().map_err(|e| e.to_string())? else {
+                break;
+            };
+            if src_y >= row_start {
+                let line = &row.data()[col_start as usize * channels..col_end as usize * channels];
+                layout.to_rgba(line, &mut rgba);
+                for acc in &mut accs {
+                    acc.add_row(src_y, col_start, &rgba);
+                }
+            }
+            rows.next_row += 1;
+        }
+        if rows.next_row < self.src_height {
+            if let Ok(mut cached) = self.png_rows.lock() {
+                *cached = Some(rows);
+            }
+        }
+
+        Ok(accs.into_iter().map(Accumulator::finish).collect())
+    }
+
+    fn decode_jpeg(&self, level: u32, rects: &[(u32, u32, u32, u32)]) -> Result<Vec<Tile>, String> {
+        // The IDCT scales down by up to 8, further reduction is box filtered
+        let dct_shift = level.min(3);
+        let mut accs: Vec<Accumulator> = rects
+            .iter()
+            .map(|&r| Accumulator::new(r, level - dct_shift))
+            .collect();
+
+        let ((col_start, col_end), (row_start, row_end)) = union_bounds(
+            &accs,
+            self.src_width.div_ceil(1 << dct_shift),
+            self.src_height.div_ceil(1 << dct_shift),
+        );
+        let scale = match &self.jpeg_bands {
+            Some(bands) => Arc::new(self.jpeg_band(bands, dct_shift, (row_start, row_end))?),
+            None => self.jpeg_scale(dct_shift)?,
+        };
+        let channels = scale.layout.channels();
+        let stride = scale.width as usize * channels;
+        let mut rgba = Vec::new();
+
+        for src_y in row_start.max(scale.top)..row_end.min(scale.top + scale.height) {
+            let offset = (src_y - scale.top) as usize * stride;
+            let line = &scale.samples[offset..offset + stride];
+            scale.layout.to_rgba(
+                &line[col_start as usize * channels..col_end as usize * channels],
+                &mut rgba,
+            );
+            for acc in &mut accs {
+                acc.add_row(src_y, col_start, &rgba);
+            }
+        }
+
+        Ok(accs.into_iter().map(Accumulator::finish).collect())
+    }
+
+    /// Decodes the bands holding `rows` of the JPEG at `1 / 2^dct_shift`.
+    ///
+    /// The bands are put behind the file's own headers with the frame height
+    /// changed to theirs, and their restart markers renumbered from zero.
+    fn jpeg_band(
+        &self,
+        bands: &JpegBands,
+        dct_shift: u32,
+        (row_start, row_end): (u32, u32),
+    ) -> Result<JpegScale, String> {
+        let first = ((row_start << dct_shift) / bands.band_height).min(bands.bands.len() as u32 - 1)
+            as usize;
+        let last = ((row_end << dct_shift).min(self.src_height))
+            .div_ceil(bands.band_height)
+            .clamp(first as u32 + 1, bands.bands.len() as u32) as usize;
+        let top = first as u32 * bands.band_height;
+        let height = (last as u32 * bands.band_height).min(self.src_height) - top;
+
+        let (data_start, data_end) = (bands.bands[first].0, bands.bands[last - 1].1);
+        let mut jpeg = Vec::with_capacity(bands.header_len + data_end - data_start + 4);
+        jpeg.extend_from_slice(&self.data[..bands.header_len]);
+        jpeg[bands.height_at..bands.height_at + 2].copy_from_slice(&(height as u16).to_be_bytes());
+        jpeg.extend_from_slice(&self.data[data_start..data_end]);
+
+        // Byte stuffing keeps 0xFF followed by a restart code out of the coded data
+        let base = (first as u32 * bands.band_intervals % 8) as u8;
+        for i in bands.header_len..jpeg.len() - 1 {
+            if jpeg[i] == 0xFF && (0xD0..=0xD7).contains(&jpeg[i + 1]) {
+                jpeg[i + 1] = 0xD0 + (jpeg[i + 1] - 0xD0 + 8 - base) % 8;
+            }
+        }
+        jpeg.extend_from_slice(&[0xFF, 0xD9]);
+
+        let mut scale = decode_jpeg_scaled(&jpeg, dct_shift, self.src_width, height)?;
+        scale.top = top >> dct_shift;
+        Ok(scale)
+    }
+
+    /// The JPEG decoded at `1 / 2^dct_shift`, reusing the last decode when it matches.
+    ///
+    /// The lock is held while decoding so concurrent batches wait for one
+    /// decode instead of each running their own.
+    fn jpeg_scale(&self, dct_shift: u32) -> Result<Arc<JpegScale>, String> {
+        let mut cached = self.jpeg_scale.lock().map_err(|e| e.to_string())?;
+        if let Some(scale) = cached.as_ref().filter(|s| s.dct_shift == dct_shift) {
+            return Ok(scale.clone());
+        }
+        // Free the previous scale before allocating the next one
+        *cached = None;
+
+        let scale = Arc::new(decode_jpeg_scaled(
+            &self.data,
+            dct_shift,
+            self.src_width,
+            self.src_height,
+        )?);
+        *cached = Some(scale.clone());
+        Ok(scale)
+    }
+
+    /// Bytes held by the JPEG scale kept between batches.
+    pub fn cached_bytes(&self) -> usize {
+        self.jpeg_scale
+            .lock()
+            .ok()
+            .and_then(|s| s.as_ref().map(|s| s.samples.len()))
+            .unwrap_or(0)
+    }
+}
+
+fn decode_jpeg_scaled(
+    data: &[u8],
+    dct_shift: u32,
+    width: u32,
+    height: u32,
+) -> Result<JpegScale, String> {
+    let mut decoder = jpeg_decoder::Decoder::new(Cursor::new(data));
+    let (width, height) = decoder
+        .scale(
+            width.div_ceil(1 << dct_shift) as u16,
+            height.div_ceil(1 << dct_shift) as u16,
+        )
+        .map_err(|e| e.to_string())?;
+    let (width, height) = (width as u32, height as u32);
+    crate::loader::check_memory_before_decode(width, height, 1)?;
+
+    let samples = decoder.decode().map_err(|e| e.to_string())?;
+    let info = decoder.info().ok_or("Missing JPEG header")?;
+    let layout = match info.pixel_format {
+        jpeg_decoder::PixelFormat::L8 => Layout::Gray,
+        jpeg_decoder::PixelFormat::RGB24 => Layout::Rgb,
+        jpeg_decoder::PixelFormat::CMYK32 => Layout::Cmyk,
+        jpeg_decoder::PixelFormat::L16 => {
+            return Err("16-bit JPEGs can't be decoded in tiles".to_string())
+        }
+    };
+
+    Ok(JpegScale {
+        dct_shift,
+        top: 0,
+        width,
+        height,
+        layout,
+        samples,
+    })
+}
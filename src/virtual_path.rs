@@ -0,0 +1,61 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Images without a file of their own, the one piped to stdin and archive
+/// entries, are read into memory.
+pub fn is_virtual(path: &Path) -> bool {
+    crate::stdin::data(path).is_some() || crate::archive::split(path).is_some()
+}
+
+/// The bytes of a virtual image, `None` for ordinary files.
+pub fn read(path: &Path) -> Option<Result<Vec<u8>, String>> {
+    if let Some(data) = crate::stdin::data(path) {
+        return Some(Ok(data.to_vec()));
+    }
+    crate::archive::split(path).map(|(archive, entry)| crate::archive::read(&archive, &entry))
+}
+
+/// Where a virtual image would be if it were a file: next to its archive, or
+/// in the working directory when piped. Used to name files derived from it.
+pub fn beside(path: &Path) -> PathBuf {
+    if crate::stdin::data(path).is_some() {
+        return std::env::current_dir().unwrap_or_default().join(path);
+    }
+    match crate::archive::split(path) {
+        Some((archive, entry)) => {
+            let name = Path::new(&entry).file_name().unwrap_or_default();
+            archive.with_file_name(name)
+        }
+        None => path.to_path_buf(),
+    }
+}
+
+/// A virtual image written to a temporary directory for external programs,
+/// removed when dropped.
+pub struct Extracted {
+    dir: PathBuf,
+    pub path: PathBuf,
+}
+
+impl Extracted {
+    pub fn new(path: &Path) -> Result<Self, String> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let data = read(path).ok_or("Not a virtual image")??;
+
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("rsiv-{}-entry-{}", std::process::id(), n));
+        std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let extracted = Self {
+            path: dir.join(beside(path).file_name().unwrap_or_default()),
+            dir,
+        };
+        std::fs::write(&extracted.path, data).map_err(|e| e.to_string())?;
+        Ok(extracted)
+    }
+}
+
+impl Drop for Extracted {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
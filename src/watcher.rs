@@ -1,69 +1,105 @@
 use crate::app::AppEvent;
-use crate::image_item::ImageItem;
-use crate::loader::{identify_format, probe_image};
-use notify_debouncer_mini::{new_debouncer, notify::RecursiveMode};
-use std::path::Path;
+use crate::loader::{identify_format, probe_item};
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, Debouncer};
+use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 use std::thread;
 use std::time::Duration;
 use winit::event_loop::EventLoopProxy;
 
-pub fn spawn_watcher(paths: Vec<String>, recursive: bool, proxy: EventLoopProxy<AppEvent>) {
-    thread::spawn(move || {
+/// Watches the listed paths and tells the app about images added, changed
+/// or deleted there.
+pub struct Watcher {
+    debouncer: Debouncer<RecommendedWatcher>,
+    watched: Vec<PathBuf>,
+}
+
+impl Watcher {
+    pub fn spawn(proxy: EventLoopProxy<AppEvent>) -> Option<Self> {
         let (tx, rx) = mpsc::channel();
 
         // Waits for the file to finish writing before telling the app.
-        let mut debouncer = new_debouncer(Duration::from_millis(100), tx).unwrap();
-
-        for path_str in paths {
-            let path = Path::new(&path_str);
-            if path.exists() {
-                let mode = if recursive {
-                    RecursiveMode::Recursive
-                } else {
-                    RecursiveMode::NonRecursive
-                };
-
-                if let Err(e) = debouncer.watcher().watch(path, mode) {
-                    crate::rsiv_warn!("Watcher error for {:?}: {}", path, e);
-                }
+        let debouncer = match new_debouncer(Duration::from_millis(100), tx) {
+            Ok(debouncer) => debouncer,
+            Err(e) => {
+                crate::rsiv_warn!("Failed to start the file watcher: {}", e);
+                return None;
             }
-        }
+        };
 
-        // Listen for events
-        for result in rx {
-            match result {
-                Ok(events) => {
-                    for event in events {
-                        use notify_debouncer_mini::DebouncedEventKind;
+        // Ends once the debouncer is dropped
+        thread::spawn(move || {
+            for result in rx {
+                match result {
+                    Ok(events) => {
+                        for event in events {
+                            use notify_debouncer_mini::DebouncedEventKind;
 
-                        match event.kind {
-                            DebouncedEventKind::Any => {
-                                // Fallback/Generic change
-                                handle_change(&event.path, &proxy);
+                            match event.kind {
+                                DebouncedEventKind::Any => {
+                                    // Fallback/Generic change
+                                    handle_change(&event.path, &proxy);
+                                }
+                                DebouncedEventKind::AnyContinuous => {} // Ignore continuous updates
+                                _ => {}
                             }
-                            DebouncedEventKind::AnyContinuous => {} // Ignore continuous updates
-                            _ => {}
                         }
                     }
+                    Err(e) => crate::rsiv_warn!("Watch error: {:?}", e),
                 }
-                Err(e) => crate::rsiv_warn!("Watch error: {:?}", e),
             }
+        });
+
+        Some(Self {
+            debouncer,
+            watched: Vec::new(),
+        })
+    }
+
+    /// Stops watching the previous paths and watches these instead.
+    pub fn watch(&mut self, paths: &[PathBuf], recursive: bool) {
+        for path in self.watched.drain(..) {
+            let _ = self.debouncer.watcher().unwatch(&path);
         }
-    });
+
+        let mode = if recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        for path in paths {
+            if !path.exists() || crate::stdin::data(path).is_some() {
+                continue;
+            }
+            match self.debouncer.watcher().watch(path, mode) {
+                Ok(()) => self.watched.push(path.clone()),
+                Err(e) => crate::rsiv_warn!("Watcher error for {:?}: {}", path, e),
+            }
+        }
+    }
 }
 
 fn handle_change(path: &Path, proxy: &EventLoopProxy<AppEvent>) {
-    if path.exists() {
+    if crate::save::is_backup(path) {
+        return;
+    }
+    if crate::archive::is_archive(path) {
+        // Rewritten archives replace their entries, deleted ones drop them
+        let items = crate::archive::entries(path)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|entry| {
+                identify_format(&entry)
+                    .and_then(|f| probe_item(&entry, f))
+                    .ok()
+            })
+            .collect();
+        let _ = proxy.send_event(AppEvent::ArchiveChanged(path.to_path_buf(), items));
+    } else if path.exists() {
         match identify_format(path) {
-            Ok(format) => match probe_image(path, format) {
-                Ok((width, height)) => {
-                    let item = ImageItem {
-                        path: path.to_path_buf(),
-                        width,
-                        height,
-                        format,
-                    };
+            Ok(format) => match probe_item(path, format) {
+                Ok(item) => {
                     let _ = proxy.send_event(AppEvent::FileChanged(item));
                 }
                 Err(_) => {
@@ -80,3 +116,33 @@ fn handle_change(path: &Path, proxy: &EventLoopProxy<AppEvent>) {
         let _ = proxy.send_event(AppEvent::FileDeleted(path.to_path_buf()));
     }
 }
+
+/// Sends `ConfigChanged` when the config file is written. Its directory is
+/// watched, as editors often replace the file instead of writing to it.
+pub fn spawn_config_watcher(proxy: EventLoopProxy<AppEvent>) {
+    let Some(config_path) = crate::config::AppConfig::find_config_path() else {
+        return;
+    };
+    let Some(dir) = config_path.parent().map(|d| d.to_path_buf()) else {
+        return;
+    };
+    if !dir.is_dir() {
+        return;
+    }
+
+    thread::spawn(move || {
+        let (tx, rx) = mpsc::channel();
+
+        let mut debouncer = new_debouncer(Duration::from_millis(100), tx).unwrap();
+        if let Err(e) = debouncer.watcher().watch(&dir, RecursiveMode::NonRecursive) {
+            crate::rsiv_warn!("Watcher error for {:?}: {}", dir, e);
+            return;
+        }
+
+        for events in rx.into_iter().flatten() {
+            if events.iter().any(|event| event.path == config_path) {
+                let _ = proxy.send_event(AppEvent::ConfigChanged);
+            }
+        }
+    });
+}